@@ -0,0 +1,300 @@
+// Compatibility manifest exchanged before launch. Different FBNeo builds, ROM
+// dumps or Lua script versions desync mid-match, so both proxies hash what they
+// are about to run and compare before agreeing on a start instant.
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompatPolicy {
+    Off,
+    // report mismatches and launch anyway
+    #[default]
+    Warn,
+    // refuse to launch on any mismatch, or when the peer never sends a manifest
+    Block,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompatManifest {
+    pub app_version: String,
+    pub emulator_hash: Option<String>,
+    pub rom: Option<String>,
+    pub rom_hash: Option<String>,
+    pub lua_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompatDiff {
+    pub field: &'static str,
+    pub local: Option<String>,
+    pub peer: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompatReport {
+    pub session_id: String,
+    pub policy: CompatPolicy,
+    pub compatible: bool,
+    pub blocked: bool,
+    pub diffs: Vec<CompatDiff>,
+    pub local: CompatManifest,
+    pub peer: Option<CompatManifest>,
+}
+
+fn hash_file(path: &Path) -> Option<String> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).ok()?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+// The main script can pull in its neighbours, so every .lua file next to it is
+// hashed in name order, together with its name.
+fn hash_lua_dir(script: &Path) -> Option<String> {
+    let dir = script.parent()?;
+    let mut scripts: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("lua"))
+        })
+        .collect();
+    scripts.sort();
+    let mut hasher = Sha256::new();
+    for path in scripts {
+        let bytes = fs::read(&path).ok()?;
+        hasher.update(path.file_name()?.to_string_lossy().as_bytes());
+        hasher.update(&bytes);
+    }
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+// ROM name from `--rom X` (fs-fbneo) or the quark:direct string (fcadefbneo)
+fn rom_from_args(args: &[String]) -> Option<String> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg.eq_ignore_ascii_case("--rom") {
+            return iter.next().cloned();
+        }
+        if arg.to_ascii_lowercase().starts_with("quark:direct,") {
+            return arg.split(',').nth(1).map(str::to_string);
+        }
+    }
+    None
+}
+
+fn find_rom_zip(emulator: &Path, rom: &str) -> Option<PathBuf> {
+    let dir = emulator.parent()?;
+    let file = format!("{rom}.zip");
+    [dir.join("ROMs"), dir.join("roms"), dir.to_path_buf()]
+        .into_iter()
+        .map(|candidate| candidate.join(&file))
+        .find(|path| path.is_file())
+}
+
+fn lua_script(args: &[String]) -> Option<PathBuf> {
+    let idx = args.iter().position(|a| a.eq_ignore_ascii_case("--lua"))?;
+    args.get(idx + 1).map(PathBuf::from)
+}
+
+// Reads and hashes files, run it off the async runtime. `args` must already
+// have their --lua path resolved.
+pub fn build_manifest(
+    emulator_path: &str,
+    args: &[String],
+    game_name: Option<&str>,
+) -> CompatManifest {
+    let emulator = Path::new(emulator_path);
+    let rom = rom_from_args(args).or_else(|| game_name.map(str::to_string));
+    CompatManifest {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        emulator_hash: hash_file(emulator),
+        rom_hash: rom
+            .as_deref()
+            .and_then(|rom| find_rom_zip(emulator, rom))
+            .and_then(|zip| hash_file(&zip)),
+        rom,
+        lua_hash: lua_script(args).and_then(|script| hash_lua_dir(&script)),
+    }
+}
+
+pub fn diff(local: &CompatManifest, peer: &CompatManifest) -> Vec<CompatDiff> {
+    let fields: [(&'static str, Option<&String>, Option<&String>); 5] = [
+        (
+            "appVersion",
+            Some(&local.app_version),
+            Some(&peer.app_version),
+        ),
+        (
+            "emulator",
+            local.emulator_hash.as_ref(),
+            peer.emulator_hash.as_ref(),
+        ),
+        ("rom", local.rom.as_ref(), peer.rom.as_ref()),
+        ("romZip", local.rom_hash.as_ref(), peer.rom_hash.as_ref()),
+        ("lua", local.lua_hash.as_ref(), peer.lua_hash.as_ref()),
+    ];
+    fields
+        .into_iter()
+        .filter(|(_, ours, theirs)| ours != theirs)
+        .map(|(field, ours, theirs)| CompatDiff {
+            field,
+            local: ours.cloned(),
+            peer: theirs.cloned(),
+        })
+        .collect()
+}
+
+// Per-session manifests, `reported` keeps the diff from being emitted twice
+#[derive(Default)]
+pub struct CompatState {
+    pub local: Option<CompatManifest>,
+    pub peer: Option<CompatManifest>,
+    pub reported: bool,
+}
+
+// Whether the start handshake may go ahead
+pub enum CompatGate {
+    Waiting,
+    Clear,
+    Blocked(String),
+}
+
+impl CompatState {
+    // `timed_out` is set once we gave up waiting on the manifests, an older
+    // client never sends one so Warn lets the launch through.
+    pub fn gate(&self, policy: CompatPolicy, timed_out: bool) -> CompatGate {
+        if policy == CompatPolicy::Off {
+            return CompatGate::Clear;
+        }
+        let (Some(local), Some(peer)) = (&self.local, &self.peer) else {
+            let missing = if self.local.is_none() {
+                "our compatibility manifest was never built"
+            } else {
+                "peer never sent a compatibility manifest"
+            };
+            return match (timed_out, policy) {
+                (false, _) => CompatGate::Waiting,
+                (true, CompatPolicy::Block) => CompatGate::Blocked(missing.to_string()),
+                (true, _) => CompatGate::Clear,
+            };
+        };
+        let diffs = diff(local, peer);
+        if diffs.is_empty() || policy == CompatPolicy::Warn {
+            return CompatGate::Clear;
+        }
+        let fields: Vec<&str> = diffs.iter().map(|d| d.field).collect();
+        CompatGate::Blocked(format!("mismatched {}", fields.join(", ")))
+    }
+
+    pub fn report(&self, session_id: &str, policy: CompatPolicy) -> Option<CompatReport> {
+        let local = self.local.clone()?;
+        let diffs = self
+            .peer
+            .as_ref()
+            .map(|peer| diff(&local, peer))
+            .unwrap_or_default();
+        let compatible = self.peer.is_some() && diffs.is_empty();
+        Some(CompatReport {
+            session_id: session_id.to_string(),
+            policy,
+            compatible,
+            blocked: !compatible && policy == CompatPolicy::Block,
+            diffs,
+            local,
+            peer: self.peer.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> CompatManifest {
+        CompatManifest {
+            app_version: "1.0.0".to_string(),
+            emulator_hash: Some("emu".to_string()),
+            rom: Some("sfiii3nr1".to_string()),
+            rom_hash: Some("rom".to_string()),
+            lua_hash: Some("lua".to_string()),
+        }
+    }
+
+    fn blocked(gate: CompatGate) -> Option<String> {
+        match gate {
+            CompatGate::Blocked(reason) => Some(reason),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn diff_lists_every_mismatched_field() {
+        assert!(diff(&manifest(), &manifest()).is_empty());
+        let peer = CompatManifest {
+            rom_hash: Some("other dump".to_string()),
+            lua_hash: None,
+            ..manifest()
+        };
+        let diffs = diff(&manifest(), &peer);
+        let fields: Vec<&str> = diffs.iter().map(|d| d.field).collect();
+        assert_eq!(fields, ["romZip", "lua"]);
+        assert_eq!(diffs[1].local.as_deref(), Some("lua"));
+        assert_eq!(diffs[1].peer, None);
+    }
+
+    #[test]
+    fn gate_waits_then_applies_the_policy() {
+        let mut state = CompatState {
+            local: Some(manifest()),
+            ..Default::default()
+        };
+        assert!(matches!(state.gate(CompatPolicy::Off, false), CompatGate::Clear));
+        assert!(matches!(state.gate(CompatPolicy::Block, false), CompatGate::Waiting));
+        // an older peer never sends one
+        assert!(matches!(state.gate(CompatPolicy::Warn, true), CompatGate::Clear));
+        assert_eq!(
+            blocked(state.gate(CompatPolicy::Block, true)).as_deref(),
+            Some("peer never sent a compatibility manifest")
+        );
+
+        state.peer = Some(manifest());
+        assert!(matches!(state.gate(CompatPolicy::Block, false), CompatGate::Clear));
+        state.peer = Some(CompatManifest {
+            app_version: "0.9.0".to_string(),
+            emulator_hash: None,
+            ..manifest()
+        });
+        assert!(matches!(state.gate(CompatPolicy::Warn, false), CompatGate::Clear));
+        assert_eq!(
+            blocked(state.gate(CompatPolicy::Block, false)).as_deref(),
+            Some("mismatched appVersion, emulator")
+        );
+        let report = state.report("s", CompatPolicy::Block).unwrap();
+        assert!(!report.compatible && report.blocked && report.diffs.len() == 2);
+
+        // still hashing our own files is not the peer's fault
+        let hashing = CompatState::default();
+        assert_eq!(
+            blocked(hashing.gate(CompatPolicy::Block, true)).as_deref(),
+            Some("our compatibility manifest was never built")
+        );
+        assert!(hashing.report("s", CompatPolicy::Block).is_none());
+    }
+}
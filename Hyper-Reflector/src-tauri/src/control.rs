@@ -0,0 +1,254 @@
+// Control messages exchanged between the two proxies over the punched link.
+// They share the socket with raw GGPO traffic, so every control datagram starts
+// with CONTROL_PREFIX and anything else is treated as emulator payload.
+use crate::compat::CompatManifest;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const CONTROL_PREFIX: &[u8] = b"\x00HRC";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ControlMessage {
+    // clock sync: `sent_at` is echoed back so the sender can measure the round trip
+    ClockPing { sent_at: u64 },
+    ClockPong { sent_at: u64, received_at: u64 },
+    // start handshake: `at` is expressed in the leader's clock.
+    // `features` lets each side know which optional framings the other understands.
+    Ready {
+        #[serde(default)]
+        features: Vec<String>,
+    },
+    Start { at: u64 },
+    StartAck { at: u64 },
+    // rematch over the same link, see rematch.rs
+    RematchOptIn { enabled: bool },
+    Rematch {
+        id: u64,
+        swap_sides: bool,
+        delay: Option<u16>,
+    },
+    RematchAccept { id: u64 },
+    RematchDecline { id: u64, reason: String },
+    // compatibility check before launch, see compat.rs
+    Manifest { manifest: CompatManifest },
+    Incompatible { reason: String },
+    // running best-of-N score from the sender's side, see set_tracker.rs
+    SetScore {
+        // which set this is, a newer id means the sender started over
+        #[serde(default)]
+        set_id: u64,
+        first_to: u32,
+        games: u32,
+        local_wins: u32,
+        peer_wins: u32,
+    },
+    // the sender is leaving for good (app closing), no rematch is coming
+    Bye { reason: String },
+    // the sender moved to the relay tunnel, the receiver follows so both
+    // sides end up on the same transport
+    UseTunnel,
+}
+
+pub fn encode(msg: &ControlMessage) -> Vec<u8> {
+    let mut out = CONTROL_PREFIX.to_vec();
+    // serializing a plain enum cannot fail
+    out.extend(serde_json::to_vec(msg).unwrap_or_default());
+    out
+}
+
+// Returns None for anything that is not a control datagram (i.e. emulator traffic).
+pub fn decode(bytes: &[u8]) -> Option<ControlMessage> {
+    let body = bytes.strip_prefix(CONTROL_PREFIX)?;
+    serde_json::from_slice(body).ok()
+}
+
+pub fn is_control(bytes: &[u8]) -> bool {
+    bytes.starts_with(CONTROL_PREFIX)
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClockSample {
+    pub offset_ms: i64,
+    pub rtt_ms: u64,
+}
+
+// Estimates the peer's clock offset NTP-style. The sample with the smallest
+// round trip wins since its midpoint assumption is the least wrong.
+#[derive(Debug, Default)]
+pub struct ClockSync {
+    best: Option<ClockSample>,
+    samples: usize,
+}
+
+impl ClockSync {
+    // `sent_at` and `received_at` are local times, `remote_at` is the peer's time.
+    pub fn record(&mut self, sent_at: u64, remote_at: u64, received_at: u64) {
+        if received_at < sent_at {
+            return;
+        }
+        let rtt_ms = received_at - sent_at;
+        let midpoint = (sent_at + received_at) / 2;
+        let sample = ClockSample {
+            offset_ms: remote_at as i64 - midpoint as i64,
+            rtt_ms,
+        };
+        self.samples += 1;
+        match self.best {
+            Some(best) if best.rtt_ms <= rtt_ms => {}
+            _ => self.best = Some(sample),
+        }
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    pub fn best(&self) -> Option<ClockSample> {
+        self.best
+    }
+
+    // Converts a timestamp from the peer's clock into ours.
+    pub fn to_local(&self, remote_ms: u64) -> u64 {
+        let offset = self.best.map(|s| s.offset_ms).unwrap_or(0);
+        (remote_ms as i64 - offset).max(0) as u64
+    }
+}
+
+// Probes older than this without a pong are counted as lost
+const PROBE_LOSS_AFTER_MS: u64 = 1000;
+const PROBE_WINDOW: usize = 40;
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkStats {
+    pub rtt_ms: Option<u64>,
+    pub rtt_avg_ms: Option<f64>,
+    pub loss_ratio: f64,
+    pub probes_sent: u64,
+}
+
+// Tracks clock pings as link probes so we get a loss ratio for the peer link
+// without touching the emulator traffic itself.
+#[derive(Debug, Default)]
+pub struct LinkProbe {
+    // (sent_at, answered)
+    window: VecDeque<(u64, bool)>,
+    stats: LinkStats,
+}
+
+impl LinkProbe {
+    pub fn on_sent(&mut self, sent_at: u64) {
+        if self.window.len() >= PROBE_WINDOW {
+            self.window.pop_front();
+        }
+        self.window.push_back((sent_at, false));
+        self.stats.probes_sent += 1;
+    }
+
+    pub fn on_pong(&mut self, sent_at: u64, now: u64) {
+        if let Some(probe) = self.window.iter_mut().find(|(t, _)| *t == sent_at) {
+            probe.1 = true;
+        }
+        let rtt = now.saturating_sub(sent_at);
+        self.stats.rtt_ms = Some(rtt);
+        self.stats.rtt_avg_ms = Some(match self.stats.rtt_avg_ms {
+            Some(avg) => avg + 0.2 * (rtt as f64 - avg),
+            None => rtt as f64,
+        });
+    }
+
+    pub fn loss_ratio(&self, now: u64) -> f64 {
+        let settled = self
+            .window
+            .iter()
+            .filter(|(t, _)| now.saturating_sub(*t) >= PROBE_LOSS_AFTER_MS);
+        let (total, lost) = settled.fold((0u32, 0u32), |(total, lost), (_, answered)| {
+            (total + 1, lost + u32::from(!answered))
+        });
+        if total == 0 {
+            0.0
+        } else {
+            lost as f64 / total as f64
+        }
+    }
+
+    pub fn stats(&self, now: u64) -> LinkStats {
+        LinkStats {
+            loss_ratio: self.loss_ratio(now),
+            ..self.stats
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_offset_uses_the_round_trip_midpoint() {
+        let mut clock = ClockSync::default();
+        assert_eq!(clock.to_local(5_000), 5_000);
+        // peer is 500ms ahead: sent at 1000, answered at its 1550, back at 1100
+        clock.record(1_000, 1_550, 1_100);
+        let best = clock.best().unwrap();
+        assert_eq!((best.offset_ms, best.rtt_ms), (500, 100));
+        assert_eq!(clock.to_local(10_500), 10_000);
+    }
+
+    #[test]
+    fn fastest_round_trip_wins() {
+        let mut clock = ClockSync::default();
+        clock.record(1_000, 1_300, 1_400);
+        // tighter sample, peer 250ms behind
+        clock.record(2_000, 1_770, 2_040);
+        // slower again, ignored for the offset
+        clock.record(3_000, 9_999, 3_300);
+        // a reply from before the ping was sent is nonsense
+        clock.record(4_000, 4_000, 3_999);
+        assert_eq!(clock.samples(), 3);
+        let best = clock.best().unwrap();
+        assert_eq!((best.offset_ms, best.rtt_ms), (-250, 40));
+        assert_eq!(clock.to_local(1_000), 1_250);
+        // never below zero
+        let mut ahead = ClockSync::default();
+        ahead.record(0, 10_000, 0);
+        assert_eq!(ahead.to_local(5_000), 0);
+    }
+
+    #[test]
+    fn link_probe_counts_only_settled_probes_as_lost() {
+        let mut probe = LinkProbe::default();
+        for at in [0, 100, 200, 300] {
+            probe.on_sent(at);
+        }
+        probe.on_pong(0, 40);
+        probe.on_pong(200, 260);
+        // nothing has been waiting long enough yet
+        assert_eq!(probe.loss_ratio(500), 0.0);
+        // 100 and 300 never came back
+        assert_eq!(probe.loss_ratio(300 + PROBE_LOSS_AFTER_MS), 0.5);
+        let stats = probe.stats(300 + PROBE_LOSS_AFTER_MS);
+        assert_eq!((stats.probes_sent, stats.rtt_ms), (4, Some(60)));
+        assert_eq!(stats.rtt_avg_ms, Some(44.0));
+
+        // the window only remembers the last PROBE_WINDOW probes
+        for n in 0..PROBE_WINDOW as u64 {
+            let at = 10_000 + n;
+            probe.on_sent(at);
+            probe.on_pong(at, at + 10);
+        }
+        assert_eq!(probe.loss_ratio(20_000), 0.0);
+    }
+}
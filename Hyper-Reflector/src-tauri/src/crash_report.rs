@@ -0,0 +1,107 @@
+// Crash reports for emulators that died on their own: enough context to tell
+// a bad ROM or Lua script apart from a netcode problem without asking the
+// player to dig through logs.
+use crate::compat::{self, CompatManifest};
+use crate::emulator_output::OutputLine;
+use crate::emulator_supervisor::{EmulatorExit, EmulatorInfo};
+use crate::proxy::ProxyManager;
+use crate::proxy_stats::ProxyStats;
+use crate::resource_usage::ResourceSample;
+use serde::Serialize;
+use std::{fs, path::PathBuf};
+use tauri::{AppHandle, Emitter, EventTarget, Manager, Runtime};
+
+pub const CRASH_DIR: &str = "crash-reports";
+// Lines of emulator output included in a report
+pub const REPORT_LINES: usize = 200;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashReport {
+    pub emulator: EmulatorInfo,
+    pub exit: EmulatorExit,
+    pub uptime_ms: u64,
+    // hashes of the emulator, ROM zip and Lua scripts it was launched with
+    pub manifest: CompatManifest,
+    pub output: Vec<OutputLine>,
+    // CPU, memory and threads over the last minute or so
+    pub usage: Vec<ResourceSample>,
+    // only for emulators launched by a proxy session that is still around
+    pub proxy_stats: Option<ProxyStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CrashEvent<'a> {
+    path: Option<String>,
+    report: &'a CrashReport,
+}
+
+fn save<R: Runtime>(app: &AppHandle<R>, report: &CrashReport) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join(CRASH_DIR);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let path = dir.join(format!(
+        "crash-{}-{}.json",
+        report.exit.exited_at, report.emulator.id
+    ));
+    let json = serde_json::to_vec_pretty(report).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+// Stats of the session that launched the emulator. Taken as soon as the exit
+// is seen, the session tears down once it hears of it.
+pub async fn session_stats<R: Runtime>(app: &AppHandle<R>, session_id: &str) -> Option<ProxyStats> {
+    let rt = app.state::<ProxyManager<R>>().get(session_id).await?;
+    Some(rt.stats().await)
+}
+
+// Builds the report, writes it next to the other app data and tells the UI.
+pub async fn report_crash<R: Runtime>(
+    app: &AppHandle<R>,
+    emulator: EmulatorInfo,
+    exit: EmulatorExit,
+    proxy_stats: Option<ProxyStats>,
+    output: Vec<OutputLine>,
+    usage: Vec<ResourceSample>,
+) {
+    let (emulator_path, args) = (emulator.emulator.clone(), emulator.args.clone());
+    let manifest = tokio::task::spawn_blocking(move || {
+        compat::build_manifest(&emulator_path, &args, None)
+    })
+    .await
+    .unwrap_or_default();
+
+    let report = CrashReport {
+        uptime_ms: exit.exited_at.saturating_sub(emulator.started_at),
+        emulator,
+        exit,
+        manifest,
+        output,
+        usage,
+        proxy_stats,
+    };
+    let path = match save(app, &report) {
+        Ok(path) => Some(path.to_string_lossy().into_owned()),
+        Err(e) => {
+            let _ = app.emit_to(
+                EventTarget::any(),
+                "proxy-log",
+                format!("failed to save crash report: {e}"),
+            );
+            None
+        }
+    };
+    let _ = app.emit_to(
+        EventTarget::any(),
+        "emulator:crashed",
+        CrashEvent {
+            path,
+            report: &report,
+        },
+    );
+}
@@ -0,0 +1,299 @@
+// Captures emulator stdout/stderr line by line. Every process gets a bounded
+// ring buffer (what bug reports and crash reports read from), a log file in
+// the app data dir, and batched `emulator:output` events for the UI.
+use crate::emulator_supervisor::EmulatorEvents;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tauri::async_runtime::JoinHandle;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    sync::mpsc,
+    time::interval,
+};
+
+// Lines kept in memory per process
+pub const BUFFER_LINES: usize = 2000;
+// The UI gets at most one event per process per tick
+const EMIT_INTERVAL: Duration = Duration::from_millis(250);
+// A chatty emulator shouldn't flood the webview, older lines in a tick are
+// dropped from the event (they still reach the buffer and the log)
+const MAX_LINES_PER_EVENT: usize = 200;
+pub const LOG_DIR: &str = "emulator-logs";
+// Logs of older launches are deleted beyond this many
+const LOGS_KEPT: usize = 50;
+// Lines waiting for the collector. The pipe readers never wait on it: a
+// blocked reader would fill the pipe and stall the emulator mid-match, so
+// lines that don't fit are dropped and counted instead.
+const CHANNEL_LINES: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputLine {
+    pub stream: OutputStream,
+    pub line: String,
+    pub at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OutputEvent<'a> {
+    id: u64,
+    session_id: Option<&'a str>,
+    lines: &'a [OutputLine],
+    dropped: usize,
+    // lines lost since the last event because the collector fell behind,
+    // these never reached the buffer or the log either
+    lost: usize,
+}
+
+#[derive(Debug)]
+pub struct OutputBuffer {
+    lines: VecDeque<OutputLine>,
+    capacity: usize,
+}
+
+impl OutputBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(capacity.min(256)),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, line: OutputLine) {
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    // Last `n` lines, oldest first
+    pub fn tail(&self, n: usize) -> Vec<OutputLine> {
+        let skip = self.lines.len().saturating_sub(n);
+        self.lines.iter().skip(skip).cloned().collect()
+    }
+}
+
+pub type SharedOutput = Arc<Mutex<OutputBuffer>>;
+
+// Deletes the oldest logs so at most `keep` are left
+fn prune_logs(dir: &Path, keep: usize) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut logs: Vec<_> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "log"))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    if logs.len() <= keep {
+        return;
+    }
+    logs.sort();
+    for (_, path) in &logs[..logs.len() - keep] {
+        let _ = fs::remove_file(path);
+    }
+}
+
+// <app data>/emulator-logs/<session or purpose>-<id>-<started at>.log
+pub fn log_path(dir: Option<PathBuf>, label: &str, id: u64, started_at: u64) -> Option<PathBuf> {
+    let dir = dir?.join(LOG_DIR);
+    fs::create_dir_all(&dir).ok()?;
+    prune_logs(&dir, LOGS_KEPT - 1);
+    let label: String = label
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    Some(dir.join(format!("{label}-{id}-{started_at}.log")))
+}
+
+// Reads raw bytes so a stray non-UTF-8 byte from the emulator doesn't end the
+// capture the way `lines()` would.
+async fn pump<R: AsyncRead + Unpin>(
+    reader: R,
+    stream: OutputStream,
+    tx: mpsc::Sender<OutputLine>,
+    lost: Arc<AtomicUsize>,
+) {
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let line = String::from_utf8_lossy(&buf)
+            .trim_end_matches(['\r', '\n'])
+            .to_string();
+        let line = OutputLine {
+            stream,
+            line,
+            at: crate::control::now_ms(),
+        };
+        match tx.try_send(line) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                lost.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => break,
+        }
+    }
+}
+
+pub struct Capture {
+    pub id: u64,
+    pub session_id: Option<String>,
+    pub buffer: SharedOutput,
+    pub log: Option<PathBuf>,
+}
+
+// Starts the readers and the collector. The collector finishes once both pipes
+// close, which is when the process is gone.
+pub fn spawn_capture(
+    events: Arc<dyn EmulatorEvents>,
+    capture: Capture,
+    stdout: Option<impl AsyncRead + Unpin + Send + 'static>,
+    stderr: Option<impl AsyncRead + Unpin + Send + 'static>,
+) -> JoinHandle<()> {
+    let (tx, mut rx) = mpsc::channel(CHANNEL_LINES);
+    let lost = Arc::new(AtomicUsize::new(0));
+    if let Some(out) = stdout {
+        let pump = pump(out, OutputStream::Stdout, tx.clone(), Arc::clone(&lost));
+        tauri::async_runtime::spawn(pump);
+    }
+    if let Some(err) = stderr {
+        let pump = pump(err, OutputStream::Stderr, tx.clone(), Arc::clone(&lost));
+        tauri::async_runtime::spawn(pump);
+    }
+    drop(tx);
+
+    tauri::async_runtime::spawn(async move {
+        let mut log = capture
+            .log
+            .as_ref()
+            .and_then(|path| File::create(path).ok())
+            .map(BufWriter::new);
+        let mut pending: Vec<OutputLine> = Vec::new();
+        let mut ticker = interval(EMIT_INTERVAL);
+        let mut open = true;
+        while open {
+            tokio::select! {
+                line = rx.recv() => match line {
+                    Some(line) => {
+                        if let Some(log) = log.as_mut() {
+                            let tag = match line.stream {
+                                OutputStream::Stdout => "out",
+                                OutputStream::Stderr => "err",
+                            };
+                            let _ = writeln!(log, "{} [{tag}] {}", line.at, line.line);
+                        }
+                        capture.buffer.lock().unwrap().push(line.clone());
+                        pending.push(line);
+                        continue;
+                    }
+                    None => open = false,
+                },
+                _ = ticker.tick() => {}
+            }
+            let lost = lost.swap(0, Ordering::Relaxed);
+            if pending.is_empty() && lost == 0 {
+                continue;
+            }
+            if let Some(log) = log.as_mut() {
+                if lost > 0 {
+                    let now = crate::control::now_ms();
+                    let _ = writeln!(log, "{now} [hr] {lost} lines dropped");
+                }
+                let _ = log.flush();
+            }
+            let dropped = pending.len().saturating_sub(MAX_LINES_PER_EVENT);
+            let event = OutputEvent {
+                id: capture.id,
+                session_id: capture.session_id.as_deref(),
+                lines: &pending[dropped..],
+                dropped,
+                lost,
+            };
+            events.emit(
+                "emulator:output",
+                serde_json::to_value(&event).unwrap_or_default(),
+            );
+            pending.clear();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(n: usize) -> OutputLine {
+        OutputLine {
+            stream: OutputStream::Stdout,
+            line: format!("line {n}"),
+            at: n as u64,
+        }
+    }
+
+    #[test]
+    fn buffer_keeps_only_the_newest_lines() {
+        let mut buffer = OutputBuffer::new(3);
+        for n in 0..5 {
+            buffer.push(line(n));
+        }
+        let tail: Vec<_> = buffer.tail(10).into_iter().map(|l| l.line).collect();
+        assert_eq!(tail, ["line 2", "line 3", "line 4"]);
+        let last: Vec<_> = buffer.tail(1).into_iter().map(|l| l.line).collect();
+        assert_eq!(last, ["line 4"]);
+    }
+
+    #[tokio::test]
+    async fn a_full_channel_drops_and_counts_lines() {
+        let (tx, mut rx) = mpsc::channel(2);
+        let lost = Arc::new(AtomicUsize::new(0));
+        let output: &[u8] = b"one\ntwo\nthree\nfour\nfive\n";
+        pump(output, OutputStream::Stdout, tx, Arc::clone(&lost)).await;
+        assert_eq!(rx.recv().await.unwrap().line, "one");
+        assert_eq!(rx.recv().await.unwrap().line, "two");
+        assert!(rx.recv().await.is_none());
+        assert_eq!(lost.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn old_logs_are_pruned() {
+        let dir = std::env::temp_dir().join(format!("hr-emulator-logs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut paths = Vec::new();
+        for id in 0..4 {
+            let path = log_path(Some(dir.clone()), "match", id, 1_000 + id).unwrap();
+            File::create(&path).unwrap();
+            // mtimes can share a tick on coarse filesystems
+            std::thread::sleep(Duration::from_millis(20));
+            paths.push(path);
+        }
+        fs::write(dir.join(LOG_DIR).join("notes.txt"), "kept").unwrap();
+        prune_logs(&dir.join(LOG_DIR), 2);
+        let left: Vec<bool> = paths.iter().map(|p| p.exists()).collect();
+        assert_eq!(left, [false, false, true, true]);
+        assert!(dir.join(LOG_DIR).join("notes.txt").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
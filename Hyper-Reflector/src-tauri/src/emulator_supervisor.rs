@@ -0,0 +1,953 @@
+// Owns every emulator process the app launches, whether it is a training
+// session, a proxied match, a mock match or a replay. Each child gets a wait
+// task that reports its exit, so nothing we spawn goes untracked.
+use crate::crash_report;
+use crate::emulator_output::{self, Capture, OutputBuffer, OutputLine, SharedOutput};
+use crate::launch::LaunchCommand;
+use crate::orphans::{self, PidRecord};
+use crate::proxy_stats::ProxyStats;
+use crate::resource_usage::{self, ResourceSample, SharedUsage, UsageHistory};
+use crate::scheduling::{self, SchedulingOptions, SchedulingReport};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    process::{ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tauri::{AppHandle, Emitter, EventTarget, Manager, Runtime, State};
+use tokio::{
+    process::Child,
+    sync::{oneshot, watch},
+    time::timeout,
+};
+
+// Output of exited processes stays readable for bug reports
+const FINISHED_KEPT: usize = 16;
+const DEFAULT_OUTPUT_LINES: usize = 200;
+// How long a crash report waits for the last output to come through the
+// pipes and into the log file
+const OUTPUT_DRAIN: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmulatorPurpose {
+    Training,
+    #[default]
+    Match,
+    Mock,
+    Replay,
+}
+
+impl EmulatorPurpose {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EmulatorPurpose::Training => "training",
+            EmulatorPurpose::Match => "match",
+            EmulatorPurpose::Mock => "mock",
+            EmulatorPurpose::Replay => "replay",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmulatorInfo {
+    pub id: u64,
+    pub purpose: EmulatorPurpose,
+    pub pid: Option<u32>,
+    pub started_at: u64,
+    // what was executed, Wine or a wrapper when not launched directly
+    pub program: String,
+    pub args: Vec<String>,
+    pub emulator: String,
+    // proxy session that launched it, if any
+    pub session_id: Option<String>,
+    pub log_path: Option<String>,
+    // how the requested priority and affinity went, None if it never started
+    pub scheduling: Option<SchedulingReport>,
+}
+
+// How a stop request is carried out: the process is asked to close first
+// (SIGTERM, or WM_CLOSE on Windows) so the emulator can shut down normally,
+// and only when the grace period runs out is it force-killed.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ShutdownOptions {
+    pub grace_ms: u64,
+}
+
+impl Default for ShutdownOptions {
+    fn default() -> Self {
+        Self { grace_ms: 3000 }
+    }
+}
+
+// The stage that actually ended the process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShutdownStage {
+    Terminate,
+    ForceKill,
+}
+
+impl ShutdownStage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ShutdownStage::Terminate => "terminate",
+            ShutdownStage::ForceKill => "force-kill",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case", rename_all_fields = "camelCase")]
+pub enum ExitKind {
+    Clean,
+    ExitCode { code: i32 },
+    Signal { signal: i32 },
+    SpawnFailed { message: String },
+    // the exit status could not be read
+    Unknown { message: String },
+}
+
+impl ExitKind {
+    fn classify(status: &std::io::Result<ExitStatus>) -> Self {
+        let status = match status {
+            Ok(status) => status,
+            Err(e) => {
+                return ExitKind::Unknown {
+                    message: e.to_string(),
+                }
+            }
+        };
+        if let Some(code) = status.code() {
+            return match code {
+                0 => ExitKind::Clean,
+                code => ExitKind::ExitCode { code },
+            };
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = status.signal() {
+                return ExitKind::Signal { signal };
+            }
+        }
+        ExitKind::Unknown {
+            message: status.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmulatorExit {
+    pub id: u64,
+    pub purpose: EmulatorPurpose,
+    pub session_id: Option<String>,
+    pub kind: ExitKind,
+    pub code: Option<i32>,
+    // true when we stopped it rather than it exiting by itself
+    pub stopped: bool,
+    pub stopped_by: Option<ShutdownStage>,
+    pub exited_at: u64,
+}
+
+impl EmulatorExit {
+    // Worth a crash report: it went away by itself and not with exit code 0
+    pub fn is_abnormal(&self) -> bool {
+        !self.stopped && self.kind != ExitKind::Clean
+    }
+}
+
+// Given to whoever asked for the launch so they can follow the exit
+pub struct EmulatorHandle {
+    pub id: u64,
+    pub scheduling: SchedulingReport,
+    pub exit: watch::Receiver<Option<EmulatorExit>>,
+}
+
+impl EmulatorHandle {
+    pub async fn wait(&mut self) -> Option<EmulatorExit> {
+        wait_for_exit(&mut self.exit).await
+    }
+}
+
+async fn wait_for_exit(exit: &mut watch::Receiver<Option<EmulatorExit>>) -> Option<EmulatorExit> {
+    exit.wait_for(|exit| exit.is_some())
+        .await
+        .ok()
+        .and_then(|exit| exit.clone())
+}
+
+struct Supervised {
+    info: EmulatorInfo,
+    kill_tx: Option<oneshot::Sender<ShutdownOptions>>,
+    exit: watch::Receiver<Option<EmulatorExit>>,
+    output: SharedOutput,
+    usage: SharedUsage,
+}
+
+// What is kept of an exited process
+struct Finished {
+    id: u64,
+    output: SharedOutput,
+    usage: SharedUsage,
+}
+
+// Pids on disk so the next run can find what this one left behind
+#[derive(Default)]
+struct Registry {
+    path: Option<PathBuf>,
+    live: HashMap<u64, PidRecord>,
+    // survivors of a previous run, until they are killed or exit
+    orphans: Vec<PidRecord>,
+}
+
+impl Registry {
+    fn persist(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let records: Vec<PidRecord> = self
+            .live
+            .values()
+            .chain(&self.orphans)
+            .cloned()
+            .collect();
+        if let Err(e) = orphans::save(path, &records) {
+            eprintln!("emulator pid registry: {e}");
+        }
+    }
+}
+
+// Where the supervisor reports spawns, output, exits and crashes. The app
+// passes its AppHandle, tests a recorder.
+pub trait EmulatorEvents: Send + Sync + 'static {
+    fn emit(&self, event: &str, payload: Value);
+    // Asked for as soon as an abnormal exit is seen, before it is published
+    fn session_stats(
+        &self,
+        session_id: String,
+    ) -> Pin<Box<dyn Future<Output = Option<ProxyStats>> + Send>>;
+    // An abnormal exit, with what was kept of the process
+    fn crashed(
+        &self,
+        info: EmulatorInfo,
+        exit: EmulatorExit,
+        proxy_stats: Option<ProxyStats>,
+        output: Vec<OutputLine>,
+        usage: Vec<ResourceSample>,
+    );
+}
+
+impl<R: Runtime> EmulatorEvents for AppHandle<R> {
+    fn emit(&self, event: &str, payload: Value) {
+        let _ = self.emit_to(EventTarget::any(), event, payload);
+    }
+
+    fn session_stats(
+        &self,
+        session_id: String,
+    ) -> Pin<Box<dyn Future<Output = Option<ProxyStats>> + Send>> {
+        let app = self.clone();
+        Box::pin(async move { crash_report::session_stats(&app, &session_id).await })
+    }
+
+    fn crashed(
+        &self,
+        info: EmulatorInfo,
+        exit: EmulatorExit,
+        proxy_stats: Option<ProxyStats>,
+        output: Vec<OutputLine>,
+        usage: Vec<ResourceSample>,
+    ) {
+        let app = self.clone();
+        tauri::async_runtime::spawn(async move {
+            crash_report::report_crash(&app, info, exit, proxy_stats, output, usage).await;
+        });
+    }
+}
+
+fn payload(value: &impl Serialize) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+struct Shared {
+    next_id: AtomicU64,
+    procs: Mutex<HashMap<u64, Supervised>>,
+    finished: Mutex<VecDeque<Finished>>,
+    registry: Mutex<Registry>,
+}
+
+// Cheap to clone, every wait task holds on to the same state
+#[derive(Clone)]
+pub struct EmulatorSupervisor {
+    shared: Arc<Shared>,
+}
+
+impl EmulatorSupervisor {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                next_id: AtomicU64::new(1),
+                procs: Mutex::new(HashMap::new()),
+                finished: Mutex::new(VecDeque::new()),
+                registry: Mutex::new(Registry::default()),
+            }),
+        }
+    }
+
+    // Called once on startup. Returns the emulators a previous run left running.
+    pub fn load_registry(&self, path: PathBuf) -> Vec<PidRecord> {
+        let survivors: Vec<PidRecord> = orphans::load(&path)
+            .into_iter()
+            .filter(orphans::is_our_emulator)
+            .collect();
+        let mut registry = self.shared.registry.lock().unwrap();
+        registry.path = Some(path);
+        registry.orphans = survivors.clone();
+        registry.persist();
+        survivors
+    }
+
+    pub fn orphans(&self) -> Vec<PidRecord> {
+        let mut registry = self.shared.registry.lock().unwrap();
+        let before = registry.orphans.len();
+        registry.orphans.retain(orphans::is_our_emulator);
+        if registry.orphans.len() != before {
+            registry.persist();
+        }
+        registry.orphans.clone()
+    }
+
+    // Kills the given orphans, or all of them. Returns the pids that are gone.
+    pub async fn kill_orphans(&self, pids: Option<Vec<u32>>) -> Vec<u32> {
+        let targets: Vec<PidRecord> = self
+            .orphans()
+            .into_iter()
+            .filter(|record| pids.as_ref().is_none_or(|pids| pids.contains(&record.pid)))
+            .collect();
+        let mut killed = Vec::new();
+        for record in targets {
+            if orphans::kill(&record).await {
+                killed.push(record.pid);
+            }
+        }
+        let mut registry = self.shared.registry.lock().unwrap();
+        registry.orphans.retain(|record| !killed.contains(&record.pid));
+        registry.persist();
+        killed
+    }
+
+    pub fn spawn<R: Runtime>(
+        &self,
+        app: &AppHandle<R>,
+        purpose: EmulatorPurpose,
+        session_id: Option<String>,
+        launch: LaunchCommand,
+    ) -> Result<EmulatorHandle, String> {
+        let log_dir = app.path().app_data_dir().ok();
+        self.spawn_with(Arc::new(app.clone()), log_dir, purpose, session_id, launch)
+    }
+
+    // `log_dir` is where the emulator-logs folder goes, None to skip the log
+    pub fn spawn_with(
+        &self,
+        events: Arc<dyn EmulatorEvents>,
+        log_dir: Option<PathBuf>,
+        purpose: EmulatorPurpose,
+        session_id: Option<String>,
+        launch: LaunchCommand,
+    ) -> Result<EmulatorHandle, String> {
+        let LaunchCommand {
+            mut cmd,
+            emulator,
+            scheduling,
+        } = launch;
+        let std_cmd = cmd.as_std();
+        let program = std_cmd.get_program().to_string_lossy().into_owned();
+        let args = std_cmd
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect();
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+        let started_at = crate::control::now_ms();
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                let message = format!("failed to launch {program}: {e}");
+                let info = EmulatorInfo {
+                    id,
+                    purpose,
+                    pid: None,
+                    started_at,
+                    program,
+                    args,
+                    emulator,
+                    session_id,
+                    log_path: None,
+                    scheduling: None,
+                };
+                let exit = EmulatorExit {
+                    id,
+                    purpose,
+                    session_id: info.session_id.clone(),
+                    kind: ExitKind::SpawnFailed {
+                        message: message.clone(),
+                    },
+                    code: None,
+                    stopped: false,
+                    stopped_by: None,
+                    exited_at: started_at,
+                };
+                events.emit("emulator:exited", payload(&exit));
+                events.crashed(info, exit, None, Vec::new(), Vec::new());
+                return Err(message);
+            }
+        };
+
+        let scheduling = match child.id() {
+            Some(pid) => scheduling::apply(
+                pid,
+                &scheduling.unwrap_or_else(|| SchedulingOptions::for_purpose(purpose)),
+            ),
+            None => SchedulingReport::default(),
+        };
+        let label = match &session_id {
+            Some(session) => session.clone(),
+            None => purpose.as_str().to_string(),
+        };
+        let log = emulator_output::log_path(log_dir, &label, id, started_at);
+        let output = Arc::new(Mutex::new(OutputBuffer::new(emulator_output::BUFFER_LINES)));
+        let capture = emulator_output::spawn_capture(
+            Arc::clone(&events),
+            Capture {
+                id,
+                session_id: session_id.clone(),
+                buffer: Arc::clone(&output),
+                log: log.clone(),
+            },
+            child.stdout.take(),
+            child.stderr.take(),
+        );
+        let info = EmulatorInfo {
+            id,
+            purpose,
+            pid: child.id(),
+            started_at,
+            program,
+            args,
+            emulator,
+            session_id,
+            log_path: log.map(|p| p.to_string_lossy().into_owned()),
+            scheduling: Some(scheduling.clone()),
+        };
+        let (kill_tx, mut kill_rx) = oneshot::channel();
+        let (exit_tx, exit_rx) = watch::channel(None);
+        let usage = Arc::new(Mutex::new(UsageHistory::new(resource_usage::HISTORY_LEN)));
+        if let Some(pid) = info.pid {
+            resource_usage::spawn_sampler(
+                pid,
+                Arc::clone(&usage),
+                resource_usage::default_probe(),
+                exit_rx.clone(),
+            );
+        }
+        self.shared.procs.lock().unwrap().insert(
+            id,
+            Supervised {
+                info: info.clone(),
+                kill_tx: Some(kill_tx),
+                exit: exit_rx.clone(),
+                output,
+                usage,
+            },
+        );
+        if let Some(pid) = info.pid {
+            let mut registry = self.shared.registry.lock().unwrap();
+            registry.live.insert(
+                id,
+                PidRecord {
+                    pid,
+                    program: info.emulator.clone(),
+                    purpose,
+                    started_at,
+                    proc_start: orphans::proc_start(pid),
+                },
+            );
+            registry.persist();
+        }
+        events.emit("emulator:spawned", payload(&info));
+
+        let supervisor = self.clone();
+        tauri::async_runtime::spawn(async move {
+            let (status, stopped_by) = tokio::select! {
+                status = child.wait() => (status, None),
+                Ok(options) = &mut kill_rx => {
+                    let (status, stage) = shut_down(&mut child, options).await;
+                    (status, Some(stage))
+                }
+            };
+            let exit = EmulatorExit {
+                id: info.id,
+                purpose: info.purpose,
+                session_id: info.session_id.clone(),
+                kind: ExitKind::classify(&status),
+                code: status.ok().and_then(|s| s.code()),
+                stopped: stopped_by.is_some(),
+                stopped_by,
+                exited_at: crate::control::now_ms(),
+            };
+            let proxy_stats = match &info.session_id {
+                Some(session_id) if exit.is_abnormal() => {
+                    events.session_stats(session_id.clone()).await
+                }
+                _ => None,
+            };
+            // whoever waits on the exit hears of it right away, the output
+            // only matters to the crash report and the log
+            supervisor.retire(id);
+            events.emit("emulator:exited", payload(&exit));
+            let _ = exit_tx.send(Some(exit.clone()));
+            let _ = timeout(OUTPUT_DRAIN, capture).await;
+            if exit.is_abnormal() {
+                let output = supervisor
+                    .output(id, crash_report::REPORT_LINES)
+                    .unwrap_or_default();
+                let usage = supervisor.usage(id).unwrap_or_default();
+                events.crashed(info, exit, proxy_stats, output, usage);
+            }
+        });
+
+        Ok(EmulatorHandle {
+            id,
+            scheduling,
+            exit: exit_rx,
+        })
+    }
+
+    pub fn list(&self) -> Vec<EmulatorInfo> {
+        let mut list: Vec<_> = self
+            .shared
+            .procs
+            .lock()
+            .unwrap()
+            .values()
+            .map(|p| p.info.clone())
+            .collect();
+        list.sort_by_key(|info| info.id);
+        list
+    }
+
+    fn retire(&self, id: u64) {
+        let Some(proc) = self.shared.procs.lock().unwrap().remove(&id) else {
+            return;
+        };
+        {
+            let mut registry = self.shared.registry.lock().unwrap();
+            if registry.live.remove(&id).is_some() {
+                registry.persist();
+            }
+        }
+        let mut finished = self.shared.finished.lock().unwrap();
+        if finished.len() == FINISHED_KEPT {
+            finished.pop_front();
+        }
+        finished.push_back(Finished {
+            id,
+            output: proc.output,
+            usage: proc.usage,
+        });
+    }
+
+    // Output and usage of a running or recently exited process
+    fn buffers(&self, id: u64) -> Option<(SharedOutput, SharedUsage)> {
+        if let Some(proc) = self.shared.procs.lock().unwrap().get(&id) {
+            return Some((Arc::clone(&proc.output), Arc::clone(&proc.usage)));
+        }
+        self.shared.finished
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|finished| finished.id == id)
+            .map(|finished| (Arc::clone(&finished.output), Arc::clone(&finished.usage)))
+    }
+
+    // Last `lines` lines of a running or recently exited process
+    pub fn output(&self, id: u64, lines: usize) -> Option<Vec<OutputLine>> {
+        let (output, _) = self.buffers(id)?;
+        let tail = output.lock().unwrap().tail(lines);
+        Some(tail)
+    }
+
+    // Recent resource samples, oldest first
+    pub fn usage(&self, id: u64) -> Option<Vec<ResourceSample>> {
+        let (_, usage) = self.buffers(id)?;
+        let samples = usage.lock().unwrap().samples();
+        Some(samples)
+    }
+
+    // The newest `n` resource samples, oldest first
+    pub fn recent_usage(&self, id: u64, n: usize) -> Option<Vec<ResourceSample>> {
+        let (_, usage) = self.buffers(id)?;
+        let recent = usage.lock().unwrap().recent(n);
+        Some(recent)
+    }
+
+    pub fn info(&self, id: u64) -> Option<EmulatorInfo> {
+        self.shared.procs.lock().unwrap().get(&id).map(|p| p.info.clone())
+    }
+
+    // Stops the process and waits for its wait task to reap it. None when the
+    // id is unknown or already gone.
+    pub async fn stop(&self, id: u64, options: ShutdownOptions) -> Option<EmulatorExit> {
+        let (kill_tx, mut exit) = {
+            let mut procs = self.shared.procs.lock().unwrap();
+            let proc = procs.get_mut(&id)?;
+            (proc.kill_tx.take(), proc.exit.clone())
+        };
+        if let Some(tx) = kill_tx {
+            let _ = tx.send(options);
+        }
+        wait_for_exit(&mut exit).await
+    }
+
+    pub async fn stop_all(&self, options: ShutdownOptions) -> Vec<EmulatorExit> {
+        let ids: Vec<u64> = self.shared.procs.lock().unwrap().keys().copied().collect();
+        let stops = ids.into_iter().map(|id| self.stop(id, options));
+        futures_util::future::join_all(stops)
+            .await
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+#[cfg(unix)]
+async fn terminate(child: &Child) -> bool {
+    match child.id() {
+        // SAFETY: plain kill(2) on a pid we spawned and have not reaped yet
+        Some(pid) => unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) == 0 },
+        None => false,
+    }
+}
+
+// Without /F taskkill posts WM_CLOSE to the process's windows, the same as
+// the user closing the emulator
+#[cfg(windows)]
+async fn terminate(child: &Child) -> bool {
+    use windows_sys::Win32::System::Threading::CREATE_NO_WINDOW;
+    let Some(pid) = child.id() else {
+        return false;
+    };
+    tokio::process::Command::new("taskkill")
+        .args(["/PID", &pid.to_string()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .creation_flags(CREATE_NO_WINDOW)
+        .status()
+        .await
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(not(any(unix, windows)))]
+async fn terminate(_child: &Child) -> bool {
+    false
+}
+
+async fn shut_down(
+    child: &mut Child,
+    options: ShutdownOptions,
+) -> (std::io::Result<ExitStatus>, ShutdownStage) {
+    if terminate(child).await {
+        let grace = Duration::from_millis(options.grace_ms);
+        if let Ok(status) = timeout(grace, child.wait()).await {
+            return (status, ShutdownStage::Terminate);
+        }
+    }
+    let _ = child.start_kill();
+    (child.wait().await, ShutdownStage::ForceKill)
+}
+
+#[tauri::command]
+pub fn list_emulators(state: State<'_, EmulatorSupervisor>) -> Vec<EmulatorInfo> {
+    state.list()
+}
+
+#[tauri::command]
+pub fn get_emulator_output(
+    state: State<'_, EmulatorSupervisor>,
+    id: u64,
+    lines: Option<usize>,
+) -> Result<Vec<OutputLine>, String> {
+    state
+        .output(id, lines.unwrap_or(DEFAULT_OUTPUT_LINES))
+        .ok_or_else(|| format!("No output kept for emulator {id}"))
+}
+
+#[tauri::command]
+pub fn get_emulator_usage(
+    state: State<'_, EmulatorSupervisor>,
+    id: u64,
+) -> Result<Vec<ResourceSample>, String> {
+    state
+        .usage(id)
+        .ok_or_else(|| format!("No usage kept for emulator {id}"))
+}
+
+#[tauri::command]
+pub async fn stop_emulator(
+    state: State<'_, EmulatorSupervisor>,
+    id: u64,
+    options: Option<ShutdownOptions>,
+) -> Result<EmulatorExit, String> {
+    state
+        .stop(id, options.unwrap_or_default())
+        .await
+        .ok_or_else(|| format!("No running emulator {id}"))
+}
+
+#[tauri::command]
+pub fn list_orphaned_emulators(state: State<'_, EmulatorSupervisor>) -> Vec<PidRecord> {
+    state.orphans()
+}
+
+#[tauri::command]
+pub async fn kill_orphaned_emulators(
+    state: State<'_, EmulatorSupervisor>,
+    pids: Option<Vec<u32>>,
+) -> Result<Vec<u32>, String> {
+    Ok(state.kill_orphans(pids).await)
+}
+
+#[tauri::command]
+pub async fn stop_all_emulators(
+    state: State<'_, EmulatorSupervisor>,
+    options: Option<ShutdownOptions>,
+) -> Result<Vec<EmulatorExit>, String> {
+    Ok(state.stop_all(options.unwrap_or_default()).await)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::process::Command;
+
+    fn spawn(script: &str) -> Child {
+        Command::new("sh").args(["-c", script]).spawn().unwrap()
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+        crashes: Mutex<Vec<EmulatorExit>>,
+    }
+
+    impl EmulatorEvents for Recorder {
+        fn emit(&self, event: &str, _payload: Value) {
+            self.events.lock().unwrap().push(event.to_string());
+        }
+
+        fn session_stats(
+            &self,
+            session_id: String,
+        ) -> Pin<Box<dyn Future<Output = Option<ProxyStats>> + Send>> {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("stats of {session_id}"));
+            Box::pin(async { None })
+        }
+
+        fn crashed(
+            &self,
+            _info: EmulatorInfo,
+            exit: EmulatorExit,
+            _proxy_stats: Option<ProxyStats>,
+            _output: Vec<OutputLine>,
+            _usage: Vec<ResourceSample>,
+        ) {
+            self.crashes.lock().unwrap().push(exit);
+        }
+    }
+
+    fn launch(script: &str) -> LaunchCommand {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]);
+        LaunchCommand {
+            cmd,
+            emulator: "sh".to_string(),
+            scheduling: Some(SchedulingOptions::default()),
+        }
+    }
+
+    async fn eventually(check: impl Fn() -> bool) {
+        for _ in 0..200 {
+            if check() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("condition never held");
+    }
+
+    #[tokio::test]
+    async fn supervises_a_real_child_until_it_is_stopped() {
+        let supervisor = EmulatorSupervisor::new();
+        let events = Arc::new(Recorder::default());
+        let mut handle = supervisor
+            .spawn_with(
+                events.clone(),
+                None,
+                EmulatorPurpose::Training,
+                Some("s1".to_string()),
+                launch("echo ready; exec sleep 30"),
+            )
+            .unwrap();
+        let id = handle.id;
+        assert_eq!(supervisor.list()[0].session_id.as_deref(), Some("s1"));
+        eventually(|| {
+            supervisor
+                .output(id, 10)
+                .is_some_and(|lines| lines.iter().any(|l| l.line == "ready"))
+        })
+        .await;
+
+        let exit = supervisor
+            .stop(id, ShutdownOptions { grace_ms: 1000 })
+            .await
+            .unwrap();
+        assert_eq!(exit.stopped_by, Some(ShutdownStage::Terminate));
+        assert!(!exit.is_abnormal());
+        assert_eq!(handle.wait().await.unwrap().id, id);
+        assert!(supervisor.list().is_empty());
+        assert!(supervisor.stop(id, ShutdownOptions::default()).await.is_none());
+        // what it printed outlives it
+        assert_eq!(supervisor.output(id, 10).unwrap()[0].line, "ready");
+
+        let recorded = events.events.lock().unwrap().clone();
+        assert_eq!(recorded.first().map(String::as_str), Some("emulator:spawned"));
+        assert_eq!(recorded.last().map(String::as_str), Some("emulator:exited"));
+        assert!(recorded.iter().any(|e| e == "emulator:output"));
+        assert!(events.crashes.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn the_exit_is_published_before_the_output_drains() {
+        let supervisor = EmulatorSupervisor::new();
+        // the background sleep keeps the pipes open after the shell is gone
+        let mut handle = supervisor
+            .spawn_with(
+                Arc::new(Recorder::default()),
+                None,
+                EmulatorPurpose::Training,
+                None,
+                launch("sleep 2 & exit 0"),
+            )
+            .unwrap();
+        let began = std::time::Instant::now();
+        assert_eq!(handle.wait().await.unwrap().kind, ExitKind::Clean);
+        assert!(began.elapsed() < OUTPUT_DRAIN / 2);
+        assert!(supervisor.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_child_that_dies_by_itself_is_reported_as_a_crash() {
+        let supervisor = EmulatorSupervisor::new();
+        let events = Arc::new(Recorder::default());
+        let mut handle = supervisor
+            .spawn_with(
+                events.clone(),
+                None,
+                EmulatorPurpose::Match,
+                Some("s2".to_string()),
+                launch("exit 3"),
+            )
+            .unwrap();
+        let exit = handle.wait().await.unwrap();
+        assert_eq!(exit.kind, ExitKind::ExitCode { code: 3 });
+        eventually(|| events.crashes.lock().unwrap().len() == 1).await;
+        assert!(supervisor.list().is_empty());
+        // the session was looked at before anyone heard of the exit
+        let recorded = events.events.lock().unwrap().clone();
+        let stats = recorded.iter().position(|e| e == "stats of s2").unwrap();
+        let exited = recorded.iter().position(|e| e == "emulator:exited").unwrap();
+        assert!(stats < exited);
+    }
+
+    fn exit(kind: ExitKind, stopped: bool) -> EmulatorExit {
+        EmulatorExit {
+            id: 1,
+            purpose: EmulatorPurpose::Match,
+            session_id: None,
+            kind,
+            code: None,
+            stopped,
+            stopped_by: stopped.then_some(ShutdownStage::ForceKill),
+            exited_at: 0,
+        }
+    }
+
+    #[test]
+    fn only_exits_we_did_not_ask_for_are_abnormal() {
+        use std::os::unix::process::ExitStatusExt;
+        let classify = |raw| ExitKind::classify(&Ok(ExitStatus::from_raw(raw)));
+        let segv = ExitKind::Signal {
+            signal: libc::SIGSEGV,
+        };
+        assert_eq!(classify(0), ExitKind::Clean);
+        assert_eq!(classify(3 << 8), ExitKind::ExitCode { code: 3 });
+        assert_eq!(classify(libc::SIGSEGV), segv);
+        let unreadable = ExitKind::classify(&Err(std::io::Error::other("no status")));
+        assert!(matches!(unreadable, ExitKind::Unknown { .. }));
+
+        assert!(!exit(ExitKind::Clean, false).is_abnormal());
+        assert!(exit(ExitKind::ExitCode { code: 3 }, false).is_abnormal());
+        assert!(exit(segv, false).is_abnormal());
+        // however it went down, a stop we asked for is not a crash
+        let killed = ExitKind::Signal {
+            signal: libc::SIGKILL,
+        };
+        assert!(!exit(killed, true).is_abnormal());
+    }
+
+    #[tokio::test]
+    async fn shut_down_terminates_then_force_kills() {
+        let options = ShutdownOptions { grace_ms: 300 };
+
+        let mut child = spawn("exec sleep 30");
+        let (status, stage) = shut_down(&mut child, options).await;
+        assert_eq!(stage, ShutdownStage::Terminate);
+        assert_eq!(
+            ExitKind::classify(&status),
+            ExitKind::Signal {
+                signal: libc::SIGTERM
+            }
+        );
+
+        // an ignored SIGTERM survives the exec, so only the kill gets it
+        let mut stubborn = spawn("trap '' TERM; exec sleep 30");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let (status, stage) = shut_down(&mut stubborn, options).await;
+        assert_eq!(stage, ShutdownStage::ForceKill);
+        assert_eq!(
+            ExitKind::classify(&status),
+            ExitKind::Signal {
+                signal: libc::SIGKILL
+            }
+        );
+    }
+}
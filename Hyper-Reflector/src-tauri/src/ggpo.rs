@@ -0,0 +1,404 @@
+// Passive decoder for the GGPO UDP protocol spoken by FBNeo netplay.
+// Layout follows ggpo's udp_msg.h (packed, little-endian):
+//   hdr:   magic u16, sequence_number u16, type u8
+//   body:  depends on type, see GgpoMessage
+// We never modify packets, this only reads what the proxy already forwards.
+use serde::Serialize;
+use std::{collections::VecDeque, time::Instant};
+
+const HEADER_LEN: usize = 5;
+const MAX_PLAYERS: usize = 4;
+// peer_connect_status[MAX_PLAYERS] of { disconnected:1, last_frame:31 }
+const CONNECT_STATUS_LEN: usize = MAX_PLAYERS * 4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct GgpoHeader {
+    pub magic: u16,
+    pub sequence: u16,
+}
+
+#[derive(Debug, Clone)]
+pub enum GgpoMessage {
+    SyncRequest,
+    SyncReply,
+    Input {
+        start_frame: u32,
+        disconnect_requested: bool,
+        ack_frame: i32,
+    },
+    QualityReport {
+        frame_advantage: i8,
+        ping: u32,
+    },
+    QualityReply {
+        pong: u32,
+    },
+    KeepAlive,
+    InputAck {
+        ack_frame: i32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct GgpoPacket {
+    pub header: GgpoHeader,
+    pub message: GgpoMessage,
+}
+
+fn read_u16(buf: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(buf.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(buf: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(buf.get(at..at + 4)?.try_into().ok()?))
+}
+
+// ggpo packs a 1-bit flag in the low bit followed by a signed 31-bit field
+fn split_flag_field(raw: u32) -> (bool, i32) {
+    (raw & 1 != 0, (raw as i32) >> 1)
+}
+
+fn sign_extend_31(raw: u32) -> i32 {
+    ((raw << 1) as i32) >> 1
+}
+
+pub fn decode(buf: &[u8]) -> Option<GgpoPacket> {
+    if buf.len() < HEADER_LEN {
+        return None;
+    }
+    let header = GgpoHeader {
+        magic: read_u16(buf, 0)?,
+        sequence: read_u16(buf, 2)?,
+    };
+    let body = HEADER_LEN;
+    let message = match buf[4] {
+        // sync_request { random_request u32, remote_magic u16, remote_endpoint u8 }
+        1 if buf.len() >= body + 7 => GgpoMessage::SyncRequest,
+        // sync_reply { random_reply u32 }
+        2 if buf.len() >= body + 4 => GgpoMessage::SyncReply,
+        3 => {
+            let at = body + CONNECT_STATUS_LEN;
+            let start_frame = read_u32(buf, at)?;
+            let (disconnect_requested, ack_frame) = split_flag_field(read_u32(buf, at + 4)?);
+            // followed by num_bits u16, input_size u8 and the compressed input bits
+            GgpoMessage::Input {
+                start_frame,
+                disconnect_requested,
+                ack_frame,
+            }
+        }
+        4 => GgpoMessage::QualityReport {
+            frame_advantage: *buf.get(body)? as i8,
+            ping: read_u32(buf, body + 1)?,
+        },
+        5 => GgpoMessage::QualityReply {
+            pong: read_u32(buf, body)?,
+        },
+        6 => GgpoMessage::KeepAlive,
+        7 => GgpoMessage::InputAck {
+            // input_ack is a bare signed 31-bit field, no flag bit
+            ack_frame: sign_extend_31(read_u32(buf, body)?),
+        },
+        _ => return None,
+    };
+    Some(GgpoPacket { header, message })
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GgpoMessageCounts {
+    pub sync_request: u64,
+    pub sync_reply: u64,
+    pub input: u64,
+    pub input_ack: u64,
+    pub quality_report: u64,
+    pub quality_reply: u64,
+    pub keep_alive: u64,
+    pub undecoded: u64,
+}
+
+impl GgpoMessageCounts {
+    fn bump(&mut self, msg: Option<&GgpoMessage>) {
+        match msg {
+            Some(GgpoMessage::SyncRequest) => self.sync_request += 1,
+            Some(GgpoMessage::SyncReply) => self.sync_reply += 1,
+            Some(GgpoMessage::Input { .. }) => self.input += 1,
+            Some(GgpoMessage::InputAck { .. }) => self.input_ack += 1,
+            Some(GgpoMessage::QualityReport { .. }) => self.quality_report += 1,
+            Some(GgpoMessage::QualityReply { .. }) => self.quality_reply += 1,
+            Some(GgpoMessage::KeepAlive) => self.keep_alive += 1,
+            None => self.undecoded += 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GgpoStats {
+    // frame advantage the remote emulator reports in its quality reports
+    pub remote_frame_advantage: Option<i8>,
+    pub local_frame_advantage: Option<i8>,
+    // round trip of our quality report -> their quality reply, measured at the proxy
+    pub quality_ping_ms: Option<u64>,
+    pub quality_ping_avg_ms: Option<f64>,
+    // average gap between input packets in each direction
+    pub input_interval_out_ms: Option<f64>,
+    pub input_interval_in_ms: Option<f64>,
+    pub last_remote_frame: Option<u32>,
+    pub last_local_frame: Option<u32>,
+    // newest of our frames the peer has acknowledged; a growing distance to
+    // last_local_frame means inputs are in flight for longer and rollbacks get deeper
+    pub remote_ack_frame: Option<i32>,
+    pub unacked_frames: Option<i64>,
+    pub remote_magic: Option<u16>,
+    pub remote_disconnect_requested: bool,
+    // gaps in the remote sequence numbers, i.e. datagrams lost before reaching us
+    pub remote_sequence_gaps: u64,
+    pub remote_loss_ratio: f64,
+    pub outgoing: GgpoMessageCounts,
+    pub incoming: GgpoMessageCounts,
+}
+
+// quality reports are sent about once a second; a handful in flight is plenty
+const MAX_PENDING_PINGS: usize = 16;
+const EWMA_ALPHA: f64 = 0.2;
+
+fn ewma(current: Option<f64>, sample: f64) -> Option<f64> {
+    Some(match current {
+        Some(avg) => avg + EWMA_ALPHA * (sample - avg),
+        None => sample,
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct GgpoTelemetry {
+    stats: GgpoStats,
+    pending_pings: VecDeque<(u32, Instant)>,
+    last_remote_sequence: Option<u16>,
+    remote_received: u64,
+    last_input_out: Option<Instant>,
+    last_input_in: Option<Instant>,
+}
+
+impl GgpoTelemetry {
+    // datagram leaving our emulator towards the peer
+    pub fn observe_outgoing(&mut self, buf: &[u8]) {
+        let packet = decode(buf);
+        self.stats
+            .outgoing
+            .bump(packet.as_ref().map(|p| &p.message));
+        let Some(packet) = packet else { return };
+        let now = Instant::now();
+        match packet.message {
+            GgpoMessage::QualityReport {
+                frame_advantage,
+                ping,
+            } => {
+                self.stats.local_frame_advantage = Some(frame_advantage);
+                if self.pending_pings.len() >= MAX_PENDING_PINGS {
+                    self.pending_pings.pop_front();
+                }
+                self.pending_pings.push_back((ping, now));
+            }
+            GgpoMessage::Input { start_frame, .. } => {
+                self.stats.last_local_frame = Some(start_frame);
+                if let Some(prev) = self.last_input_out.replace(now) {
+                    let gap = now.duration_since(prev).as_secs_f64() * 1000.0;
+                    self.stats.input_interval_out_ms = ewma(self.stats.input_interval_out_ms, gap);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // datagram from the peer about to be handed to our emulator
+    pub fn observe_incoming(&mut self, buf: &[u8]) {
+        let packet = decode(buf);
+        self.stats
+            .incoming
+            .bump(packet.as_ref().map(|p| &p.message));
+        let Some(packet) = packet else { return };
+        let now = Instant::now();
+
+        self.track_sequence(packet.header.sequence);
+        self.stats.remote_magic = Some(packet.header.magic);
+
+        match packet.message {
+            GgpoMessage::QualityReport {
+                frame_advantage, ..
+            } => {
+                self.stats.remote_frame_advantage = Some(frame_advantage);
+            }
+            GgpoMessage::QualityReply { pong } => {
+                if let Some(idx) = self.pending_pings.iter().position(|(p, _)| *p == pong) {
+                    let (_, sent) = self.pending_pings[idx];
+                    self.pending_pings.drain(..=idx);
+                    let rtt = now.duration_since(sent).as_millis() as u64;
+                    self.stats.quality_ping_ms = Some(rtt);
+                    self.stats.quality_ping_avg_ms = ewma(self.stats.quality_ping_avg_ms, rtt as f64);
+                }
+            }
+            GgpoMessage::Input {
+                start_frame,
+                disconnect_requested,
+                ack_frame,
+            } => {
+                self.stats.remote_disconnect_requested |= disconnect_requested;
+                self.record_remote_ack(ack_frame);
+                self.stats.last_remote_frame = Some(start_frame);
+                if let Some(prev) = self.last_input_in.replace(now) {
+                    let gap = now.duration_since(prev).as_secs_f64() * 1000.0;
+                    self.stats.input_interval_in_ms = ewma(self.stats.input_interval_in_ms, gap);
+                }
+            }
+            GgpoMessage::InputAck { ack_frame } => self.record_remote_ack(ack_frame),
+            _ => {}
+        }
+    }
+
+    fn record_remote_ack(&mut self, ack_frame: i32) {
+        // ggpo uses -1 (all bits set) for "nothing acked yet"
+        if ack_frame < 0 {
+            return;
+        }
+        self.stats.remote_ack_frame = Some(ack_frame);
+        self.stats.unacked_frames = self
+            .stats
+            .last_local_frame
+            .map(|local| local as i64 - ack_frame as i64);
+    }
+
+    fn track_sequence(&mut self, sequence: u16) {
+        self.remote_received += 1;
+        if let Some(prev) = self.last_remote_sequence {
+            let step = sequence.wrapping_sub(prev);
+            // anything "backwards" is reordering or a resync; only count forward gaps
+            if step > 1 && step < u16::MAX / 2 {
+                self.stats.remote_sequence_gaps += (step - 1) as u64;
+            }
+            if step == 0 || step >= u16::MAX / 2 {
+                return;
+            }
+        }
+        self.last_remote_sequence = Some(sequence);
+        let expected = self.remote_received + self.stats.remote_sequence_gaps;
+        self.stats.remote_loss_ratio = self.stats.remote_sequence_gaps as f64 / expected as f64;
+    }
+
+    pub fn snapshot(&self) -> GgpoStats {
+        self.stats.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAGIC: u16 = 0xbeef;
+
+    // hdr as laid out in udp_msg.h
+    fn packet(sequence: u16, kind: u8, body: &[u8]) -> Vec<u8> {
+        let mut buf = MAGIC.to_le_bytes().to_vec();
+        buf.extend(sequence.to_le_bytes());
+        buf.push(kind);
+        buf.extend(body);
+        buf
+    }
+
+    // u.input: peer_connect_status[4], start_frame, disconnect_requested:1 +
+    // ack_frame:31, num_bits, input_size, bits
+    fn input_body(start_frame: u32, disconnect: bool, ack_frame: i32) -> Vec<u8> {
+        let mut body = Vec::new();
+        for _ in 0..MAX_PLAYERS {
+            // disconnected:0, last_frame:-1
+            body.extend(((-1i32 as u32) << 1).to_le_bytes());
+        }
+        body.extend(start_frame.to_le_bytes());
+        body.extend(((ack_frame as u32) << 1 | u32::from(disconnect)).to_le_bytes());
+        body.extend(16u16.to_le_bytes());
+        body.push(2);
+        body.extend([0x12, 0x34]);
+        body
+    }
+
+    #[test]
+    fn decodes_the_header_and_every_message_type() {
+        let sync = decode(&packet(7, 1, &[1, 2, 3, 4, 0xef, 0xbe, 0])).unwrap();
+        assert_eq!((sync.header.magic, sync.header.sequence), (MAGIC, 7));
+        assert!(matches!(sync.message, GgpoMessage::SyncRequest));
+        assert!(matches!(
+            decode(&packet(8, 2, &[1, 2, 3, 4])).unwrap().message,
+            GgpoMessage::SyncReply
+        ));
+
+        match decode(&packet(9, 3, &input_body(1200, true, 1195))).unwrap().message {
+            GgpoMessage::Input {
+                start_frame,
+                disconnect_requested,
+                ack_frame,
+            } => assert_eq!((start_frame, disconnect_requested, ack_frame), (1200, true, 1195)),
+            other => panic!("{other:?}"),
+        }
+
+        let mut report = vec![(-3i8) as u8];
+        report.extend(123_456u32.to_le_bytes());
+        match decode(&packet(10, 4, &report)).unwrap().message {
+            GgpoMessage::QualityReport {
+                frame_advantage,
+                ping,
+            } => assert_eq!((frame_advantage, ping), (-3, 123_456)),
+            other => panic!("{other:?}"),
+        }
+        assert!(matches!(
+            decode(&packet(11, 5, &123_456u32.to_le_bytes())).unwrap().message,
+            GgpoMessage::QualityReply { pong: 123_456 }
+        ));
+        assert!(matches!(
+            decode(&packet(12, 6, &[])).unwrap().message,
+            GgpoMessage::KeepAlive
+        ));
+        assert!(matches!(
+            decode(&packet(13, 7, &1195u32.to_le_bytes())).unwrap().message,
+            GgpoMessage::InputAck { ack_frame: 1195 }
+        ));
+    }
+
+    #[test]
+    fn negative_acks_keep_their_sign() {
+        // -1 in 31 bits, whatever the unused top bit holds
+        for raw in [0x7fff_ffffu32, 0xffff_ffff] {
+            assert!(matches!(
+                decode(&packet(1, 7, &raw.to_le_bytes())).unwrap().message,
+                GgpoMessage::InputAck { ack_frame: -1 }
+            ));
+        }
+        assert!(matches!(
+            decode(&packet(1, 3, &input_body(0, false, -1))).unwrap().message,
+            GgpoMessage::Input { ack_frame: -1, .. }
+        ));
+
+        // "nothing acked yet" must not produce an unacked count
+        let mut telemetry = GgpoTelemetry::default();
+        telemetry.observe_outgoing(&packet(1, 3, &input_body(50, false, -1)));
+        telemetry.observe_incoming(&packet(1, 7, &0x7fff_ffffu32.to_le_bytes()));
+        assert_eq!(telemetry.snapshot().unacked_frames, None);
+        telemetry.observe_incoming(&packet(2, 7, &45u32.to_le_bytes()));
+        assert_eq!(telemetry.snapshot().unacked_frames, Some(5));
+    }
+
+    #[test]
+    fn truncated_or_unknown_packets_are_rejected() {
+        assert!(decode(&[0xef, 0xbe, 1, 0]).is_none());
+        // sync_request needs all 7 body bytes
+        assert!(decode(&packet(1, 1, &[1, 2, 3, 4, 5, 6])).is_none());
+        assert!(decode(&packet(1, 2, &[1, 2, 3])).is_none());
+        // input cut off inside the ack field
+        let body = input_body(10, false, 9);
+        assert!(decode(&packet(1, 3, &body[..CONNECT_STATUS_LEN + 6])).is_none());
+        assert!(decode(&packet(1, 4, &[0, 1, 2, 3])).is_none());
+        assert!(decode(&packet(1, 5, &[1, 2])).is_none());
+        assert!(decode(&packet(1, 7, &[1, 2, 3])).is_none());
+        assert!(decode(&packet(1, 0, &[])).is_none());
+        assert!(decode(&packet(1, 8, &[0; 8])).is_none());
+    }
+}
@@ -0,0 +1,309 @@
+// How an emulator gets started. The backend is a machine setting (on Linux the
+// Windows build usually runs through Wine), working directory and environment
+// come with each launch. Training, mock and proxy launches all go through
+// build_command so they behave the same.
+use crate::scheduling::SchedulingOptions;
+use crate::{resolve_emulator_path, resolve_generic_path};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tauri::{AppHandle, Manager, Runtime, State};
+use tauri_plugin_shell::ShellExt;
+use tokio::process::Command as TokioCommand;
+
+pub const SETTINGS_FILE: &str = "launch_settings.json";
+const DEFAULT_SIDECAR: &str = "emulator";
+const DEFAULT_WINE: &str = "wine";
+// placeholders in wrapper templates
+const EXE: &str = "{exe}";
+const ARGS: &str = "{args}";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case", rename_all_fields = "camelCase")]
+pub enum LaunchBackend {
+    // run the emulator binary itself
+    #[default]
+    Direct,
+    // a binary bundled with the app as a Tauri sidecar
+    Sidecar { name: Option<String> },
+    // `wine <exe> <args>`, optionally with its own WINEPREFIX
+    Wine {
+        binary: Option<String>,
+        prefix: Option<String>,
+    },
+    // argv template, e.g. ["gamemoderun", "{exe}", "{args}"]. {args} must be a
+    // token of its own, {exe} may sit inside one. Without {exe} the emulator
+    // goes right before its arguments, at the end when there is no {args};
+    // without {args} the arguments follow the last {exe}.
+    Wrapper { template: Vec<String> },
+}
+
+// Per-launch settings, part of every launch request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LaunchOptions {
+    // overrides the configured backend for this launch only
+    pub backend: Option<LaunchBackend>,
+    pub working_dir: Option<String>,
+    pub env: BTreeMap<String, String>,
+    // priority and affinity, the purpose's defaults when left out
+    pub scheduling: Option<SchedulingOptions>,
+}
+
+// What the supervisor spawns. `emulator` is the emulator binary even when
+// `cmd` starts Wine or a wrapper, hashes and orphan checks go by it.
+pub struct LaunchCommand {
+    pub cmd: TokioCommand,
+    pub emulator: String,
+    pub scheduling: Option<SchedulingOptions>,
+}
+
+pub struct LaunchSettings {
+    path: Option<PathBuf>,
+    backend: Mutex<LaunchBackend>,
+}
+
+impl LaunchSettings {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let backend = path
+            .as_deref()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            backend: Mutex::new(backend),
+        }
+    }
+
+    pub fn backend(&self) -> LaunchBackend {
+        self.backend.lock().unwrap().clone()
+    }
+
+    pub fn set_backend(&self, backend: LaunchBackend) -> Result<(), String> {
+        if let LaunchBackend::Wrapper { template } = &backend {
+            check_template(template)?;
+        }
+        if let Some(path) = &self.path {
+            let json = serde_json::to_vec_pretty(&backend).map_err(|e| e.to_string())?;
+            fs::write(path, json).map_err(|e| e.to_string())?;
+        }
+        *self.backend.lock().unwrap() = backend;
+        Ok(())
+    }
+}
+
+// Templates expand_template can't honour
+fn check_template(template: &[String]) -> Result<(), String> {
+    if template.first().is_none_or(|program| program.trim().is_empty()) {
+        return Err("Wrapper template needs a program".to_string());
+    }
+    if let Some(token) = template.iter().find(|t| t.contains(ARGS) && *t != ARGS) {
+        return Err(format!(
+            "{ARGS} must be a template token of its own, not part of '{token}'"
+        ));
+    }
+    Ok(())
+}
+
+// argv for the wrapper backend, program first
+fn expand_template(template: &[String], exe: &str, args: &[String]) -> Vec<String> {
+    let mut argv = Vec::new();
+    let mut exe_pending = !template.iter().any(|token| token.contains(EXE));
+    // without {args} the arguments follow the last {exe}
+    let args_after = if template.iter().any(|token| token == ARGS) {
+        None
+    } else {
+        template.iter().rposition(|token| token.contains(EXE))
+    };
+    for (idx, token) in template.iter().enumerate() {
+        if token == ARGS {
+            if std::mem::take(&mut exe_pending) {
+                argv.push(exe.to_string());
+            }
+            argv.extend(args.iter().cloned());
+        } else if token.contains(EXE) {
+            argv.push(token.replace(EXE, exe));
+            if args_after == Some(idx) {
+                argv.extend(args.iter().cloned());
+            }
+        } else {
+            argv.push(token.clone());
+        }
+    }
+    if exe_pending {
+        argv.push(exe.to_string());
+        argv.extend(args.iter().cloned());
+    }
+    argv
+}
+
+// `args` must already have their --lua path resolved
+pub fn build_command<R: Runtime>(
+    app: &AppHandle<R>,
+    exe_path: &str,
+    args: Vec<String>,
+    options: &LaunchOptions,
+) -> Result<LaunchCommand, String> {
+    let backend = match &options.backend {
+        Some(backend) => backend.clone(),
+        None => app.state::<LaunchSettings>().backend(),
+    };
+    let (mut cmd, emulator) = match backend {
+        LaunchBackend::Sidecar { name } => {
+            let name = name.as_deref().unwrap_or(DEFAULT_SIDECAR);
+            let sidecar: std::process::Command =
+                app.shell().sidecar(name).map_err(|e| e.to_string())?.into();
+            let emulator = sidecar.get_program().to_string_lossy().into_owned();
+            let mut cmd = TokioCommand::from(sidecar);
+            cmd.args(args);
+            (cmd, emulator)
+        }
+        LaunchBackend::Direct => {
+            let exe = resolve_emulator_path(app, exe_path)?;
+            let mut cmd = TokioCommand::new(&exe);
+            cmd.args(args);
+            (cmd, exe.to_string_lossy().into_owned())
+        }
+        LaunchBackend::Wine { binary, prefix } => {
+            let exe = resolve_emulator_path(app, exe_path)?;
+            let mut cmd = TokioCommand::new(binary.as_deref().unwrap_or(DEFAULT_WINE));
+            cmd.arg(&exe).args(args);
+            if let Some(prefix) = prefix {
+                cmd.env("WINEPREFIX", resolve_generic_path(app, &prefix)?);
+            }
+            (cmd, exe.to_string_lossy().into_owned())
+        }
+        LaunchBackend::Wrapper { template } => {
+            // a per-launch override never went through set_backend
+            check_template(&template)?;
+            let exe = resolve_emulator_path(app, exe_path)?;
+            let exe = exe.to_string_lossy().into_owned();
+            let argv = expand_template(&template, &exe, &args);
+            let (program, rest) = argv
+                .split_first()
+                .ok_or("Wrapper template needs a program")?;
+            let mut cmd = TokioCommand::new(program);
+            cmd.args(rest);
+            (cmd, exe)
+        }
+    };
+    if let Some(dir) = &options.working_dir {
+        cmd.current_dir(resolve_generic_path(app, dir)?);
+    }
+    cmd.envs(&options.env);
+    Ok(LaunchCommand {
+        cmd,
+        emulator,
+        scheduling: options.scheduling.clone(),
+    })
+}
+
+// The bundled emulator under files/emu for prepare_user_resources. A native
+// build is preferred when launching directly outside Windows, the Windows
+// build otherwise (Wine, wrappers, Windows itself).
+pub fn bundled_emulator(emulator_dir: &Path, backend: &LaunchBackend) -> PathBuf {
+    let dir = emulator_dir.join("hyper-screw-fbneo");
+    let native_first = cfg!(not(windows)) && *backend == LaunchBackend::Direct;
+    let names = if native_first {
+        ["fs-fbneo", "fs-fbneo.exe"]
+    } else {
+        ["fs-fbneo.exe", "fs-fbneo"]
+    };
+    names
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| dir.join(names[0]))
+}
+
+#[tauri::command]
+pub fn get_launch_backend(settings: State<'_, LaunchSettings>) -> LaunchBackend {
+    settings.backend()
+}
+
+#[tauri::command]
+pub fn set_launch_backend(
+    settings: State<'_, LaunchSettings>,
+    backend: LaunchBackend,
+) -> Result<(), String> {
+    settings.set_backend(backend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn wrapper_templates_expand_placeholders() {
+        let args = strings(&["--rom", "sfiii3nr1"]);
+        assert_eq!(
+            expand_template(&strings(&["gamemoderun", "{exe}", "{args}"]), "/emu/fbneo", &args),
+            strings(&["gamemoderun", "/emu/fbneo", "--rom", "sfiii3nr1"])
+        );
+        assert_eq!(
+            expand_template(&strings(&["gamemoderun", "{exe}"]), "/emu/fbneo", &args),
+            strings(&["gamemoderun", "/emu/fbneo", "--rom", "sfiii3nr1"])
+        );
+        // {exe} without {args}: the arguments follow the last {exe}
+        assert_eq!(
+            expand_template(&strings(&["run", "--exe={exe}", "--"]), "/emu/fbneo", &args),
+            strings(&["run", "--exe=/emu/fbneo", "--rom", "sfiii3nr1", "--"])
+        );
+        // no {exe}: emulator and arguments are appended
+        assert_eq!(
+            expand_template(&strings(&["taskset", "-c", "2"]), "/emu/fbneo", &args),
+            strings(&["taskset", "-c", "2", "/emu/fbneo", "--rom", "sfiii3nr1"])
+        );
+        // {args} without {exe}: the emulator still comes before its arguments
+        assert_eq!(
+            expand_template(&strings(&["prime-run", "{args}", "-w"]), "/emu/fbneo", &args),
+            strings(&["prime-run", "/emu/fbneo", "--rom", "sfiii3nr1", "-w"])
+        );
+    }
+
+    #[test]
+    fn templates_with_embedded_args_are_rejected() {
+        let settings = LaunchSettings::load(None);
+        let embedded = LaunchBackend::Wrapper {
+            template: strings(&["wrap", "{exe}", "--x={args}"]),
+        };
+        assert!(settings.set_backend(embedded).is_err());
+        let empty = LaunchBackend::Wrapper {
+            template: strings(&[" "]),
+        };
+        assert!(settings.set_backend(empty).is_err());
+        let fine = LaunchBackend::Wrapper {
+            template: strings(&["wrap", "--exe={exe}", "{args}"]),
+        };
+        assert!(settings.set_backend(fine.clone()).is_ok());
+        assert_eq!(settings.backend(), fine);
+    }
+
+    #[test]
+    fn backend_settings_round_trip_as_tagged_json() {
+        let wine: LaunchBackend = serde_json::from_str(
+            r#"{"kind":"wine","binary":"wine64","prefix":"~/.wine-fbneo"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            wine,
+            LaunchBackend::Wine {
+                binary: Some("wine64".to_string()),
+                prefix: Some("~/.wine-fbneo".to_string()),
+            }
+        );
+        let direct: LaunchBackend = serde_json::from_str(r#"{"kind":"direct"}"#).unwrap();
+        assert_eq!(direct, LaunchBackend::Direct);
+        let options: LaunchOptions = serde_json::from_str("{}").unwrap();
+        assert!(options.backend.is_none() && options.env.is_empty());
+    }
+}
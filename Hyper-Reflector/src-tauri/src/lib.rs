@@ -1,23 +1,61 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 use serde::Serialize;
-use tauri::{AppHandle, Emitter, EventTarget, State, Manager};
+use tauri::{AppHandle, State, Manager, Runtime};
 use std::{
     env,
     fs::{self, OpenOptions},
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
-use tauri_plugin_shell::ShellExt;
 use walkdir::WalkDir;
 
+mod compat;
+mod control;
+mod crash_report;
+mod emulator_output;
+mod emulator_supervisor;
+mod ggpo;
+mod launch;
+mod loopback;
+mod match_analytics;
+mod match_history;
+mod match_stats;
+mod match_watcher;
+mod orphans;
+mod prewarm;
 mod proxy;
-use proxy::{kill_emulator_only, start_proxy, stop_proxy, ProxyManager};
-
-// This saves the child process
-#[derive(Default)]
-struct ProcState {
-    child: Option<tauri_plugin_shell::process::CommandChild>,
-}
+mod proxy_stats;
+mod redundancy;
+mod resource_usage;
+mod rematch;
+mod scheduling;
+mod set_tracker;
+mod shutdown;
+mod training;
+mod tunnel;
+mod upload_queue;
+use emulator_supervisor::{
+    get_emulator_output, get_emulator_usage, kill_orphaned_emulators, list_emulators,
+    list_orphaned_emulators, stop_all_emulators, stop_emulator, EmulatorPurpose,
+    EmulatorSupervisor,
+};
+use launch::{get_launch_backend, set_launch_backend, LaunchOptions, LaunchSettings};
+use loopback::{loopback_status, start_loopback_match, stop_loopback_match, LoopbackManager};
+use match_analytics::{get_match_trends, get_matchup_stats, get_streak_history, MatchAnalytics};
+use match_history::{get_match_record, list_match_history, MatchHistory};
+use match_stats::parse_match_stats;
+use prewarm::{cancel_prewarm, prewarm_proxy, prewarm_status, PrewarmManager};
+use rematch::{request_rematch, set_rematch_opt_in};
+use set_tracker::{get_set_status, record_set_game, start_set};
+use training::{
+    restart_training_mode, start_training_mode, stop_training_mode, training_status,
+    TrainingManager,
+};
+use upload_queue::{configure_uploads, enqueue_match_upload, pending_uploads, UploadQueue};
+use proxy::{
+    get_compat_report, get_proxy_stats, kill_emulator_only, list_proxy_sessions, start_proxy,
+    stop_proxy, ProxyManager,
+};
 
 #[derive(Default)]
 struct AudioState {
@@ -42,7 +80,11 @@ fn set_sink(app: &tauri::AppHandle, new_sink: Option<Arc<rodio::Sink>>) {
     *guard = new_sink; // guard drops here
 }
 
-fn resolve_path_common(app: &AppHandle, raw: &str, empty_msg: &str) -> Result<PathBuf, String> {
+fn resolve_path_common<R: Runtime>(
+    app: &AppHandle<R>,
+    raw: &str,
+    empty_msg: &str,
+) -> Result<PathBuf, String> {
     let trimmed = raw.trim();
     if trimmed.is_empty() {
         return Err(empty_msg.to_string());
@@ -123,15 +165,24 @@ fn resolve_path_common(app: &AppHandle, raw: &str, empty_msg: &str) -> Result<Pa
     Ok(provided)
 }
 
-pub(crate) fn resolve_emulator_path(app: &AppHandle, raw: &str) -> Result<PathBuf, String> {
+pub(crate) fn resolve_emulator_path<R: Runtime>(
+    app: &AppHandle<R>,
+    raw: &str,
+) -> Result<PathBuf, String> {
     resolve_path_common(app, raw, "Emulator path is empty")
 }
 
-fn resolve_generic_path(app: &AppHandle, raw: &str) -> Result<PathBuf, String> {
+pub(crate) fn resolve_generic_path<R: Runtime>(
+    app: &AppHandle<R>,
+    raw: &str,
+) -> Result<PathBuf, String> {
     resolve_path_common(app, raw, "Path is empty")
 }
 
-pub(crate) fn resolve_lua_args(app: &AppHandle, args: &mut Vec<String>) -> Result<(), String> {
+pub(crate) fn resolve_lua_args<R: Runtime>(
+    app: &AppHandle<R>,
+    args: &mut Vec<String>,
+) -> Result<(), String> {
     let mut idx = 0;
     while idx < args.len() {
         if args[idx].eq_ignore_ascii_case("--lua") {
@@ -258,7 +309,7 @@ fn test_writable(dir: &Path) -> bool {
     }
 }
 
-fn ensure_writable_files_dir(app: &AppHandle) -> Result<PathBuf, String> {
+pub(crate) fn ensure_writable_files_dir(app: &AppHandle) -> Result<PathBuf, String> {
     if let Ok(exe_dir) = app.path().executable_dir() {
         let exe_files = exe_dir.join("files");
         if exe_files.exists() && test_writable(&exe_files) {
@@ -306,9 +357,8 @@ async fn prepare_user_resources(app: tauri::AppHandle) -> Result<PreparedResourc
     let lua_dir = require_subdir(&files_dir, "lua")?;
     let sounds_dir = require_subdir(&files_dir, "sounds")?;
 
-    let emulator_path = emulator_dir
-        .join("hyper-screw-fbneo")
-        .join("fs-fbneo.exe");
+    let backend = app.state::<LaunchSettings>().backend();
+    let emulator_path = launch::bundled_emulator(&emulator_dir, &backend);
 
     Ok(PreparedResources {
         emulator_path: emulator_path.to_string_lossy().to_string(),
@@ -387,65 +437,20 @@ fn play_sound(app: tauri::AppHandle, path: String) -> Result<(), String> {
 }
 
 #[tauri::command]
-async fn start_training_mode(
+async fn launch_emulator(
     app: tauri::AppHandle,
-    proc: State<'_, Arc<Mutex<ProcState>>>,
-    use_sidecar: bool,
-    exe_path: Option<String>,
+    supervisor: State<'_, EmulatorSupervisor>,
+    exe_path: String,
     mut args: Vec<String>,
-) -> Result<(), String> {
-    let cmd_builder = if use_sidecar {
-        app.shell().sidecar("emulator").map_err(|e| e.to_string())?
-    } else {
-        // need to set up sidecar code later
-        let path = exe_path.ok_or("exe_path required when use_sidecar=false")?;
-        let resolved = resolve_emulator_path(&app, &path)?;
-        app.shell().command(resolved)
-    };
+    purpose: Option<EmulatorPurpose>,
+    launch: Option<LaunchOptions>,
+) -> Result<u64, String> {
     resolve_lua_args(&app, &mut args)?;
-    let cmd = cmd_builder.args(args);
-    let (mut rx, child) = cmd.spawn().map_err(|e| e.to_string())?;
-    {
-        let mut guard = proc.lock().unwrap();
-        guard.child = Some(child);
-    }
-
-    tauri::async_runtime::spawn({
-        let app = app.clone();
-        async move {
-            use tauri_plugin_shell::process::CommandEvent;
-            // while let Some(event) = rx.recv().await {
-            //     match event {
-            //         CommandEvent::Stdout(bytes) => {
-            //             let line = String::from_utf8_lossy(&bytes).to_string();
-            //             let _ = app.emit("emulator:stdout", line);
-            //         }
-            //         CommandEvent::Stderr(bytes) => {
-            //             let line = String::from_utf8_lossy(&bytes).to_string();
-            //             let _ = app.emit("emulator:stderr", line);
-            //         }
-            //         CommandEvent::Terminated(payload) => {
-            //             let _ = app.emit("emulator:exit", payload);
-            //         }
-            //         _ => {}
-            //     }
-            // }
-        }
-    });
-
-    Ok(())
-}
-
-#[tauri::command]
-async fn launch_emulator(app: tauri::AppHandle, exe_path: String, mut args: Vec<String>) -> Result<(), String> {
-    let resolved = resolve_emulator_path(&app, &exe_path)?;
-    resolve_lua_args(&app, &mut args)?;
-    let command = app
-        .shell()
-        .command(resolved)
-        .args(args);
-    let (_rx, _child) = command.spawn().map_err(|e| e.to_string())?;
-    Ok(())
+    let command = launch::build_command(&app, &exe_path, args, &launch.unwrap_or_default())?;
+    // the frontend only launches bare emulators for mock matches
+    let purpose = purpose.unwrap_or(EmulatorPurpose::Mock);
+    let handle = supervisor.spawn(&app, purpose, None, command)?;
+    Ok(handle.id)
 }
 
 //Just for testing
@@ -473,25 +478,111 @@ pub fn run() {
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_http::init())
         .plugin(tauri_plugin_opener::init())
-        .manage(Arc::new(Mutex::new(ProcState::default())))
+        .manage(EmulatorSupervisor::new())
         .manage(AudioState::default())
-        .manage(ProxyManager::new())
+        .manage(ProxyManager::<tauri::Wry>::new())
+        .manage(PrewarmManager::new())
+        .manage(LoopbackManager::new())
+        .manage(TrainingManager::new())
+        .manage(MatchAnalytics::new())
+        .setup(|app| {
+            let db_path = app
+                .path()
+                .app_data_dir()
+                .map_err(|e| e.to_string())
+                .and_then(|dir| {
+                    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+                    Ok(dir.join(match_history::DB_FILE))
+                });
+            let launch_settings = db_path
+                .as_ref()
+                .ok()
+                .map(|path| path.with_file_name(launch::SETTINGS_FILE));
+            app.manage(LaunchSettings::load(launch_settings));
+            if let Ok(path) = &db_path {
+                let registry = path.with_file_name(orphans::REGISTRY_FILE);
+                let orphans = app.state::<EmulatorSupervisor>().load_registry(registry);
+                if !orphans.is_empty() {
+                    eprintln!("{} emulator(s) left running by a previous session", orphans.len());
+                }
+            }
+            let history = db_path
+                .clone()
+                .and_then(|path| MatchHistory::open(&path).map_err(|e| e.to_string()))
+                .or_else(|e| {
+                    eprintln!("match history: falling back to memory: {e}");
+                    MatchHistory::in_memory()
+                })?;
+            app.manage(history);
+            let uploads = db_path
+                .and_then(|path| UploadQueue::open(&path).map_err(|e| e.to_string()))
+                .or_else(|e| {
+                    eprintln!("upload queue: falling back to memory: {e}");
+                    UploadQueue::in_memory(Default::default())
+                })?;
+            let uploads = Arc::new(uploads);
+            upload_queue::spawn_worker(app.handle().clone(), Arc::clone(&uploads));
+            app.manage(uploads);
+            // the Lua match files live in the writable files dir, no point
+            // watching anything if it could not be prepared
+            match ensure_writable_files_dir(app.handle()) {
+                Ok(dir) => match_watcher::spawn(app.handle().clone(), dir),
+                Err(e) => eprintln!("match watcher disabled: {e}"),
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             play_sound,
             stop_sound,
             greet,
             start_training_mode,
+            stop_training_mode,
+            training_status,
+            restart_training_mode,
             launch_emulator,
             run_custom_process,
             start_proxy,
             stop_proxy,
+            list_proxy_sessions,
+            set_rematch_opt_in,
+            request_rematch,
+            start_set,
+            record_set_game,
+            get_set_status,
+            get_proxy_stats,
+            get_compat_report,
             kill_emulator_only,
+            list_emulators,
+            get_emulator_output,
+            get_emulator_usage,
+            stop_emulator,
+            stop_all_emulators,
+            list_orphaned_emulators,
+            kill_orphaned_emulators,
+            get_launch_backend,
+            set_launch_backend,
+            prewarm_proxy,
+            prewarm_status,
+            cancel_prewarm,
+            start_loopback_match,
+            loopback_status,
+            stop_loopback_match,
+            parse_match_stats,
+            list_match_history,
+            get_match_record,
+            get_matchup_stats,
+            get_streak_history,
+            get_match_trends,
+            configure_uploads,
+            enqueue_match_upload,
+            pending_uploads,
             prepare_user_resources,
             read_files_text,
             write_files_text
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(shutdown::on_event);
 }
 
 fn resolve_files_path(app: &AppHandle, relative: &str) -> Result<PathBuf, String> {
@@ -0,0 +1,413 @@
+// Mock match that goes through the real proxy path. Two ProxyRuntimes punch
+// through an in-process stand-in for the rendezvous server on 127.0.0.1 and
+// each one launches its own emulator, so punch, relay and teardown can all be
+// exercised on one machine.
+use crate::emulator_supervisor::EmulatorPurpose;
+use crate::launch::LaunchOptions;
+use crate::proxy::{
+    spawn_session, OpponentEnvelope, PeerEndpoint, ProxyManager, PunchMessage, StartArgs,
+};
+use crate::resolve_emulator_path;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tauri::{AppHandle, Emitter, EventTarget, Runtime};
+use tokio::{net::UdpSocket, sync::Mutex, task::JoinHandle};
+
+static NEXT_LOOPBACK: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoopbackSide {
+    pub user_name: String,
+    // exact CLI args, the emulator must use game_port locally and send to listen_port
+    #[serde(default)]
+    pub emulator_args: Vec<String>,
+    pub game_port: u16,
+    pub listen_port: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoopbackArgs {
+    pub emulator_path: String,
+    pub delay: u16,
+    pub game_name: Option<String>,
+    // player 1 first; defaults to ports 7000/7001 and 7002/7003
+    pub p1: Option<LoopbackSide>,
+    pub p2: Option<LoopbackSide>,
+    #[serde(default)]
+    pub launch: LaunchOptions,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoopbackInfo {
+    pub id: String,
+    pub server: String,
+    pub session_ids: Vec<String>,
+}
+
+struct Loopback {
+    id: String,
+    server: SocketAddr,
+    session_ids: Vec<String>,
+    task: JoinHandle<()>,
+}
+
+pub struct LoopbackManager {
+    inner: Mutex<Option<Loopback>>,
+}
+
+impl LoopbackManager {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(None),
+        }
+    }
+}
+
+// Minimal rendezvous server: remembers where each uid punched from and, once
+// both sides of a pair have checked in, tells each one about the other.
+async fn spawn_rendezvous(match_id: String) -> anyhow::Result<(SocketAddr, JoinHandle<()>)> {
+    let sock = UdpSocket::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).await?;
+    let addr = sock.local_addr()?;
+    let task = tokio::spawn(async move {
+        let mut seen: HashMap<String, SocketAddr> = HashMap::new();
+        let mut buf = vec![0u8; 2048];
+        while let Ok((n, from)) = sock.recv_from(&mut buf).await {
+            let Ok(msg) = serde_json::from_slice::<PunchMessage>(&buf[..n]) else {
+                continue;
+            };
+            if msg.kill {
+                seen.remove(&msg.uid);
+                continue;
+            }
+            seen.insert(msg.uid.clone(), from);
+            let Some(&peer) = seen.get(&msg.peer_uid) else {
+                continue;
+            };
+            for (to, other) in [(from, peer), (peer, from)] {
+                let envelope = OpponentEnvelope {
+                    match_id: Some(match_id.clone()),
+                    peer: PeerEndpoint {
+                        address: other.ip().to_string(),
+                        port: other.port(),
+                    },
+                };
+                if let Ok(bytes) = serde_json::to_vec(&envelope) {
+                    let _ = sock.send_to(&bytes, to).await;
+                }
+            }
+        }
+    });
+    Ok((addr, task))
+}
+
+fn default_side(player: u8) -> LoopbackSide {
+    let base = 7000 + (u16::from(player) - 1) * 2;
+    LoopbackSide {
+        user_name: format!("Player {player}"),
+        emulator_args: Vec::new(),
+        game_port: base,
+        listen_port: base + 1,
+    }
+}
+
+pub(crate) async fn stop_loopback<R: Runtime>(
+    state: &LoopbackManager,
+    proxies: &ProxyManager<R>,
+) -> Result<(), String> {
+    let Some(loopback) = state.inner.lock().await.take() else {
+        return Ok(());
+    };
+    let mut result = Ok(());
+    for session_id in &loopback.session_ids {
+        if let Some(rt) = proxies.remove(session_id).await {
+            if let Err(e) = rt.stop().await {
+                result = Err(e.to_string());
+            }
+        }
+    }
+    loopback.task.abort();
+    result
+}
+
+async fn start_loopback<R: Runtime>(
+    app: &AppHandle<R>,
+    state: &LoopbackManager,
+    proxies: &ProxyManager<R>,
+    args: LoopbackArgs,
+) -> Result<LoopbackInfo, String> {
+    // only one loopback at a time, they'd fight over the emulator ports anyway
+    stop_loopback(state, proxies).await?;
+
+    let emulator_path = resolve_emulator_path(app, &args.emulator_path)?
+        .to_string_lossy()
+        .to_string();
+    let id = format!("loopback-{}", NEXT_LOOPBACK.fetch_add(1, Ordering::Relaxed));
+    let (server, task) = spawn_rendezvous(id.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let sides = [
+        args.p1.clone().unwrap_or_else(|| default_side(1)),
+        args.p2.clone().unwrap_or_else(|| default_side(2)),
+    ];
+    let mut session_ids: Vec<String> = Vec::new();
+    for (idx, side) in sides.into_iter().enumerate() {
+        let player = idx as u8 + 1;
+        let session_id = format!("{id}-p{player}");
+        let start = StartArgs {
+            my_uid: format!("{id}-uid-{player}"),
+            peer_uid: format!("{id}-uid-{}", 3 - player),
+            server_host: server.ip().to_string(),
+            server_port: server.port(),
+            match_id: None,
+            emulator_path: emulator_path.clone(),
+            player,
+            delay: args.delay,
+            user_name: side.user_name,
+            game_name: args.game_name.clone(),
+            emulator_game_port: Some(side.game_port),
+            emulator_listen_port: Some(side.listen_port),
+            emulator_args: side.emulator_args,
+            inspect_ggpo: true,
+            redundancy: None,
+            tunnel: None,
+            on_existing: Default::default(),
+            allow_rematch: false,
+            set_format: None,
+            compat_policy: Default::default(),
+            emulator_purpose: EmulatorPurpose::Mock,
+            emulator_shutdown: Default::default(),
+            launch: args.launch.clone(),
+        };
+        if let Err(e) = spawn_session(app, proxies, session_id.clone(), start, None).await {
+            for started in &session_ids {
+                if let Some(rt) = proxies.remove(started).await {
+                    let _ = rt.stop().await;
+                }
+            }
+            task.abort();
+            return Err(e);
+        }
+        session_ids.push(session_id);
+    }
+
+    let _ = app.emit_to(
+        EventTarget::any(),
+        "proxy-log",
+        format!("{id}: rendezvous on {server}, sessions {}", session_ids.join(", ")),
+    );
+    *state.inner.lock().await = Some(Loopback {
+        id: id.clone(),
+        server,
+        session_ids: session_ids.clone(),
+        task,
+    });
+    Ok(LoopbackInfo {
+        id,
+        server: server.to_string(),
+        session_ids,
+    })
+}
+
+#[tauri::command]
+pub async fn start_loopback_match(
+    app: AppHandle,
+    state: tauri::State<'_, LoopbackManager>,
+    proxies: tauri::State<'_, ProxyManager>,
+    args: LoopbackArgs,
+) -> Result<LoopbackInfo, String> {
+    start_loopback(&app, &state, &proxies, args).await
+}
+
+#[tauri::command]
+pub async fn loopback_status(
+    state: tauri::State<'_, LoopbackManager>,
+) -> Result<Option<LoopbackInfo>, String> {
+    Ok(state.inner.lock().await.as_ref().map(|loopback| LoopbackInfo {
+        id: loopback.id.clone(),
+        server: loopback.server.to_string(),
+        session_ids: loopback.session_ids.clone(),
+    }))
+}
+
+#[tauri::command]
+pub async fn stop_loopback_match(
+    state: tauri::State<'_, LoopbackManager>,
+    proxies: tauri::State<'_, ProxyManager>,
+) -> Result<(), String> {
+    stop_loopback(&state, &proxies).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::{self, ControlMessage};
+    use crate::emulator_supervisor::EmulatorSupervisor;
+    use crate::launch::LaunchBackend;
+    use std::time::{Duration, Instant};
+    use tauri::{test::MockRuntime, Manager};
+
+    async fn bind() -> UdpSocket {
+        UdpSocket::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).await.unwrap()
+    }
+
+    async fn punch(sock: &UdpSocket, server: SocketAddr, uid: &str, peer_uid: &str, kill: bool) {
+        let msg = PunchMessage {
+            uid: uid.to_string(),
+            peer_uid: peer_uid.to_string(),
+            kill,
+        };
+        sock.send_to(&serde_json::to_vec(&msg).unwrap(), server).await.unwrap();
+    }
+
+    async fn recv(sock: &UdpSocket) -> (Vec<u8>, SocketAddr) {
+        let mut buf = vec![0u8; 2048];
+        let (n, from) = tokio::time::timeout(Duration::from_secs(2), sock.recv_from(&mut buf))
+            .await
+            .expect("nothing arrived")
+            .unwrap();
+        buf.truncate(n);
+        (buf, from)
+    }
+
+    async fn envelope(sock: &UdpSocket) -> OpponentEnvelope {
+        let (bytes, _) = recv(sock).await;
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    fn endpoint(sock: &UdpSocket) -> (String, u16) {
+        let addr = sock.local_addr().unwrap();
+        (addr.ip().to_string(), addr.port())
+    }
+
+    #[tokio::test]
+    async fn sides_punch_through_the_rendezvous_and_talk_directly() {
+        let (server, task) = spawn_rendezvous("loopback-test".to_string()).await.unwrap();
+        let p1 = bind().await;
+        let p2 = bind().await;
+
+        // the first punch has nobody to pair with yet
+        punch(&p1, server, "uid-1", "uid-2", false).await;
+        punch(&p2, server, "uid-2", "uid-1", false).await;
+        let at_p1 = envelope(&p1).await;
+        let at_p2 = envelope(&p2).await;
+        assert_eq!(at_p1.match_id.as_deref(), Some("loopback-test"));
+        assert_eq!((at_p1.peer.address, at_p1.peer.port), endpoint(&p2));
+        assert_eq!((at_p2.peer.address, at_p2.peer.port), endpoint(&p1));
+
+        // what the proxies do next: ping, then the clock handshake, straight
+        // to the address from the envelope
+        let p2_addr = p2.local_addr().unwrap();
+        p1.send_to(b"ping", p2_addr).await.unwrap();
+        let (bytes, from) = recv(&p2).await;
+        assert_eq!((bytes.as_slice(), from), (b"ping".as_slice(), p1.local_addr().unwrap()));
+
+        let ping = ControlMessage::ClockPing { sent_at: 1_000 };
+        p2.send_to(&control::encode(&ping), from).await.unwrap();
+        let (bytes, _) = recv(&p1).await;
+        let Some(ControlMessage::ClockPing { sent_at }) = control::decode(&bytes) else {
+            panic!("expected a clock ping");
+        };
+        let pong = ControlMessage::ClockPong {
+            sent_at,
+            received_at: 1_010,
+        };
+        p1.send_to(&control::encode(&pong), p2_addr).await.unwrap();
+        let (bytes, _) = recv(&p2).await;
+        assert!(matches!(
+            control::decode(&bytes),
+            Some(ControlMessage::ClockPong { sent_at: 1_000, received_at: 1_010 })
+        ));
+
+        // a side that drops out and comes back on a new socket is re-paired
+        punch(&p2, server, "uid-2", "uid-1", true).await;
+        let p2_again = bind().await;
+        punch(&p2_again, server, "uid-2", "uid-1", false).await;
+        let at_p1 = envelope(&p1).await;
+        assert_eq!((at_p1.peer.address, at_p1.peer.port), endpoint(&p2_again));
+        task.abort();
+    }
+
+    // Stand-in emulator: ignores the netplay arguments and idles until stopped
+    #[cfg(unix)]
+    fn stub_emulator(dir: &std::path::Path) -> String {
+        use std::os::unix::fs::PermissionsExt;
+        let path = dir.join("stub-fbneo");
+        std::fs::write(&path, "#!/bin/sh\nexec sleep 30\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn loopback_runs_both_sessions_through_the_proxy() {
+        let dir = std::env::temp_dir().join(format!("hr-loopback-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut context = tauri::test::mock_context(tauri::test::noop_assets());
+        // emulator logs land in the app data dir, removed again below
+        context.config_mut().identifier = format!("hr-loopback-test-{}", std::process::id());
+        let app = tauri::test::mock_builder().build(context).unwrap();
+        app.manage(EmulatorSupervisor::new());
+        let app = app.handle().clone();
+        let (state, proxies) = (LoopbackManager::new(), ProxyManager::<MockRuntime>::new());
+
+        let side = |player: u8| LoopbackSide {
+            user_name: format!("Player {player}"),
+            emulator_args: vec!["--stub".to_string()],
+            game_port: 0,
+            listen_port: 0,
+        };
+        let args = LoopbackArgs {
+            emulator_path: stub_emulator(&dir),
+            delay: 0,
+            game_name: None,
+            p1: Some(side(1)),
+            p2: Some(side(2)),
+            launch: LaunchOptions {
+                backend: Some(LaunchBackend::Direct),
+                ..Default::default()
+            },
+        };
+        let info = start_loopback(&app, &state, &proxies, args).await.unwrap();
+        assert_eq!(info.session_ids.len(), 2);
+
+        // both sides punch through the rendezvous, agree on a start and launch
+        let deadline = Instant::now() + Duration::from_secs(15);
+        let sessions = loop {
+            let mut sessions = Vec::new();
+            for id in &info.session_ids {
+                sessions.push(proxies.get(id).await.unwrap().info().await);
+            }
+            if sessions.iter().all(|s| s.emulator_running) {
+                break sessions;
+            }
+            assert!(Instant::now() < deadline, "emulators never launched: {sessions:?}");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        };
+        // each one talks to the other's punch socket
+        let port = |addr: &Option<String>| {
+            addr.as_deref()
+                .map(|a| a.parse::<SocketAddr>().unwrap().port())
+        };
+        assert_eq!(port(&sessions[0].peer), port(&sessions[1].local_addr));
+        assert_eq!(port(&sessions[1].peer), port(&sessions[0].local_addr));
+        let supervisor = app.state::<EmulatorSupervisor>();
+        let mut launched: Vec<_> =
+            supervisor.list().into_iter().filter_map(|e| e.session_id).collect();
+        launched.sort();
+        assert_eq!(launched, info.session_ids);
+
+        stop_loopback(&state, &proxies).await.unwrap();
+        for id in &info.session_ids {
+            assert!(proxies.get(id).await.is_none());
+        }
+        assert!(supervisor.list().is_empty());
+        let _ = std::fs::remove_dir_all(app.path().app_data_dir().unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,416 @@
+// Matchup analytics over the local match history. Aggregates are kept in
+// memory and only the rows recorded since the last refresh are folded in, so
+// asking again after a match costs one small query.
+use crate::match_history::{MatchHistory, MatchRecord};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+use tauri::State;
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+// 1970-01-01 was a Thursday, weeks here start on Monday
+const WEEK_OFFSET_MS: u64 = 3 * DAY_MS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchupKey {
+    pub own_char: u32,
+    pub own_super: u32,
+    pub opponent_char: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Tally {
+    games: u32,
+    wins: u32,
+    meter: f64,
+    opponent_meter: f64,
+}
+
+impl Tally {
+    fn add(&mut self, won: bool, meter: f64, opponent_meter: f64) {
+        self.games += 1;
+        self.wins += u32::from(won);
+        self.meter += meter;
+        self.opponent_meter += opponent_meter;
+    }
+
+    fn win_rate(&self) -> f64 {
+        if self.games == 0 {
+            0.0
+        } else {
+            self.wins as f64 / self.games as f64
+        }
+    }
+
+    fn avg(&self, total: f64) -> f64 {
+        if self.games == 0 {
+            0.0
+        } else {
+            total / self.games as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchupStats {
+    #[serde(flatten)]
+    pub key: MatchupKey,
+    pub games: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub win_rate: f64,
+    pub avg_meter_built: f64,
+    pub avg_opponent_meter_built: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Streak {
+    pub length: u32,
+    pub started_at: u64,
+    pub ended_at: u64,
+    // still running
+    pub current: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreakHistory {
+    pub current: u32,
+    pub best: u32,
+    // finished streaks of two wins or more, newest first
+    pub streaks: Vec<Streak>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrendBucket {
+    #[default]
+    Day,
+    Week,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrendPoint {
+    pub period_start: u64,
+    pub games: u32,
+    pub wins: u32,
+    pub win_rate: f64,
+    pub avg_meter_built: f64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MatchupFilter {
+    pub own_char: Option<u32>,
+    pub opponent_char: Option<u32>,
+    // leave out matchups with fewer games than this
+    pub min_games: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OpenStreak {
+    length: u32,
+    started_at: u64,
+    ended_at: u64,
+}
+
+#[derive(Default)]
+struct AnalyticsCache {
+    last_id: i64,
+    matchups: HashMap<MatchupKey, Tally>,
+    // keyed by UTC day start
+    days: BTreeMap<u64, Tally>,
+    streak: Option<OpenStreak>,
+    finished: Vec<Streak>,
+    best: u32,
+}
+
+// Total meter for one side out of the condensed payload stored with the record
+fn total_meter(stats: &Value, slot: u8) -> f64 {
+    stats["participants"][format!("player{slot}")]["totalMeter"]
+        .as_f64()
+        .unwrap_or(0.0)
+}
+
+impl AnalyticsCache {
+    // Matches recorded without a known slot have no side and are skipped
+    fn fold(&mut self, record: &MatchRecord) {
+        self.last_id = self.last_id.max(record.id);
+        let (
+            Some(own_slot @ (1 | 2)),
+            Some(won),
+            Some(own_char),
+            Some(own_super),
+            Some(opponent_char),
+        ) = (
+            record.player_slot,
+            record.won,
+            record.own_char,
+            record.own_super,
+            record.opponent_char,
+        )
+        else {
+            return;
+        };
+        let meter = total_meter(&record.stats, own_slot);
+        let opponent_meter = total_meter(&record.stats, 3 - own_slot);
+        let key = MatchupKey {
+            own_char,
+            own_super,
+            opponent_char,
+        };
+        self.matchups
+            .entry(key)
+            .or_default()
+            .add(won, meter, opponent_meter);
+        let day = record.played_at - record.played_at % DAY_MS;
+        self.days
+            .entry(day)
+            .or_default()
+            .add(won, meter, opponent_meter);
+
+        if won {
+            let open = self.streak.get_or_insert(OpenStreak {
+                length: 0,
+                started_at: record.played_at,
+                ended_at: record.played_at,
+            });
+            open.length += 1;
+            open.ended_at = record.played_at;
+            self.best = self.best.max(open.length);
+        } else if let Some(open) = self.streak.take() {
+            if open.length >= 2 {
+                self.finished.push(Streak {
+                    length: open.length,
+                    started_at: open.started_at,
+                    ended_at: open.ended_at,
+                    current: false,
+                });
+            }
+        }
+    }
+
+    fn matchups(&self, filter: &MatchupFilter) -> Vec<MatchupStats> {
+        let mut out: Vec<MatchupStats> = self
+            .matchups
+            .iter()
+            .filter(|(key, tally)| {
+                filter.own_char.is_none_or(|c| c == key.own_char)
+                    && filter.opponent_char.is_none_or(|c| c == key.opponent_char)
+                    && tally.games >= filter.min_games.unwrap_or(0)
+            })
+            .map(|(key, tally)| MatchupStats {
+                key: *key,
+                games: tally.games,
+                wins: tally.wins,
+                losses: tally.games - tally.wins,
+                win_rate: tally.win_rate(),
+                avg_meter_built: tally.avg(tally.meter),
+                avg_opponent_meter_built: tally.avg(tally.opponent_meter),
+            })
+            .collect();
+        out.sort_by(|a, b| b.games.cmp(&a.games).then(a.key.cmp(&b.key)));
+        out
+    }
+
+    fn streaks(&self) -> StreakHistory {
+        let mut streaks: Vec<Streak> = self.finished.iter().rev().cloned().collect();
+        if let Some(open) = self.streak {
+            streaks.insert(
+                0,
+                Streak {
+                    length: open.length,
+                    started_at: open.started_at,
+                    ended_at: open.ended_at,
+                    current: true,
+                },
+            );
+        }
+        StreakHistory {
+            current: self.streak.map(|s| s.length).unwrap_or(0),
+            best: self.best,
+            streaks,
+        }
+    }
+
+    fn trends(&self, bucket: TrendBucket) -> Vec<TrendPoint> {
+        let mut periods: BTreeMap<u64, Tally> = BTreeMap::new();
+        for (day, tally) in &self.days {
+            let start = match bucket {
+                TrendBucket::Day => *day,
+                TrendBucket::Week => {
+                    let shifted = day + WEEK_OFFSET_MS;
+                    shifted - shifted % (7 * DAY_MS) - WEEK_OFFSET_MS
+                }
+            };
+            let period = periods.entry(start).or_default();
+            period.games += tally.games;
+            period.wins += tally.wins;
+            period.meter += tally.meter;
+        }
+        periods
+            .into_iter()
+            .map(|(period_start, tally)| TrendPoint {
+                period_start,
+                games: tally.games,
+                wins: tally.wins,
+                win_rate: tally.win_rate(),
+                avg_meter_built: tally.avg(tally.meter),
+            })
+            .collect()
+    }
+}
+
+pub struct MatchAnalytics {
+    cache: Mutex<AnalyticsCache>,
+}
+
+impl MatchAnalytics {
+    pub fn new() -> Self {
+        Self {
+            cache: Mutex::new(AnalyticsCache::default()),
+        }
+    }
+
+    // Folds in whatever was recorded since the last call
+    fn refresh(&self, history: &MatchHistory) -> Result<(), String> {
+        let mut cache = self.cache.lock().unwrap();
+        let fresh = history
+            .recorded_after(cache.last_id)
+            .map_err(|e| e.to_string())?;
+        for record in &fresh {
+            cache.fold(record);
+        }
+        Ok(())
+    }
+
+    pub fn matchups(
+        &self,
+        history: &MatchHistory,
+        filter: &MatchupFilter,
+    ) -> Result<Vec<MatchupStats>, String> {
+        self.refresh(history)?;
+        Ok(self.cache.lock().unwrap().matchups(filter))
+    }
+
+    pub fn streaks(&self, history: &MatchHistory) -> Result<StreakHistory, String> {
+        self.refresh(history)?;
+        Ok(self.cache.lock().unwrap().streaks())
+    }
+
+    pub fn trends(
+        &self,
+        history: &MatchHistory,
+        bucket: TrendBucket,
+    ) -> Result<Vec<TrendPoint>, String> {
+        self.refresh(history)?;
+        Ok(self.cache.lock().unwrap().trends(bucket))
+    }
+}
+
+#[tauri::command]
+pub fn get_matchup_stats(
+    analytics: State<'_, MatchAnalytics>,
+    history: State<'_, MatchHistory>,
+    filter: Option<MatchupFilter>,
+) -> Result<Vec<MatchupStats>, String> {
+    analytics.matchups(&history, &filter.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn get_streak_history(
+    analytics: State<'_, MatchAnalytics>,
+    history: State<'_, MatchHistory>,
+) -> Result<StreakHistory, String> {
+    analytics.streaks(&history)
+}
+
+#[tauri::command]
+pub fn get_match_trends(
+    analytics: State<'_, MatchAnalytics>,
+    history: State<'_, MatchHistory>,
+    bucket: Option<TrendBucket>,
+) -> Result<Vec<TrendPoint>, String> {
+    analytics.trends(&history, bucket.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::match_history::MatchContext;
+    use crate::match_stats::MatchStats;
+
+    const LEGACY: &str = include_str!("../tests/fixtures/match_stats/legacy_p1_win.txt");
+    const JSON: &str = include_str!("../tests/fixtures/match_stats/json_p2_win.txt");
+
+    // Records the fixture under a fresh uuid and timestamp so one file can
+    // stand in for many matches
+    fn record(history: &MatchHistory, raw: &str, uuid: &str, created_at: u64) {
+        let mut stats = MatchStats::parse(raw).unwrap();
+        stats.match_uuid = uuid.to_string();
+        stats.created_at = Some(created_at);
+        let context = MatchContext {
+            player_slot: Some(1),
+            ..Default::default()
+        };
+        history
+            .record(&stats, &stats.condensed(0), &context, 0)
+            .unwrap();
+    }
+
+    #[test]
+    fn matchups_and_streaks_update_incrementally() {
+        let history = MatchHistory::in_memory().unwrap();
+        let analytics = MatchAnalytics::new();
+        // we are player 1: LEGACY is a win as Ken vs Chun-Li, JSON a loss as Ryu vs Gouki
+        record(&history, LEGACY, "a", 1_760_000_000);
+        record(&history, LEGACY, "b", 1_760_000_100);
+
+        let streaks = analytics.streaks(&history).unwrap();
+        assert_eq!((streaks.current, streaks.best), (2, 2));
+
+        record(&history, JSON, "c", 1_760_000_200);
+        record(&history, LEGACY, "d", 1_760_100_000);
+
+        let matchups = analytics
+            .matchups(&history, &MatchupFilter::default())
+            .unwrap();
+        assert_eq!(matchups.len(), 2);
+        let ken = &matchups[0];
+        assert_eq!((ken.key.own_char, ken.key.opponent_char), (11, 16));
+        assert_eq!((ken.games, ken.wins), (3, 3));
+        assert_eq!(ken.avg_meter_built, 44.0);
+        assert_eq!(matchups[1].win_rate, 0.0);
+
+        let streaks = analytics.streaks(&history).unwrap();
+        assert_eq!((streaks.current, streaks.best), (1, 2));
+        assert_eq!(streaks.streaks.len(), 2);
+        assert!(streaks.streaks[0].current);
+        assert_eq!(streaks.streaks[1].length, 2);
+    }
+
+    #[test]
+    fn trends_bucket_by_day_and_week() {
+        let history = MatchHistory::in_memory().unwrap();
+        let analytics = MatchAnalytics::new();
+        // Mon 2025-10-13, Tue 2025-10-14 and Mon 2025-10-20 (UTC)
+        record(&history, LEGACY, "a", 1_760_320_800);
+        record(&history, JSON, "b", 1_760_407_200);
+        record(&history, LEGACY, "c", 1_760_925_600);
+
+        let days = analytics.trends(&history, TrendBucket::Day).unwrap();
+        assert_eq!(days.len(), 3);
+        let weeks = analytics.trends(&history, TrendBucket::Week).unwrap();
+        assert_eq!(weeks.len(), 2);
+        assert_eq!(weeks[0].period_start, 1_760_313_600_000);
+        assert_eq!((weeks[0].games, weeks[0].wins), (2, 1));
+        assert_eq!(weeks[0].win_rate, 0.5);
+    }
+}
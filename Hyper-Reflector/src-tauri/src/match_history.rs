@@ -0,0 +1,433 @@
+// Local record of every match the watcher parsed, kept in SQLite under the app
+// data dir so results survive failed uploads and can be browsed offline.
+use crate::match_stats::{MatchStats, Winner};
+use rusqlite::{params, params_from_iter, types::Value as SqlValue, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{path::Path, sync::Mutex};
+
+pub const DB_FILE: &str = "match_history.sqlite3";
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS matches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    match_uuid TEXT NOT NULL UNIQUE,
+    match_id TEXT,
+    session_id TEXT,
+    opponent_uid TEXT,
+    player_slot INTEGER,
+    delay INTEGER,
+    rtt_ms INTEGER,
+    rtt_avg_ms REAL,
+    loss_ratio REAL,
+    -- our side, NULL when the slot is unknown
+    own_char INTEGER,
+    opponent_char INTEGER,
+    own_super INTEGER,
+    opponent_super INTEGER,
+    won INTEGER,
+    played_at INTEGER NOT NULL,
+    recorded_at INTEGER NOT NULL,
+    stats TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS matches_played_at ON matches (played_at);
+CREATE INDEX IF NOT EXISTS matches_opponent ON matches (opponent_uid);
+";
+
+// What the proxy knew about the match when the stats came in. Everything is
+// optional since training mode and offline play run without a session.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchContext {
+    pub session_id: Option<String>,
+    pub match_id: Option<String>,
+    pub opponent_uid: Option<String>,
+    // 1 or 2
+    pub player_slot: Option<u8>,
+    pub delay: Option<u16>,
+    pub rtt_ms: Option<u64>,
+    pub rtt_avg_ms: Option<f64>,
+    pub loss_ratio: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchRecord {
+    pub id: i64,
+    pub match_uuid: String,
+    pub match_id: Option<String>,
+    pub session_id: Option<String>,
+    pub opponent_uid: Option<String>,
+    pub player_slot: Option<u8>,
+    pub delay: Option<u16>,
+    pub rtt_ms: Option<u64>,
+    pub rtt_avg_ms: Option<f64>,
+    pub loss_ratio: Option<f64>,
+    // None when the slot is unknown, `stats` still has both players
+    pub own_char: Option<u32>,
+    pub opponent_char: Option<u32>,
+    pub own_super: Option<u32>,
+    pub opponent_super: Option<u32>,
+    pub won: Option<bool>,
+    pub played_at: u64,
+    pub recorded_at: u64,
+    // condensed upload payload
+    pub stats: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Win,
+    Loss,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoryQuery {
+    pub opponent_uid: Option<String>,
+    // our character, `opponent_character` is theirs
+    pub character: Option<u32>,
+    pub opponent_character: Option<u32>,
+    // epoch ms, inclusive
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub outcome: Option<Outcome>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryPage {
+    pub total: u64,
+    pub offset: u32,
+    pub limit: u32,
+    pub items: Vec<MatchRecord>,
+}
+
+const RECORD_COLUMNS: &str = "id, match_uuid, match_id, session_id, opponent_uid, player_slot, \
+     delay, rtt_ms, rtt_avg_ms, loss_ratio, own_char, opponent_char, own_super, opponent_super, \
+     won, played_at, recorded_at, stats";
+
+fn record_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<MatchRecord> {
+    let stats: String = row.get(17)?;
+    Ok(MatchRecord {
+        id: row.get(0)?,
+        match_uuid: row.get(1)?,
+        match_id: row.get(2)?,
+        session_id: row.get(3)?,
+        opponent_uid: row.get(4)?,
+        player_slot: row.get(5)?,
+        delay: row.get(6)?,
+        rtt_ms: row.get(7)?,
+        rtt_avg_ms: row.get(8)?,
+        loss_ratio: row.get(9)?,
+        own_char: row.get(10)?,
+        opponent_char: row.get(11)?,
+        own_super: row.get(12)?,
+        opponent_super: row.get(13)?,
+        won: row.get(14)?,
+        played_at: row.get(15)?,
+        recorded_at: row.get(16)?,
+        stats: serde_json::from_str(&stats).unwrap_or(Value::Null),
+    })
+}
+
+pub struct MatchHistory {
+    conn: Mutex<Connection>,
+}
+
+impl MatchHistory {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    // Used when the data dir is unusable, history then lasts for the session
+    pub fn in_memory() -> rusqlite::Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    // Returns false when the match was already recorded (same match-uuid).
+    // Without a known slot the row has no side, it is neither a win nor a loss.
+    pub fn record(
+        &self,
+        stats: &MatchStats,
+        condensed: &Value,
+        context: &MatchContext,
+        now_ms: u64,
+    ) -> rusqlite::Result<bool> {
+        let (own, opponent, won) = match context.player_slot {
+            Some(1) => (
+                Some(&stats.player1),
+                Some(&stats.player2),
+                Some(stats.winner == Winner::Player1),
+            ),
+            Some(2) => (
+                Some(&stats.player2),
+                Some(&stats.player1),
+                Some(stats.winner == Winner::Player2),
+            ),
+            _ => (None, None, None),
+        };
+        // the Lua script writes seconds, we keep milliseconds like everything else
+        let played_at = stats
+            .created_at
+            .map(|at| if at < 10_000_000_000 { at * 1000 } else { at })
+            .unwrap_or(now_ms);
+        let inserted = self.conn.lock().unwrap().execute(
+            "INSERT INTO matches (match_uuid, match_id, session_id, opponent_uid, player_slot, \
+             delay, rtt_ms, rtt_avg_ms, loss_ratio, own_char, opponent_char, own_super, \
+             opponent_super, won, played_at, recorded_at, stats) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17) \
+             ON CONFLICT (match_uuid) DO NOTHING",
+            params![
+                stats.match_uuid,
+                context.match_id,
+                context.session_id,
+                context.opponent_uid,
+                context.player_slot,
+                context.delay,
+                context.rtt_ms,
+                context.rtt_avg_ms,
+                context.loss_ratio,
+                own.map(|p| p.char),
+                opponent.map(|p| p.char),
+                own.map(|p| p.super_art),
+                opponent.map(|p| p.super_art),
+                won,
+                played_at,
+                now_ms,
+                condensed.to_string(),
+            ],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    pub fn get(&self, match_uuid: &str) -> rusqlite::Result<Option<MatchRecord>> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row(
+                &format!("SELECT {RECORD_COLUMNS} FROM matches WHERE match_uuid = ?1"),
+                params![match_uuid],
+                record_from_row,
+            )
+            .optional()
+    }
+
+    // Rows recorded after `after_id`, in insertion order, for incremental readers
+    pub fn recorded_after(&self, after_id: i64) -> rusqlite::Result<Vec<MatchRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {RECORD_COLUMNS} FROM matches WHERE id > ?1 ORDER BY id"
+        ))?;
+        let rows = stmt
+            .query_map(params![after_id], record_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>();
+        rows
+    }
+
+    // Newest first
+    pub fn query(&self, query: &HistoryQuery) -> rusqlite::Result<HistoryPage> {
+        let mut clauses: Vec<&str> = Vec::new();
+        let mut values: Vec<SqlValue> = Vec::new();
+        if let Some(uid) = &query.opponent_uid {
+            clauses.push("opponent_uid = ?");
+            values.push(SqlValue::Text(uid.clone()));
+        }
+        if let Some(character) = query.character {
+            clauses.push("own_char = ?");
+            values.push(SqlValue::Integer(character.into()));
+        }
+        if let Some(character) = query.opponent_character {
+            clauses.push("opponent_char = ?");
+            values.push(SqlValue::Integer(character.into()));
+        }
+        if let Some(from) = query.from {
+            clauses.push("played_at >= ?");
+            values.push(SqlValue::Integer(from as i64));
+        }
+        if let Some(to) = query.to {
+            clauses.push("played_at <= ?");
+            values.push(SqlValue::Integer(to as i64));
+        }
+        if let Some(outcome) = query.outcome {
+            clauses.push("won = ?");
+            values.push(SqlValue::Integer((outcome == Outcome::Win).into()));
+        }
+        let filter = if clauses.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", clauses.join(" AND "))
+        };
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+        let offset = query.offset.unwrap_or(0);
+
+        let conn = self.conn.lock().unwrap();
+        let total: u64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM matches{filter}"),
+            params_from_iter(values.iter()),
+            |row| row.get(0),
+        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {RECORD_COLUMNS} FROM matches{filter} \
+             ORDER BY played_at DESC, id DESC LIMIT {limit} OFFSET {offset}"
+        ))?;
+        let items = stmt
+            .query_map(params_from_iter(values.iter()), record_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(HistoryPage {
+            total,
+            offset,
+            limit,
+            items,
+        })
+    }
+}
+
+#[tauri::command]
+pub fn list_match_history(
+    history: tauri::State<'_, MatchHistory>,
+    query: Option<HistoryQuery>,
+) -> Result<HistoryPage, String> {
+    history
+        .query(&query.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_match_record(
+    history: tauri::State<'_, MatchHistory>,
+    match_uuid: String,
+) -> Result<Option<MatchRecord>, String> {
+    history.get(&match_uuid).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGACY: &str = include_str!("../tests/fixtures/match_stats/legacy_p1_win.txt");
+    const JSON: &str = include_str!("../tests/fixtures/match_stats/json_p2_win.txt");
+
+    fn record(history: &MatchHistory, raw: &str, context: &MatchContext) -> bool {
+        let stats = MatchStats::parse(raw).unwrap();
+        history
+            .record(&stats, &stats.condensed(0), context, 1_000)
+            .unwrap()
+    }
+
+    #[test]
+    fn records_once_per_match_uuid_from_our_side() {
+        let history = MatchHistory::in_memory().unwrap();
+        let context = MatchContext {
+            opponent_uid: Some("rival".to_string()),
+            player_slot: Some(2),
+            ..Default::default()
+        };
+        assert!(record(&history, LEGACY, &context));
+        assert!(!record(&history, LEGACY, &context));
+
+        let saved = history
+            .get("7f3c9a2e-4b1d-4f6a-9c1e-2d8b5a0e6f13")
+            .unwrap()
+            .unwrap();
+        // player 1 won and we were player 2
+        assert_eq!(saved.won, Some(false));
+        assert_eq!(saved.own_char, Some(16));
+        assert_eq!(saved.opponent_char, Some(11));
+        assert_eq!(saved.played_at, 1_760_841_600_000);
+        assert_eq!(saved.stats["matchUuid"], saved.match_uuid);
+    }
+
+    #[test]
+    fn query_filters_and_pages() {
+        let history = MatchHistory::in_memory().unwrap();
+        let rival = MatchContext {
+            opponent_uid: Some("rival".to_string()),
+            player_slot: Some(1),
+            ..Default::default()
+        };
+        record(&history, LEGACY, &rival);
+        record(&history, JSON, &MatchContext::default());
+
+        let all = history.query(&HistoryQuery::default()).unwrap();
+        assert_eq!(all.total, 2);
+        // newest first
+        assert_eq!(
+            all.items[0].match_uuid,
+            "c0d5e1a4-93b2-4e7f-8a61-5f0b2c9d7e88"
+        );
+
+        let wins_vs_rival = history
+            .query(&HistoryQuery {
+                opponent_uid: Some("rival".to_string()),
+                outcome: Some(Outcome::Win),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(wins_vs_rival.total, 1);
+        assert_eq!(wins_vs_rival.items[0].own_char, Some(11));
+
+        let by_date = history
+            .query(&HistoryQuery {
+                from: Some(1_760_845_000_000),
+                limit: Some(1),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(by_date.total, 1);
+        assert_eq!(by_date.items.len(), 1);
+
+        let second_page = history
+            .query(&HistoryQuery {
+                limit: Some(1),
+                offset: Some(1),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(second_page.total, 2);
+        assert_eq!(
+            second_page.items[0].match_uuid,
+            "7f3c9a2e-4b1d-4f6a-9c1e-2d8b5a0e6f13"
+        );
+    }
+
+    #[test]
+    fn unknown_slot_records_no_side() {
+        let history = MatchHistory::in_memory().unwrap();
+        record(&history, LEGACY, &MatchContext::default());
+
+        let saved = history
+            .get("7f3c9a2e-4b1d-4f6a-9c1e-2d8b5a0e6f13")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (saved.won, saved.own_char, saved.own_super),
+            (None, None, None)
+        );
+        assert_eq!(saved.stats["participants"]["player1"]["char"], 11);
+        for outcome in [Outcome::Win, Outcome::Loss] {
+            let page = history
+                .query(&HistoryQuery {
+                    outcome: Some(outcome),
+                    ..Default::default()
+                })
+                .unwrap();
+            assert_eq!(page.total, 0);
+        }
+    }
+}
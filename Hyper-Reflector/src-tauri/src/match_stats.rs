@@ -0,0 +1,412 @@
+// Typed view of the stats hyper_reflector.lua writes after a match. Newer
+// scripts write JSON, older ones `key: value` lines where repeated keys build up
+// arrays. Both are validated here so a broken file is reported field by field
+// instead of being uploaded with zeroes.
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+// Only the most recent meter events are uploaded
+const MAX_METER_EVENTS: usize = 200;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Winner {
+    Player1,
+    Player2,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerStats {
+    pub char: u32,
+    pub super_art: u32,
+    pub total_meter: f64,
+    pub meter_gained: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchStats {
+    pub match_uuid: String,
+    pub created_at: Option<u64>,
+    pub winner: Winner,
+    pub player1: PlayerStats,
+    pub player2: PlayerStats,
+}
+
+// JSON object first, otherwise the line format. Numbers are coerced, anything
+// else stays a string; validation happens in MatchStats::from_value.
+pub fn parse_raw(raw: &str) -> Option<Value> {
+    if raw.trim().is_empty() {
+        return None;
+    }
+    if let Ok(value @ Value::Object(_)) = serde_json::from_str::<Value>(raw) {
+        return Some(value);
+    }
+
+    let mut out = Map::new();
+    for line in raw.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        // only the first colon separates, values may contain more
+        let (key, raw_value) = line.split_once(':').unwrap_or((line, ""));
+        let (key, raw_value) = (key.trim(), raw_value.trim());
+        if key.is_empty() {
+            continue;
+        }
+        let value = if let Ok(n) = raw_value.parse::<i64>() {
+            Value::from(n)
+        } else {
+            raw_value
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(Value::Number)
+                .unwrap_or_else(|| Value::String(raw_value.to_string()))
+        };
+        match out.get_mut(key) {
+            Some(Value::Array(items)) => items.push(value),
+            Some(existing) => {
+                let first = existing.take();
+                *existing = Value::Array(vec![first, value]);
+            }
+            None => {
+                out.insert(key.to_string(), value);
+            }
+        }
+    }
+    Some(Value::Object(out))
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64().filter(|n| n.is_finite()),
+        Value::String(s) => s.trim().parse::<f64>().ok().filter(|n| n.is_finite()),
+        _ => None,
+    }
+}
+
+fn as_flag(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::Number(n) => n.as_f64().map(|n| n != 0.0),
+        Value::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Collects every problem instead of stopping at the first one
+struct Reader<'a> {
+    source: &'a Map<String, Value>,
+    errors: Vec<FieldError>,
+}
+
+impl<'a> Reader<'a> {
+    fn fail(&mut self, field: &str, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        });
+    }
+
+    // The line format turns a key written twice into an array; for scalar
+    // fields the last write wins, same as the frontend used to pick.
+    fn scalar(&self, field: &str) -> Option<&'a Value> {
+        match self.source.get(field)? {
+            Value::Array(items) => items.last(),
+            value => Some(value),
+        }
+    }
+
+    fn string(&mut self, field: &str) -> Option<String> {
+        let value = match self.scalar(field) {
+            Some(Value::String(s)) if !s.trim().is_empty() => s.trim().to_string(),
+            Some(Value::Number(n)) => n.to_string(),
+            Some(_) => {
+                self.fail(field, "expected a non-empty string");
+                return None;
+            }
+            None => {
+                self.fail(field, "missing");
+                return None;
+            }
+        };
+        Some(value)
+    }
+
+    fn code(&mut self, field: &str) -> Option<u32> {
+        let Some(value) = self.scalar(field) else {
+            self.fail(field, "missing");
+            return None;
+        };
+        match as_number(value) {
+            Some(n) if n >= 0.0 && n.fract() == 0.0 && n <= u32::MAX as f64 => Some(n as u32),
+            _ => {
+                self.fail(
+                    field,
+                    format!("expected a non-negative integer, got {value}"),
+                );
+                None
+            }
+        }
+    }
+
+    fn optional_number(&mut self, field: &str) -> Option<f64> {
+        let value = self.scalar(field)?;
+        let parsed = as_number(value);
+        if parsed.is_none() {
+            self.fail(field, format!("expected a number, got {value}"));
+        }
+        parsed
+    }
+
+    fn optional_flag(&mut self, field: &str) -> Option<bool> {
+        let value = self.scalar(field)?;
+        let parsed = as_flag(value);
+        if parsed.is_none() {
+            self.fail(field, format!("expected true/false or 1/0, got {value}"));
+        }
+        parsed
+    }
+
+    // A single meter event in the line format is a plain value, not an array
+    fn samples(&mut self, field: &str) -> Option<Vec<f64>> {
+        let items = match self.source.get(field) {
+            Some(Value::Array(items)) => items.as_slice(),
+            Some(value) => std::slice::from_ref(value),
+            None => {
+                self.fail(field, "missing");
+                return None;
+            }
+        };
+        let mut out = Vec::with_capacity(items.len());
+        for (idx, item) in items.iter().enumerate() {
+            match as_number(item) {
+                Some(n) => out.push(n),
+                None => {
+                    self.fail(field, format!("entry {idx} is not a number: {item}"));
+                    return None;
+                }
+            }
+        }
+        Some(out)
+    }
+
+    fn winner(&mut self) -> Option<Winner> {
+        let p1 = self.optional_flag("p1-win");
+        let p2 = self.optional_flag("p2-win");
+        match (p1, p2) {
+            (Some(true), Some(true)) => {
+                self.fail("p1-win", "both p1-win and p2-win are set");
+                None
+            }
+            (Some(true), _) | (None, Some(false)) => Some(Winner::Player1),
+            (_, Some(true)) | (Some(false), None) => Some(Winner::Player2),
+            (Some(false), Some(false)) => {
+                self.fail("p1-win", "neither p1-win nor p2-win is set");
+                None
+            }
+            // very old scripts only wrote `winner: player1|player2`
+            (None, None) => match self.scalar("winner").and_then(Value::as_str) {
+                Some("player1") => Some(Winner::Player1),
+                Some("player2") => Some(Winner::Player2),
+                _ => {
+                    if !self.source.contains_key("p1-win") && !self.source.contains_key("p2-win") {
+                        self.fail("p1-win", "missing p1-win / p2-win");
+                    }
+                    None
+                }
+            },
+        }
+    }
+
+    fn player(&mut self, slot: u8) -> Option<PlayerStats> {
+        let char = self.code(&format!("player{slot}-char"));
+        let super_art = self.code(&format!("player{slot}-super"));
+        let meter_gained = self.samples(&format!("p{slot}-meter-gained"));
+        // older scripts don't write the total, it is the sum of the events
+        let total = self.optional_number(&format!("p{slot}-total-meter-gained"));
+        let meter_gained = meter_gained?;
+        Some(PlayerStats {
+            char: char?,
+            super_art: super_art?,
+            total_meter: total.unwrap_or_else(|| meter_gained.iter().sum()),
+            meter_gained,
+        })
+    }
+}
+
+impl MatchStats {
+    pub fn parse(raw: &str) -> Result<Self, Vec<FieldError>> {
+        let value = parse_raw(raw).ok_or_else(|| {
+            vec![FieldError {
+                field: String::new(),
+                message: "stats file is empty".to_string(),
+            }]
+        })?;
+        Self::from_value(&value)
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, Vec<FieldError>> {
+        let Value::Object(source) = value else {
+            return Err(vec![FieldError {
+                field: String::new(),
+                message: "expected an object".to_string(),
+            }]);
+        };
+        let mut reader = Reader {
+            source,
+            errors: Vec::new(),
+        };
+        let match_uuid = reader.string("match-uuid");
+        let created_at = reader
+            .optional_number("created-at")
+            .filter(|n| *n >= 0.0)
+            .map(|n| n as u64);
+        let winner = reader.winner();
+        let player1 = reader.player(1);
+        let player2 = reader.player(2);
+
+        match (match_uuid, winner, player1, player2) {
+            (Some(match_uuid), Some(winner), Some(player1), Some(player2))
+                if reader.errors.is_empty() =>
+            {
+                Ok(Self {
+                    match_uuid,
+                    created_at,
+                    winner,
+                    player1,
+                    player2,
+                })
+            }
+            _ => Err(reader.errors),
+        }
+    }
+
+    // What the backend stores under matchData.raw. The dashed keys are what its
+    // older parser reads, the nested ones what the newer one does.
+    pub fn condensed(&self, now_ms: u64) -> Value {
+        let limit = |samples: &[f64]| -> Vec<f64> {
+            samples[samples.len().saturating_sub(MAX_METER_EVENTS)..].to_vec()
+        };
+        let p1_samples = limit(&self.player1.meter_gained);
+        let p2_samples = limit(&self.player2.meter_gained);
+        let p1_win = self.winner == Winner::Player1;
+        json!({
+            "matchUuid": self.match_uuid,
+            "createdAt": self.created_at.unwrap_or(now_ms),
+            "winner": self.winner,
+            "p1-win": p1_win,
+            "p2-win": !p1_win,
+            "player1-char": self.player1.char,
+            "player2-char": self.player2.char,
+            "player1-super": self.player1.super_art,
+            "player2-super": self.player2.super_art,
+            "p1-total-meter-gained": self.player1.total_meter,
+            "p2-total-meter-gained": self.player2.total_meter,
+            "p1-meter-gained": p1_samples,
+            "p2-meter-gained": p2_samples,
+            "participants": {
+                "player1": {
+                    "char": self.player1.char,
+                    "super": self.player1.super_art,
+                    "totalMeter": self.player1.total_meter,
+                },
+                "player2": {
+                    "char": self.player2.char,
+                    "super": self.player2.super_art,
+                    "totalMeter": self.player2.total_meter,
+                },
+            },
+            "meterSamples": {
+                "player1": p1_samples,
+                "player2": p2_samples,
+            },
+        })
+    }
+}
+
+#[tauri::command]
+pub fn parse_match_stats(raw: String) -> Result<Value, Vec<FieldError>> {
+    MatchStats::parse(&raw).map(|stats| stats.condensed(crate::control::now_ms()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hand-written in the format the Lua script writes, see the README next
+    // to them; not captured from a real run yet.
+    const LEGACY: &str = include_str!("../tests/fixtures/match_stats/legacy_p1_win.txt");
+    const JSON: &str = include_str!("../tests/fixtures/match_stats/json_p2_win.txt");
+    const TRUNCATED: &str = include_str!("../tests/fixtures/match_stats/legacy_truncated.txt");
+
+    #[test]
+    fn legacy_lines_collect_repeated_meter_events() {
+        let stats = MatchStats::parse(LEGACY).unwrap();
+        assert_eq!(stats.match_uuid, "7f3c9a2e-4b1d-4f6a-9c1e-2d8b5a0e6f13");
+        assert_eq!(stats.winner, Winner::Player1);
+        assert_eq!(stats.player1.char, 11);
+        assert_eq!(stats.player2.super_art, 2);
+        assert_eq!(stats.player1.meter_gained, vec![12.0, 8.0, 20.0, 4.0]);
+        assert_eq!(stats.player2.meter_gained, vec![6.0, 14.0, 9.0]);
+        assert_eq!(stats.player1.total_meter, 44.0);
+    }
+
+    #[test]
+    fn json_payload_produces_condensed_upload() {
+        let stats = MatchStats::parse(JSON).unwrap();
+        assert_eq!(stats.winner, Winner::Player2);
+        let condensed = stats.condensed(0);
+        assert_eq!(
+            condensed["matchUuid"],
+            "c0d5e1a4-93b2-4e7f-8a61-5f0b2c9d7e88"
+        );
+        assert_eq!(condensed["createdAt"], 1760845200);
+        assert_eq!(condensed["winner"], "player2");
+        assert_eq!(condensed["p1-win"], false);
+        assert_eq!(condensed["player2-char"], 14);
+        assert_eq!(condensed["participants"]["player1"]["super"], 3);
+        assert_eq!(
+            condensed["meterSamples"]["player2"],
+            json!([22.0, 7.0, 16.0, 11.0])
+        );
+    }
+
+    #[test]
+    fn truncated_file_reports_each_bad_field() {
+        let errors = MatchStats::parse(TRUNCATED).unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec!["p1-win", "player2-char", "player2-super", "p2-meter-gained"]
+        );
+    }
+
+    #[test]
+    fn line_values_keep_their_colons() {
+        let value = parse_raw("match-uuid: a:b:c\nnote:\n: orphan").unwrap();
+        assert_eq!(value["match-uuid"], "a:b:c");
+        assert_eq!(value["note"], "");
+        assert!(value.get("").is_none());
+    }
+
+    #[test]
+    fn condensed_keeps_only_the_latest_meter_events() {
+        let mut stats = MatchStats::parse(LEGACY).unwrap();
+        stats.player1.meter_gained = (0..250).map(f64::from).collect();
+        let condensed = stats.condensed(0);
+        let samples = condensed["p1-meter-gained"].as_array().unwrap();
+        assert_eq!(samples.len(), MAX_METER_EVENTS);
+        assert_eq!(samples[0], 50.0);
+    }
+}
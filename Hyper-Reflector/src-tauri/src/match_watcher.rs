@@ -0,0 +1,294 @@
+// Background watcher for the files hyper_reflector.lua writes after a match.
+// The script drops `read-tracking-file` into the command file once the stats
+// file is complete; we pick both up here instead of polling from the webview,
+// so it keeps working while the window is hidden or minimized.
+use crate::emulator_supervisor::{EmulatorInfo, EmulatorSupervisor};
+use crate::match_history::{MatchContext, MatchHistory};
+use crate::match_stats::{self, FieldError, MatchStats};
+use crate::proxy::ProxyManager;
+use notify::{RecursiveMode, Watcher};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tauri::{AppHandle, Emitter, EventTarget, Manager};
+use tokio::{sync::mpsc, time::interval};
+
+pub const COMMAND_FILE: &str = "hyper_read_commands.txt";
+pub const STATS_FILE: &str = "hyper_track_match.txt";
+const READ_COMMAND: &str = "read-tracking-file";
+// FBNeo rejects arguments it doesn't know, so a proxied emulator gets its
+// session and slot through the environment. hyper_reflector.lua doesn't read
+// them yet; once it copies them into the stats under SESSION_KEY / PLAYER_KEY
+// the watcher prefers them over guessing from the running emulators.
+pub const SESSION_ENV: &str = "HYPER_REFLECTOR_SESSION";
+pub const PLAYER_ENV: &str = "HYPER_REFLECTOR_PLAYER";
+const SESSION_KEY: &str = "session-id";
+const PLAYER_KEY: &str = "player-slot";
+
+// Filesystem events can be dropped (network drives, some Wine setups), so the
+// files are still checked on a slow timer next to the watcher. Without a
+// watcher we poll at the rate the frontend used to.
+const SAFETY_POLL: Duration = Duration::from_secs(5);
+const FALLBACK_POLL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchStatsEvent {
+    pub raw: String,
+    // loosely parsed payload, present even when validation failed
+    pub stats: Option<Value>,
+    // the upload payload, only when the stats validated
+    pub condensed: Option<Value>,
+    pub errors: Vec<FieldError>,
+}
+
+// Moves the file aside before reading so anything the script writes meanwhile
+// lands in a fresh file instead of being truncated away with ours. On Windows
+// the rename fails while Lua still holds the file; we try again next tick.
+fn take_file(path: &Path) -> std::io::Result<Option<String>> {
+    let taken = path.with_extension("txt.reading");
+    match fs::rename(path, &taken) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let contents = fs::read_to_string(&taken);
+    let _ = fs::remove_file(&taken);
+    contents.map(Some)
+}
+
+fn has_command(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|c| !c.trim().is_empty())
+}
+
+// Raw stats once the script has asked for them to be read
+fn take_stats(app: &AppHandle, dir: &Path) -> Option<String> {
+    let command_path = dir.join(COMMAND_FILE);
+    if !has_command(&command_path) {
+        return None;
+    }
+    let command = match take_file(&command_path) {
+        Ok(Some(command)) => command,
+        Ok(None) => return None,
+        Err(e) => {
+            let _ = app.emit_to(
+                EventTarget::any(),
+                "proxy-log",
+                format!("match watcher: command file busy: {e}"),
+            );
+            return None;
+        }
+    };
+    if !command.to_ascii_lowercase().contains(READ_COMMAND) {
+        return None;
+    }
+
+    match take_file(&dir.join(STATS_FILE)) {
+        Ok(Some(raw)) if !raw.trim().is_empty() => Some(raw),
+        Ok(_) => None,
+        Err(e) => {
+            let _ = app.emit_to(
+                EventTarget::any(),
+                "proxy-log",
+                format!("match watcher: failed to read stats: {e}"),
+            );
+            None
+        }
+    }
+}
+
+// Session and slot the stats were tagged with, see SESSION_ENV
+fn stats_origin(stats: &Value) -> (Option<String>, Option<u8>) {
+    let session = match &stats[SESSION_KEY] {
+        Value::String(id) if !id.trim().is_empty() => Some(id.trim().to_string()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    };
+    let slot = match &stats[PLAYER_KEY] {
+        Value::Number(slot) => slot.as_u64(),
+        Value::String(slot) => slot.trim().parse().ok(),
+        _ => None,
+    };
+    let slot = slot
+        .filter(|slot| matches!(slot, 1 | 2))
+        .map(|slot| slot as u8);
+    (session, slot)
+}
+
+// Untagged stats are tied to the proxy session when the running emulators
+// belong to exactly one. Training or replay emulators next to it don't count,
+// so a match played next to one is still attributed to the session, which has
+// the slot. Two proxied emulators (loopback) stay ambiguous.
+fn running_session(emulators: &[EmulatorInfo]) -> Option<String> {
+    let mut sessions = emulators.iter().filter_map(|e| e.session_id.as_ref());
+    let first = sessions.next()?;
+    sessions.all(|id| id == first).then(|| first.clone())
+}
+
+async fn match_context(app: &AppHandle, stats: &Value) -> MatchContext {
+    let (session, slot) = stats_origin(stats);
+    let session =
+        session.or_else(|| running_session(&app.state::<EmulatorSupervisor>().list()));
+    let mut context = match session {
+        Some(id) => match app.state::<ProxyManager>().match_context(&id).await {
+            Some(context) => context,
+            None => MatchContext {
+                session_id: Some(id),
+                ..Default::default()
+            },
+        },
+        None => MatchContext::default(),
+    };
+    if slot.is_some() {
+        context.player_slot = slot;
+    }
+    context
+}
+
+// A match played over a proxy session also counts towards that session's set
+async fn count_set_game(app: &AppHandle, session_id: &str, stats: &Value) {
+    let Some(rt) = app.state::<ProxyManager>().get(session_id).await else {
+        return;
+    };
+    if let Err(e) = rt.record_set_game(stats).await {
+        let _ = app.emit_to(
+            EventTarget::any(),
+            "proxy-log",
+            format!("match watcher: failed to count set game: {e}"),
+        );
+    }
+}
+
+// Valid stats go into the local history before the frontend hears about them
+async fn handle_stats(app: &AppHandle, raw: String) {
+    let now = crate::control::now_ms();
+    let stats = match_stats::parse_raw(&raw);
+    let (condensed, errors) = match stats.as_ref().map(|s| (s, MatchStats::from_value(s))) {
+        Some((value, Ok(parsed))) => {
+            let condensed = parsed.condensed(now);
+            let context = match_context(app, value).await;
+            if let Err(e) = app
+                .state::<MatchHistory>()
+                .record(&parsed, &condensed, &context, now)
+            {
+                let _ = app.emit_to(
+                    EventTarget::any(),
+                    "proxy-log",
+                    format!("match watcher: failed to save match history: {e}"),
+                );
+            }
+            if let Some(id) = &context.session_id {
+                count_set_game(app, id, value).await;
+            }
+            (Some(condensed), Vec::new())
+        }
+        Some((_, Err(errors))) => (None, errors),
+        None => (None, Vec::new()),
+    };
+    let _ = app.emit_to(
+        EventTarget::any(),
+        "match:stats",
+        MatchStatsEvent {
+            raw,
+            stats,
+            condensed,
+            errors,
+        },
+    );
+}
+
+// Runs for the lifetime of the app. The watcher is kept inside the task since
+// dropping it stops the notifications.
+pub fn spawn(app: AppHandle, dir: PathBuf) {
+    tauri::async_runtime::spawn(async move {
+        let (tx, mut rx) = mpsc::channel::<()>(1);
+        let created = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            if res.is_ok() {
+                // a full channel already means "check soon"
+                let _ = tx.try_send(());
+            }
+        })
+        .and_then(|mut w| w.watch(&dir, RecursiveMode::NonRecursive).map(|_| w));
+        let watcher = match created {
+            Ok(w) => Some(w),
+            Err(e) => {
+                let _ = app.emit_to(
+                    EventTarget::any(),
+                    "proxy-log",
+                    format!("match watcher: falling back to polling ({e})"),
+                );
+                None
+            }
+        };
+
+        let mut ticker = interval(if watcher.is_some() {
+            SAFETY_POLL
+        } else {
+            FALLBACK_POLL
+        });
+        loop {
+            tokio::select! {
+                Some(()) = rx.recv() => {}
+                _ = ticker.tick() => {}
+            }
+            let (reader, dir) = (app.clone(), dir.clone());
+            let taken = tokio::task::spawn_blocking(move || take_stats(&reader, &dir)).await;
+            if let Ok(Some(raw)) = taken {
+                handle_stats(&app, raw).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_carry_their_session_and_slot() {
+        let tagged =
+            match_stats::parse_raw("match-uuid: a\nsession-id: proxy-3\nplayer-slot: 2").unwrap();
+        assert_eq!(
+            stats_origin(&tagged),
+            (Some("proxy-3".to_string()), Some(2))
+        );
+
+        let numeric = serde_json::json!({ "session-id": 42, "player-slot": "1" });
+        assert_eq!(stats_origin(&numeric), (Some("42".to_string()), Some(1)));
+
+        let untagged = match_stats::parse_raw("match-uuid: a\nplayer-slot: 3").unwrap();
+        assert_eq!(stats_origin(&untagged), (None, None));
+    }
+
+    fn emulator(id: u64, session_id: Option<&str>) -> EmulatorInfo {
+        EmulatorInfo {
+            id,
+            purpose: Default::default(),
+            pid: None,
+            started_at: 0,
+            program: "fbneo".to_string(),
+            args: Vec::new(),
+            emulator: "fbneo".to_string(),
+            session_id: session_id.map(str::to_string),
+            log_path: None,
+            scheduling: None,
+        }
+    }
+
+    #[test]
+    fn untagged_stats_go_to_the_only_proxied_emulator() {
+        let training = emulator(1, None);
+        let proxied = emulator(2, Some("s-1"));
+        assert_eq!(running_session(std::slice::from_ref(&training)), None);
+        assert_eq!(
+            running_session(&[training.clone(), proxied.clone()]),
+            Some("s-1".to_string())
+        );
+        assert_eq!(running_session(&[proxied, training, emulator(3, Some("s-2"))]), None);
+    }
+}
@@ -0,0 +1,344 @@
+// Emulators left behind by a previous run that crashed or was killed. They
+// hold on to 7000/7001 and make the next match fail to bind, so the supervisor
+// keeps their pids on disk and we look for survivors on startup. A survivor is
+// told apart from an unrelated process that reused the pid by its start time
+// and binary: through /proc on Linux, process handles on Windows. Elsewhere
+// nothing is reported.
+use crate::emulator_supervisor::EmulatorPurpose;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+#[cfg(any(target_os = "linux", windows))]
+use std::time::Duration;
+
+pub const REGISTRY_FILE: &str = "emulator_pids.json";
+// How long an orphan asked to exit gets before it is killed
+#[cfg(any(target_os = "linux", windows))]
+const KILL_GRACE: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PidRecord {
+    pub pid: u32,
+    pub program: String,
+    pub purpose: EmulatorPurpose,
+    pub started_at: u64,
+    // start time of the process (Linux clock ticks, Windows FILETIME), tells
+    // pid reuse apart
+    #[serde(default)]
+    pub proc_start: Option<u64>,
+}
+
+pub fn load(path: &Path) -> Vec<PidRecord> {
+    fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(path: &Path, records: &[PidRecord]) -> std::io::Result<()> {
+    let json = serde_json::to_vec_pretty(records).map_err(std::io::Error::other)?;
+    // written aside and renamed so a crash mid-write can't leave half a file
+    let tmp: PathBuf = path.with_extension("json.tmp");
+    fs::write(&tmp, json)?;
+    fs::rename(tmp, path)
+}
+
+// Fields of /proc/<pid>/stat after the command name, starting with the state.
+// The name is parenthesised and may itself contain spaces or parens, so split
+// after the last ')'.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn stat_fields(stat: &str) -> Option<Vec<&str>> {
+    let rest = &stat[stat.rfind(')')? + 1..];
+    Some(rest.split_whitespace().collect())
+}
+
+// (state, start time) from /proc/<pid>/stat
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_stat(stat: &str) -> Option<(char, u64)> {
+    let fields = stat_fields(stat)?;
+    let state = fields.first()?.chars().next()?;
+    // field 22 overall, 20th after the command name
+    let start = fields.get(19)?.parse().ok()?;
+    Some((state, start))
+}
+
+#[cfg(target_os = "linux")]
+pub fn proc_start(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    parse_stat(&stat).map(|(_, start)| start)
+}
+
+#[cfg(windows)]
+pub fn proc_start(pid: u32) -> Option<u64> {
+    windows::with_process(pid, windows::QUERY, windows::created_at)
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn proc_start(_pid: u32) -> Option<u64> {
+    None
+}
+
+#[cfg_attr(not(any(target_os = "linux", windows)), allow(dead_code))]
+fn file_name_lower(path: &str) -> Option<String> {
+    // cmdline under Wine carries Windows paths
+    path.rsplit(['/', '\\'])
+        .next()
+        .filter(|name| !name.is_empty())
+        .map(str::to_ascii_lowercase)
+}
+
+// Alive, same process we started, and still running our emulator binary
+// (directly or as the first argument, which is how Wine shows up)
+#[cfg(target_os = "linux")]
+pub fn is_our_emulator(record: &PidRecord) -> bool {
+    let proc_dir = PathBuf::from(format!("/proc/{}", record.pid));
+    let Some((state, start)) = fs::read_to_string(proc_dir.join("stat"))
+        .ok()
+        .and_then(|stat| parse_stat(&stat))
+    else {
+        return false;
+    };
+    if state == 'Z' || record.proc_start.is_some_and(|expected| expected != start) {
+        return false;
+    }
+    let program = Path::new(&record.program);
+    if let (Ok(exe), Ok(expected)) = (fs::read_link(proc_dir.join("exe")), program.canonicalize())
+    {
+        if exe == expected {
+            return true;
+        }
+    }
+    let Some(wanted) = file_name_lower(&record.program) else {
+        return false;
+    };
+    let cmdline = fs::read(proc_dir.join("cmdline")).unwrap_or_default();
+    cmdline
+        .split(|b| *b == 0)
+        .take(2)
+        .filter_map(|arg| file_name_lower(&String::from_utf8_lossy(arg)))
+        .any(|name| name == wanted)
+}
+
+// Alive, created when the record says, and running our emulator binary. A
+// wrapper started in its place doesn't count, Windows launches are direct.
+#[cfg(windows)]
+pub fn is_our_emulator(record: &PidRecord) -> bool {
+    windows::with_process(record.pid, windows::QUERY, |handle| {
+        if !windows::running(handle)
+            || record
+                .proc_start
+                .is_some_and(|expected| windows::created_at(handle) != Some(expected))
+        {
+            return Some(false);
+        }
+        let image = windows::image_path(handle)?;
+        let name = file_name_lower(&image)?;
+        Some(file_name_lower(&record.program) == Some(name))
+    })
+    .unwrap_or(false)
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn is_our_emulator(_record: &PidRecord) -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+fn signal(pid: u32, signal: i32) -> bool {
+    // SAFETY: plain kill(2), the pid was just verified to be our emulator
+    unsafe { libc::kill(pid as libc::pid_t, signal) == 0 }
+}
+
+#[cfg(target_os = "linux")]
+async fn request_exit(pid: u32) {
+    signal(pid, libc::SIGTERM);
+}
+
+#[cfg(target_os = "linux")]
+fn force_exit(pid: u32) {
+    signal(pid, libc::SIGKILL);
+}
+
+// Without /F taskkill closes the emulator's window, like the supervisor does
+#[cfg(windows)]
+async fn request_exit(pid: u32) {
+    use std::process::Stdio;
+    use windows_sys::Win32::System::Threading::CREATE_NO_WINDOW;
+    let _ = tokio::process::Command::new("taskkill")
+        .args(["/PID", &pid.to_string()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .creation_flags(CREATE_NO_WINDOW)
+        .status()
+        .await;
+}
+
+#[cfg(windows)]
+fn force_exit(pid: u32) {
+    windows::with_process(pid, windows::TERMINATE, windows::terminate);
+}
+
+// Asks it to exit, then kills it if it is still around after the grace
+// period. Returns whether the process is gone.
+#[cfg(any(target_os = "linux", windows))]
+pub async fn kill(record: &PidRecord) -> bool {
+    if !is_our_emulator(record) {
+        return true;
+    }
+    request_exit(record.pid).await;
+    let deadline = tokio::time::Instant::now() + KILL_GRACE;
+    while tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        if !is_our_emulator(record) {
+            return true;
+        }
+    }
+    force_exit(record.pid);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    !is_our_emulator(record)
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub async fn kill(_record: &PidRecord) -> bool {
+    false
+}
+
+#[cfg(windows)]
+mod windows {
+    use windows_sys::Win32::Foundation::{CloseHandle, FILETIME, HANDLE, STILL_ACTIVE};
+    use windows_sys::Win32::System::Threading::{
+        GetExitCodeProcess, GetProcessTimes, OpenProcess, QueryFullProcessImageNameW,
+        TerminateProcess, PROCESS_ACCESS_RIGHTS, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_TERMINATE,
+    };
+
+    pub const QUERY: PROCESS_ACCESS_RIGHTS = PROCESS_QUERY_LIMITED_INFORMATION;
+    pub const TERMINATE: PROCESS_ACCESS_RIGHTS =
+        PROCESS_QUERY_LIMITED_INFORMATION | PROCESS_TERMINATE;
+
+    // None when the process can't be opened (gone, or not ours to open)
+    pub fn with_process<T>(
+        pid: u32,
+        access: PROCESS_ACCESS_RIGHTS,
+        f: impl FnOnce(HANDLE) -> Option<T>,
+    ) -> Option<T> {
+        // SAFETY: the handle is only used by `f` and closed right after
+        unsafe {
+            let handle = OpenProcess(access, 0, pid);
+            if handle.is_null() {
+                return None;
+            }
+            let result = f(handle);
+            CloseHandle(handle);
+            result
+        }
+    }
+
+    pub fn running(handle: HANDLE) -> bool {
+        let mut code = 0u32;
+        // SAFETY: `code` is a plain out parameter we own
+        unsafe { GetExitCodeProcess(handle, &mut code) != 0 && code == STILL_ACTIVE as u32 }
+    }
+
+    // Creation time as a FILETIME, 100ns units since 1601
+    pub fn created_at(handle: HANDLE) -> Option<u64> {
+        let zero = FILETIME {
+            dwLowDateTime: 0,
+            dwHighDateTime: 0,
+        };
+        let (mut created, mut exited, mut kernel, mut user) = (zero, zero, zero, zero);
+        // SAFETY: the FILETIMEs are plain out parameters we own
+        let ok = unsafe {
+            GetProcessTimes(handle, &mut created, &mut exited, &mut kernel, &mut user)
+        };
+        (ok != 0).then(|| {
+            (u64::from(created.dwHighDateTime) << 32) | u64::from(created.dwLowDateTime)
+        })
+    }
+
+    pub fn image_path(handle: HANDLE) -> Option<String> {
+        let mut buf = [0u16; 1024];
+        let mut len = buf.len() as u32;
+        // SAFETY: `len` holds the buffer's capacity and gets the written length
+        let ok = unsafe {
+            QueryFullProcessImageNameW(handle, PROCESS_NAME_WIN32, buf.as_mut_ptr(), &mut len)
+        };
+        (ok != 0).then(|| String::from_utf16_lossy(&buf[..len as usize]))
+    }
+
+    pub fn terminate(handle: HANDLE) -> Option<()> {
+        // SAFETY: opened with PROCESS_TERMINATE, verified to be our emulator
+        (unsafe { TerminateProcess(handle, 1) } != 0).then_some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stat_parsing_survives_odd_command_names() {
+        let stat = "4242 (fs fbneo (x)) S 1 4242 4242 0 -1 4194304 100 0 0 0 \
+                    5 3 0 0 20 0 4 0 987654 1000 200";
+        assert_eq!(parse_stat(stat), Some(('S', 987654)));
+        assert_eq!(parse_stat("garbage"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn recognises_and_kills_a_surviving_emulator() {
+        let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let pid = child.id();
+        // cmdline reads empty for a moment while exec finishes
+        std::thread::sleep(Duration::from_millis(100));
+        let record = PidRecord {
+            pid,
+            program: "sleep".to_string(),
+            purpose: EmulatorPurpose::Match,
+            started_at: 0,
+            proc_start: proc_start(pid),
+        };
+        assert!(is_our_emulator(&record));
+        // same pid, but not the binary we launched
+        let other = PidRecord {
+            program: "fs-fbneo.exe".to_string(),
+            ..record.clone()
+        };
+        assert!(!is_our_emulator(&other));
+        // same pid and binary, started at another time: the pid was reused
+        let reused = PidRecord {
+            proc_start: record.proc_start.map(|start| start + 1),
+            ..record.clone()
+        };
+        assert!(!is_our_emulator(&reused));
+
+        // the test is the parent, reap in the background so the zombie goes away
+        let reaper = std::thread::spawn(move || child.wait());
+        assert!(kill(&record).await);
+        reaper.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn registry_round_trips() {
+        let dir = std::env::temp_dir().join(format!("hr-orphans-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(REGISTRY_FILE);
+        assert!(load(&path).is_empty());
+        let record = PidRecord {
+            pid: 1234,
+            program: "/opt/fbneo/fs-fbneo".to_string(),
+            purpose: EmulatorPurpose::Training,
+            started_at: 1,
+            proc_start: Some(99),
+        };
+        save(&path, std::slice::from_ref(&record)).unwrap();
+        let loaded = load(&path);
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].program, record.program);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
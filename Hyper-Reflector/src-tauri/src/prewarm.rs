@@ -0,0 +1,302 @@
+// Background "ready" socket kept while a challenge is pending, so start_proxy
+// can adopt it instead of binding from scratch. The punch server only knows
+// the match punch ({ uid, peerUid, kill }) and answers it with an
+// OpponentEnvelope once both sides have punched, so there is nothing to warm
+// up before the opponent is known: prewarming starts with a challenge, not in
+// the idle lobby. The socket re-punches to keep the NAT mapping open, and the
+// envelope it got is handed over with the socket.
+use crate::proxy::{OpponentEnvelope, PunchMessage};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tauri::{AppHandle, Emitter, EventTarget};
+use tokio::{net::UdpSocket, sync::Mutex, task::JoinHandle, time::interval};
+
+// Most home NATs drop idle UDP mappings after 30-60s
+const PUNCH_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrewarmArgs {
+    pub my_uid: String,
+    // the challenged / challenging player
+    pub peer_uid: String,
+    pub server_host: String,
+    pub server_port: u16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrewarmStatus {
+    pub local_addr: String,
+    pub server: String,
+    pub peer_uid: String,
+    // the opponent's endpoint, once the server paired us
+    pub opponent: Option<String>,
+}
+
+// What start_proxy gets when it adopts the warm socket
+pub struct WarmSocket {
+    pub sock: Arc<UdpSocket>,
+    pub opponent: Option<SocketAddr>,
+}
+
+struct Prewarmed {
+    sock: Arc<UdpSocket>,
+    server: SocketAddr,
+    peer_uid: String,
+    opponent: Arc<Mutex<Option<SocketAddr>>>,
+    task: JoinHandle<()>,
+}
+
+impl Prewarmed {
+    fn abort(&self) {
+        self.task.abort();
+    }
+}
+
+pub struct PrewarmManager {
+    inner: Mutex<Option<Prewarmed>>,
+}
+
+impl PrewarmManager {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(None),
+        }
+    }
+
+    pub async fn cancel(&self) {
+        if let Some(old) = self.inner.lock().await.take() {
+            old.abort();
+        }
+    }
+
+    // Hands the socket over if it was warmed against `server`, a socket
+    // warmed against a different server is useless for this match and gets
+    // dropped. The opponent only comes along when it's the same one.
+    pub async fn take_for(&self, server: SocketAddr, peer_uid: &str) -> Option<WarmSocket> {
+        let warm = self.inner.lock().await.take()?;
+        warm.abort();
+        if warm.server != server {
+            return None;
+        }
+        let opponent = if warm.peer_uid == peer_uid {
+            *warm.opponent.lock().await
+        } else {
+            None
+        };
+        Some(WarmSocket {
+            sock: warm.sock,
+            opponent,
+        })
+    }
+
+    async fn status(&self) -> Result<Option<PrewarmStatus>, String> {
+        let guard = self.inner.lock().await;
+        let Some(warm) = &*guard else {
+            return Ok(None);
+        };
+        let opponent = *warm.opponent.lock().await;
+        Ok(Some(PrewarmStatus {
+            local_addr: warm.sock.local_addr().map_err(|e| e.to_string())?.to_string(),
+            server: warm.server.to_string(),
+            peer_uid: warm.peer_uid.clone(),
+            opponent: opponent.map(|addr| addr.to_string()),
+        }))
+    }
+}
+
+// Punches every PUNCH_INTERVAL and records the opponent from the server's
+// envelopes. `on_opponent` runs for every envelope, the server repeats them
+// whenever either side punches again.
+fn spawn_punch(
+    sock: Arc<UdpSocket>,
+    server: SocketAddr,
+    punch: Vec<u8>,
+    opponent: Arc<Mutex<Option<SocketAddr>>>,
+    on_opponent: impl Fn(&OpponentEnvelope) + Send + 'static,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(PUNCH_INTERVAL);
+        let mut buf = vec![0u8; 2048];
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    if sock.send_to(&punch, server).await.is_err() {
+                        break;
+                    }
+                }
+                r = sock.recv_from(&mut buf) => {
+                    let Ok((n, from)) = r else { break };
+                    if from != server {
+                        continue;
+                    }
+                    let Ok(envelope) = serde_json::from_slice::<OpponentEnvelope>(&buf[..n]) else {
+                        continue;
+                    };
+                    let Ok(addr) = format!("{}:{}", envelope.peer.address, envelope.peer.port)
+                        .parse::<SocketAddr>()
+                    else {
+                        continue;
+                    };
+                    *opponent.lock().await = Some(addr);
+                    on_opponent(&envelope);
+                }
+            }
+        }
+    })
+}
+
+async fn warm_up(app: AppHandle, args: PrewarmArgs) -> anyhow::Result<Prewarmed> {
+    let server: SocketAddr = format!("{}:{}", args.server_host, args.server_port).parse()?;
+    let sock = Arc::new(UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))).await?);
+    let opponent = Arc::new(Mutex::new(None));
+
+    let punch = serde_json::to_vec(&PunchMessage {
+        uid: args.my_uid.clone(),
+        peer_uid: args.peer_uid.clone(),
+        kill: false,
+    })?;
+    let task = spawn_punch(
+        Arc::clone(&sock),
+        server,
+        punch,
+        Arc::clone(&opponent),
+        move |envelope| {
+            let _ = app.emit_to(EventTarget::any(), "proxy:prewarmed", envelope.peer.clone());
+        },
+    );
+
+    Ok(Prewarmed {
+        sock,
+        server,
+        peer_uid: args.peer_uid,
+        opponent,
+        task,
+    })
+}
+
+#[tauri::command]
+pub async fn prewarm_proxy(
+    app: AppHandle,
+    state: tauri::State<'_, PrewarmManager>,
+    args: PrewarmArgs,
+) -> Result<Option<PrewarmStatus>, String> {
+    let warm = warm_up(app, args).await.map_err(|e| e.to_string())?;
+    if let Some(old) = state.inner.lock().await.replace(warm) {
+        old.abort();
+    }
+    state.status().await
+}
+
+#[tauri::command]
+pub async fn prewarm_status(
+    state: tauri::State<'_, PrewarmManager>,
+) -> Result<Option<PrewarmStatus>, String> {
+    state.status().await
+}
+
+#[tauri::command]
+pub async fn cancel_prewarm(state: tauri::State<'_, PrewarmManager>) -> Result<(), String> {
+    state.cancel().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::PeerEndpoint;
+    use std::collections::HashMap;
+
+    // Stand-in punch server, same pairing as the loopback rendezvous
+    async fn spawn_server() -> SocketAddr {
+        let sock = UdpSocket::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).await.unwrap();
+        let addr = sock.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut seen: HashMap<String, SocketAddr> = HashMap::new();
+            let mut buf = vec![0u8; 2048];
+            while let Ok((n, from)) = sock.recv_from(&mut buf).await {
+                let Ok(msg) = serde_json::from_slice::<PunchMessage>(&buf[..n]) else {
+                    continue;
+                };
+                seen.insert(msg.uid.clone(), from);
+                let Some(&peer) = seen.get(&msg.peer_uid) else {
+                    continue;
+                };
+                for (to, other) in [(from, peer), (peer, from)] {
+                    let envelope = OpponentEnvelope {
+                        match_id: None,
+                        peer: PeerEndpoint {
+                            address: other.ip().to_string(),
+                            port: other.port(),
+                        },
+                    };
+                    let _ = sock.send_to(&serde_json::to_vec(&envelope).unwrap(), to).await;
+                }
+            }
+        });
+        addr
+    }
+
+    async fn warm(server: SocketAddr, uid: &str, peer_uid: &str) -> Prewarmed {
+        let sock = Arc::new(UdpSocket::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).await.unwrap());
+        let opponent = Arc::new(Mutex::new(None));
+        let punch = serde_json::to_vec(&PunchMessage {
+            uid: uid.to_string(),
+            peer_uid: peer_uid.to_string(),
+            kill: false,
+        })
+        .unwrap();
+        let task = spawn_punch(Arc::clone(&sock), server, punch, Arc::clone(&opponent), |_| {});
+        Prewarmed {
+            sock,
+            server,
+            peer_uid: peer_uid.to_string(),
+            opponent,
+            task,
+        }
+    }
+
+    async fn paired(warm: &Prewarmed) -> SocketAddr {
+        for _ in 0..100 {
+            if let Some(addr) = *warm.opponent.lock().await {
+                return addr;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("never paired");
+    }
+
+    #[tokio::test]
+    async fn both_sides_learn_each_other_and_hand_the_socket_over() {
+        let server = spawn_server().await;
+        let a = warm(server, "a", "b").await;
+        let b = warm(server, "b", "a").await;
+        assert_eq!(paired(&a).await, b.sock.local_addr().unwrap());
+        assert_eq!(paired(&b).await, a.sock.local_addr().unwrap());
+
+        let a_addr = a.sock.local_addr().unwrap();
+        let manager = PrewarmManager::new();
+        *manager.inner.lock().await = Some(a);
+        let status = manager.status().await.unwrap().unwrap();
+        assert_eq!(status.opponent, Some(b.sock.local_addr().unwrap().to_string()));
+        let adopted = manager.take_for(server, "b").await.unwrap();
+        assert_eq!(adopted.sock.local_addr().unwrap(), a_addr);
+        assert_eq!(adopted.opponent, Some(b.sock.local_addr().unwrap()));
+        assert!(manager.status().await.unwrap().is_none());
+
+        // a different opponent keeps the socket but not the pairing
+        *manager.inner.lock().await = Some(b);
+        let adopted = manager.take_for(server, "c").await.unwrap();
+        assert_eq!(adopted.opponent, None);
+
+        // and a different server drops it altogether
+        let c = warm(server, "c", "d").await;
+        *manager.inner.lock().await = Some(c);
+        let elsewhere = SocketAddr::from((Ipv4Addr::LOCALHOST, 9));
+        assert!(manager.take_for(elsewhere, "d").await.is_none());
+    }
+}
@@ -2,20 +2,45 @@
 // I did not write this, this is a port by chatGPT of the our original node proxy
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use crate::compat::{self, CompatGate, CompatPolicy, CompatState};
+use crate::control::{self, ClockSync, ControlMessage, LinkProbe};
+use crate::emulator_supervisor::{
+    EmulatorExit, EmulatorHandle, EmulatorPurpose, EmulatorSupervisor, ShutdownOptions,
+    ShutdownStage,
+};
+use crate::ggpo::GgpoTelemetry;
+use crate::launch::{self, LaunchOptions};
+use crate::match_history::MatchContext;
+use crate::match_watcher;
+use crate::prewarm::{PrewarmManager, WarmSocket};
+use crate::proxy_stats::{ProxyCounters, ProxyStats};
+use crate::redundancy::{self, RedundancyConfig, RedundantReceiver, RedundantSender};
+use crate::resource_usage;
+use crate::rematch::{
+    self, LaunchOverrides, PendingRematch, RematchEvent, RematchOptions, RematchState,
+};
+use crate::set_tracker::{self, PeerSet, ScoreView, SetStatus, SetTracker};
+use crate::tunnel::{TunnelConfig, TunnelDatagram, TunnelHello, TunnelLink};
 use crate::{resolve_emulator_path, resolve_lua_args};
 use anyhow::anyhow;
+use futures_util::future::join_all;
 use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
     net::{Ipv4Addr, SocketAddr},
-    sync::Arc,
-    time::Duration,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
-use tauri::{AppHandle, Emitter, EventTarget};
+use tauri::{AppHandle, Emitter, EventTarget, Manager, Runtime, Wry};
 use tokio::{
     net::UdpSocket,
-    process::Command as TokioCommand,
-    sync::{oneshot, Mutex},
+    sync::{mpsc, oneshot, Mutex},
     task::JoinHandle,
-    time::interval,
+    time::{interval, sleep_until},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +64,15 @@ pub struct OpponentEnvelope {
     pub peer: PeerEndpoint, // { address, port }
 }
 
+// What start_proxy does when a session with the same id is already running
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExistingSessionPolicy {
+    #[default]
+    Reject,
+    Replace,
+}
+
 // ---- Arguments you pass from the frontend ----
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StartArgs {
@@ -57,27 +91,147 @@ pub struct StartArgs {
     pub emulator_game_port: Option<u16>, // where emulator expects its peer (default 7000)
     pub emulator_listen_port: Option<u16>, // where we listen for emulator (default 7001)
     pub emulator_args: Vec<String>, // exact CLI args to launch emulator
+    // decode GGPO traffic for frame advantage / ping telemetry in proxy stats
+    #[serde(default)]
+    pub inspect_ggpo: bool,
+    // redundant sending of emulator datagrams on lossy links
+    #[serde(default)]
+    pub redundancy: Option<RedundancyConfig>,
+    // TCP / WebSocket relay used when UDP is blocked
+    #[serde(default)]
+    pub tunnel: Option<TunnelConfig>,
+    #[serde(default)]
+    pub on_existing: ExistingSessionPolicy,
+    // keep the session around after the emulator exits so a rematch can reuse the link
+    #[serde(default)]
+    pub allow_rematch: bool,
+    // best-of-N set for this session, "FT2" / "FT3" / "FT5"
+    #[serde(default)]
+    pub set_format: Option<String>,
+    // what to do when the peer runs a different app / emulator / ROM / Lua build
+    #[serde(default)]
+    pub compat_policy: CompatPolicy,
+    // loopback sessions launch as mock, everything else is a real match
+    #[serde(default)]
+    pub emulator_purpose: EmulatorPurpose,
+    // grace period used when the session stops the emulator
+    #[serde(default)]
+    pub emulator_shutdown: ShutdownOptions,
+    // working dir / env / backend override for the emulator
+    #[serde(default)]
+    pub launch: LaunchOptions,
+}
+
+// How many clock samples we want before declaring ourselves ready
+const MIN_CLOCK_SAMPLES: usize = 5;
+// Minimum time between agreeing on a start instant and launching
+const START_LEAD_MS: u64 = 2000;
+// How often the leader repeats an unacknowledged start proposal
+const START_RESEND: Duration = Duration::from_millis(100);
+// If the peer never answers the start handshake (older client, blocked control
+// traffic) we fall back to launching right away like we used to.
+const START_SYNC_TIMEOUT: Duration = Duration::from_secs(8);
+// How long the handshake waits for the peer's compatibility manifest before
+// the policy decides (Warn goes ahead, Block refuses). Shorter than the start
+// sync timeout so an older peer still gets a synchronized start.
+const MANIFEST_TIMEOUT: Duration = Duration::from_secs(3);
+// Link probes go out fast while we sample the clock, then settle down
+const PROBE_INTERVAL_SYNC: Duration = Duration::from_millis(100);
+const PROBE_INTERVAL: Duration = Duration::from_millis(250);
+// Our rematch opt-in is repeated every this many probes in case it got lost
+const OPT_IN_RESEND_PROBES: u64 = 8;
+const REMATCH_TIMEOUT: Duration = Duration::from_secs(5);
+const REMATCH_RESEND: Duration = Duration::from_millis(250);
+
+#[derive(Default)]
+struct StartSync {
+    clock: ClockSync,
+    peer_ready: bool,
+    peer_features: Vec<String>,
+    // scheduled launch instant in our local clock (epoch ms)
+    start_at: Option<u64>,
+    // leader only: the instant we proposed, in our clock
+    proposed_at: Option<u64>,
+    peer_acked: bool,
+    launched: bool,
+}
+
+impl StartSync {
+    // Keeps the clock samples, everything else is redone for the next launch
+    fn reset_for_rematch(&mut self) {
+        self.peer_ready = false;
+        self.start_at = None;
+        self.proposed_at = None;
+        self.peer_acked = false;
+        self.launched = false;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartSchedule {
+    pub match_id: Option<String>,
+    pub start_at: u64,
+    pub countdown_ms: u64,
+    pub clock_offset_ms: i64,
+    pub rtt_ms: u64,
+    pub leader: bool,
 }
 
-pub struct ProxyRuntime {
+pub struct ProxyRuntime<R: Runtime = Wry> {
     // Network
     local_sock: Arc<UdpSocket>, // random local port for holepunch + send to peer & server
+    prewarmed: bool, // adopted the lobby socket instead of binding one
     emu_listener: Arc<UdpSocket>, // bound to 7001 (or random) to receive from emulator
     opponent: Arc<Mutex<Option<SocketAddr>>>,
     keepalive_task: Mutex<Option<JoinHandle<()>>>,
-    // Emulator process
-    child: Mutex<Option<tokio::process::Child>>,
+    // Emulator process, owned by the EmulatorSupervisor
+    emulator: Mutex<Option<u64>>,
+    exit_watch: Mutex<Option<JoinHandle<()>>>,
+    launch: std::sync::Mutex<LaunchOverrides>,
+    rematch: Mutex<RematchState>,
+    set: Mutex<Option<SetTracker>>,
+    start_sync: Mutex<StartSync>,
+    sync_task: Mutex<Option<JoinHandle<()>>>,
+    compat: std::sync::Mutex<CompatState>,
+    // Stats
+    counters: ProxyCounters,
+    ggpo: Option<std::sync::Mutex<GgpoTelemetry>>,
+    started_at: Instant,
+    stats_task: Mutex<Option<JoinHandle<()>>>,
+    // Link quality / redundancy
+    link_probe: std::sync::Mutex<LinkProbe>,
+    probe_task: Mutex<Option<JoinHandle<()>>>,
+    redundancy_tx: Option<std::sync::Mutex<RedundantSender>>,
+    redundancy_rx: std::sync::Mutex<RedundantReceiver>,
+    // Relay fallback, when set it replaces the UDP path to the peer
+    tunnel: Mutex<Option<TunnelLink>>,
+    tunnel_task: Mutex<Option<JoinHandle<()>>>,
     // Control
     stop_tx: Mutex<Option<oneshot::Sender<()>>>,
     // Meta
-    app: AppHandle,
+    session_id: String,
+    app: AppHandle<R>,
     args: StartArgs,
 }
 
-impl ProxyRuntime {
-    async fn new(app: AppHandle, args: StartArgs) -> anyhow::Result<Arc<Self>> {
-        // 1) local socket: bind to 0.0.0.0:0
-        let local_sock = UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))).await?;
+impl<R: Runtime> ProxyRuntime<R> {
+    async fn new(
+        app: AppHandle<R>,
+        session_id: String,
+        args: StartArgs,
+        warm: Option<WarmSocket>,
+    ) -> anyhow::Result<Arc<Self>> {
+        // 1) local socket: reuse the prewarmed one (NAT mapping already open and
+        //    maybe paired with the opponent) or bind to 0.0.0.0:0
+        let (local_sock, opponent, prewarmed) = match warm {
+            Some(warm) => (warm.sock, warm.opponent, true),
+            None => (
+                Arc::new(UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))).await?),
+                None,
+                false,
+            ),
+        };
         // 2) emulator listener: bind to 127.0.0.1:port (default 7001)
         let emu_port = args.emulator_listen_port.unwrap_or(7001);
         let emu_listener =
@@ -85,19 +239,57 @@ impl ProxyRuntime {
                 Ok(s) => s,
                 Err(_e) => {
                     // fallback to random port if 7001 busy
-                    //app.emit_all("proxy-log", format!("Port {emu_port} busy")).ok();
-                    let _ = app.emit_to(EventTarget::any(), "proxy-log", format!("Port busy"));
+                    let _ = app.emit_to(EventTarget::any(), "proxy-log", format!("Port {emu_port} busy"));
                     UdpSocket::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).await?
                 }
             };
 
+        let set = args
+            .set_format
+            .as_deref()
+            .map(set_tracker::parse_format)
+            .transpose()
+            .map_err(|e| anyhow!(e))?
+            // both sides get the format from the match, so the same id
+            .map(|first_to| SetTracker::new(first_to, 0));
+
         let rt = Arc::new(Self {
-            local_sock: Arc::new(local_sock),
+            local_sock,
+            prewarmed,
             emu_listener: Arc::new(emu_listener),
-            opponent: Arc::new(Mutex::new(None)),
+            opponent: Arc::new(Mutex::new(opponent)),
             keepalive_task: Mutex::new(None),
-            child: Mutex::new(None),
+            emulator: Mutex::new(None),
+            exit_watch: Mutex::new(None),
+            launch: std::sync::Mutex::new(LaunchOverrides {
+                player: args.player,
+                delay: args.delay,
+            }),
+            rematch: Mutex::new(RematchState {
+                local_opt_in: args.allow_rematch,
+                ..Default::default()
+            }),
+            set: Mutex::new(set),
+            start_sync: Mutex::new(StartSync::default()),
+            sync_task: Mutex::new(None),
+            compat: std::sync::Mutex::new(CompatState::default()),
+            counters: ProxyCounters::default(),
+            ggpo: args
+                .inspect_ggpo
+                .then(|| std::sync::Mutex::new(GgpoTelemetry::default())),
+            started_at: Instant::now(),
+            stats_task: Mutex::new(None),
+            link_probe: std::sync::Mutex::new(LinkProbe::default()),
+            probe_task: Mutex::new(None),
+            redundancy_tx: args
+                .redundancy
+                .clone()
+                .map(|config| std::sync::Mutex::new(RedundantSender::new(config))),
+            redundancy_rx: std::sync::Mutex::new(RedundantReceiver::default()),
+            tunnel: Mutex::new(None),
+            tunnel_task: Mutex::new(None),
             stop_tx: Mutex::new(None),
+            session_id,
             app,
             args,
         });
@@ -106,8 +298,13 @@ impl ProxyRuntime {
     }
 
     async fn start(self: &Arc<Self>) -> anyhow::Result<()> {
-        // send initial punch message to server: { uid, peerUid, kill:false }
-        self.send_to_server(false).await?;
+        let force_tunnel = self.args.tunnel.as_ref().is_some_and(|t| t.force);
+        if force_tunnel {
+            self.start_tunnel().await?;
+        } else {
+            // send initial punch message to server: { uid, peerUid, kill:false }
+            self.send_to_server(false).await?;
+        }
 
         // spawn the two proxy loops
         let (stop_tx, stop_rx) = oneshot::channel::<()>();
@@ -116,8 +313,15 @@ impl ProxyRuntime {
         self.spawn_local_reader(stop_rx).await?;
         self.spawn_emulator_reader().await?;
         self.spawn_handshake_watchdog().await?;
+        self.spawn_stats_reporter().await;
+        self.spawn_manifest_build();
+        // a prewarmed socket may already be paired, no need to wait for the
+        // server to answer the punch again
+        if self.opponent.lock().await.is_some() {
+            self.on_peer_connected().await;
+        }
 
-        // we don't start emulator immediately; we mimic your behavior: start on first send to B.
+        // the emulator is launched once both peers agree on a start instant, see spawn_start_sync
         Ok(())
     }
 
@@ -127,7 +331,6 @@ impl ProxyRuntime {
     ) -> anyhow::Result<()> {
         let this = Arc::clone(self);
         let sock = Arc::clone(&self.local_sock);
-        let emu_listener = Arc::clone(&self.emu_listener);
 
         tokio::spawn(async move {
             let mut buf = vec![0u8; 65535];
@@ -136,27 +339,8 @@ impl ProxyRuntime {
                 tokio::select! {
                     r = sock.recv_from(&mut buf) => {
                         match r {
-                            Ok((n, _from)) => {
-                                let slice = &buf[..n];
-                                let as_str = std::str::from_utf8(slice).unwrap_or("");
-
-                                // Learn opponent addr
-                                if let Ok(env) = serde_json::from_slice::<OpponentEnvelope>(slice) {
-                                    if let Ok(addr) = format!("{}:{}", env.peer.address, env.peer.port).parse::<SocketAddr>() {
-                                        *this.opponent.lock().await = Some(addr);
-                                        let _ = this.send_to_peer(b"ping").await;
-                                    }
-                                }
-
-                                // Keepalive or forward to emulator
-                                if as_str == "ping" || as_str.contains("\"port\"") {
-                                    this.ensure_keepalive().await; // takes &Arc<Self>
-                                } else {
-                                    let emu_game_port = this.args.emulator_game_port.unwrap_or(7000);
-                                    let _ = emu_listener
-                                        .send_to(slice, SocketAddr::from((Ipv4Addr::LOCALHOST, emu_game_port)))
-                                        .await;
-                                }
+                            Ok((n, from)) => {
+                                this.handle_peer_datagram(&buf[..n], Some(from), false).await;
                             }
                             Err(_e) => {
                                 let _ = this.app.emit_to(
@@ -178,6 +362,141 @@ impl ProxyRuntime {
         Ok(())
     }
 
+    // Everything that arrives from the peer side, over UDP (`from` is the sender)
+    // or through the relay tunnel (`stale` is set for datagrams that took too long).
+    async fn handle_peer_datagram(self: &Arc<Self>, slice: &[u8], from: Option<SocketAddr>, stale: bool) {
+        if let Some(msg) = control::decode(slice) {
+            self.handle_control(msg, from).await;
+            return;
+        }
+        if control::is_control(slice) {
+            // control message from a newer client we don't understand
+            return;
+        }
+
+        if redundancy::is_frame(slice) {
+            if stale {
+                self.counters.record_stale();
+                return;
+            }
+            let payloads = self.redundancy_rx.lock().unwrap().unwrap(slice);
+            for payload in payloads {
+                self.counters.record_from_peer(payload.len());
+                self.deliver_to_emulator(&self.emu_listener, payload).await;
+            }
+            return;
+        }
+
+        let as_str = std::str::from_utf8(slice).unwrap_or("");
+
+        // Learn opponent addr
+        if let Ok(env) = serde_json::from_slice::<OpponentEnvelope>(slice) {
+            if let Ok(addr) = format!("{}:{}", env.peer.address, env.peer.port).parse::<SocketAddr>() {
+                *self.opponent.lock().await = Some(addr);
+                self.on_peer_connected().await;
+            }
+        }
+
+        // Keepalive or forward to emulator
+        if as_str == "ping" || as_str.contains("\"port\"") {
+            self.ensure_keepalive().await; // takes &Arc<Self>
+            return;
+        }
+        // only emulator traffic counts, control and redundancy framing don't
+        self.counters.record_from_peer(slice.len());
+        if stale {
+            self.counters.record_stale();
+        } else {
+            self.deliver_to_emulator(&self.emu_listener, slice).await;
+        }
+    }
+
+    async fn on_peer_connected(self: &Arc<Self>) {
+        let _ = self.send_to_peer(b"ping").await;
+        self.spawn_link_probe().await;
+        self.spawn_start_sync().await;
+    }
+
+    async fn peer_connected(&self) -> bool {
+        self.opponent.lock().await.is_some() || self.tunnel.lock().await.is_some()
+    }
+
+    // Knowing the peer's address doesn't mean UDP gets through, a clock pong
+    // means it does both ways
+    async fn peer_answering(&self) -> bool {
+        self.start_sync.lock().await.clock.samples() > 0 || self.tunnel.lock().await.is_some()
+    }
+
+    // Switches the peer path over to the relay. Inbound frames are fed through
+    // the same handling as UDP datagrams.
+    async fn start_tunnel(self: &Arc<Self>) -> anyhow::Result<()> {
+        let config = self
+            .args
+            .tunnel
+            .clone()
+            .ok_or_else(|| anyhow!("no tunnel relay configured"))?;
+        // held across the connect so the watchdog and a UseTunnel from the peer
+        // can't both open a link
+        let mut tunnel = self.tunnel.lock().await;
+        if tunnel.is_some() {
+            return Ok(());
+        }
+        // tell the peer before leaving UDP, or it keeps sending where we no
+        // longer listen. Repeated since it's a single lossy datagram.
+        if let Some(addr) = *self.opponent.lock().await {
+            let notice = control::encode(&ControlMessage::UseTunnel);
+            for _ in 0..3 {
+                let _ = self.local_sock.send_to(&notice, addr).await;
+            }
+        }
+        let hello = TunnelHello {
+            uid: self.args.my_uid.clone(),
+            peer_uid: self.args.peer_uid.clone(),
+            match_id: self.args.match_id.clone(),
+        };
+        let (link, inbound) = TunnelLink::connect(&config, &hello).await?;
+        *tunnel = Some(link);
+        drop(tunnel);
+        let _ = self.app.emit_to(
+            EventTarget::any(),
+            "proxy-log",
+            format!("UDP unavailable, tunneling through relay {}", config.relay),
+        );
+
+        let handle = tokio::spawn(Arc::clone(self).read_tunnel(inbound, config.stale_after_ms));
+        *self.tunnel_task.lock().await = Some(handle);
+
+        self.on_peer_connected().await;
+        Ok(())
+    }
+
+    // Boxed so the reader -> handle_control -> start_tunnel cycle has a
+    // nameable, Send future type
+    fn read_tunnel(
+        self: Arc<Self>,
+        mut inbound: mpsc::UnboundedReceiver<TunnelDatagram>,
+        stale_after_ms: u64,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move {
+            while let Some(datagram) = inbound.recv().await {
+                // without a clock sample the peer's timestamps can't be compared
+                // to ours, so nothing is dropped as stale until then
+                let stale = {
+                    let sync = self.start_sync.lock().await;
+                    sync.clock.samples() > 0
+                        && control::now_ms().saturating_sub(sync.clock.to_local(datagram.sent_at))
+                            > stale_after_ms
+                };
+                self.handle_peer_datagram(&datagram.payload, None, stale).await;
+            }
+            let _ = self.app.emit_to(
+                EventTarget::any(),
+                "proxy-log",
+                "tunnel connection closed".to_string(),
+            );
+        })
+    }
+
     async fn spawn_emulator_reader(self: &Arc<Self>) -> anyhow::Result<()> {
         let this = Arc::clone(self);
         let emu_listener = Arc::clone(&self.emu_listener);
@@ -188,7 +507,11 @@ impl ProxyRuntime {
                 match emu_listener.recv_from(&mut buf).await {
                     Ok((n, _from)) => {
                         let payload = &buf[..n];
-                        let _ = this.send_to_peer(payload).await; // ✅ use `this`
+                        this.counters.record_from_emulator();
+                        if let Some(ggpo) = &this.ggpo {
+                            ggpo.lock().unwrap().observe_outgoing(payload);
+                        }
+                        let _ = this.send_emulator_payload(payload).await;
                     }
                     Err(_e) => {
                         let _ = this.app.emit_to(
@@ -206,14 +529,65 @@ impl ProxyRuntime {
         Ok(())
     }
 
+    async fn deliver_to_emulator(&self, emu_listener: &UdpSocket, payload: &[u8]) {
+        if let Some(ggpo) = &self.ggpo {
+            ggpo.lock().unwrap().observe_incoming(payload);
+        }
+        self.counters.record_to_emulator();
+        let emu_game_port = self.args.emulator_game_port.unwrap_or(7000);
+        let _ = emu_listener
+            .send_to(payload, SocketAddr::from((Ipv4Addr::LOCALHOST, emu_game_port)))
+            .await;
+    }
+
+    async fn peer_supports(&self, feature: &str) -> bool {
+        self.start_sync
+            .lock()
+            .await
+            .peer_features
+            .iter()
+            .any(|f| f == feature)
+    }
+
+    async fn send_emulator_payload(&self, payload: &[u8]) -> anyhow::Result<()> {
+        if let Some(tx) = &self.redundancy_tx {
+            if self.peer_supports(redundancy::FEATURE).await {
+                let datagrams = tx.lock().unwrap().wrap(payload);
+                for datagram in datagrams {
+                    self.send_to_peer(&datagram).await?;
+                }
+                return Ok(());
+            }
+        }
+        self.send_to_peer(payload).await
+    }
+
     async fn spawn_handshake_watchdog(self: &Arc<Self>) -> anyhow::Result<()> {
         let this = Arc::clone(self);
         tokio::spawn(async move {
             let mut waited = 0u64;
+            let fallback_after = this.args.tunnel.as_ref().map(|t| t.fallback_after_secs);
             loop {
-                {
-                    if this.opponent.lock().await.is_some() {
-                        break;
+                // with a relay to fall back to, only a working link counts
+                let connected = if fallback_after.is_some() {
+                    this.peer_answering().await
+                } else {
+                    this.peer_connected().await
+                };
+                if connected {
+                    break;
+                }
+                if fallback_after.is_some_and(|after| waited >= after) {
+                    match this.start_tunnel().await {
+                        Ok(()) => break,
+                        Err(e) => {
+                            let _ = this.app.emit_to(
+                                EventTarget::any(),
+                                "proxy-log",
+                                format!("tunnel fallback failed: {e}"),
+                            );
+                            waited = 15;
+                        }
                     }
                 }
                 if waited >= 15 {
@@ -229,7 +603,7 @@ impl ProxyRuntime {
                         }),
                     );
                     let _ = this.send_to_server(true).await;
-                    let _ = this.stop().await;
+                    this.shutdown().await;
                     break;
                 }
                 waited += 1;
@@ -239,6 +613,747 @@ impl ProxyRuntime {
         Ok(())
     }
 
+    fn is_start_leader(&self) -> bool {
+        self.launch.lock().unwrap().player == 1
+    }
+
+    async fn send_control(&self, msg: &ControlMessage) -> anyhow::Result<()> {
+        self.send_to_peer(&control::encode(msg)).await
+    }
+
+    async fn handle_control(self: &Arc<Self>, msg: ControlMessage, from: Option<SocketAddr>) {
+        match msg {
+            ControlMessage::ClockPing { sent_at } => {
+                // reply straight to the sender, we may not have the server envelope yet
+                let pong = ControlMessage::ClockPong {
+                    sent_at,
+                    received_at: control::now_ms(),
+                };
+                let _ = match from {
+                    Some(addr) => self
+                        .local_sock
+                        .send_to(&control::encode(&pong), addr)
+                        .await
+                        .map(|_| ())
+                        .map_err(anyhow::Error::from),
+                    None => self.send_control(&pong).await,
+                };
+            }
+            ControlMessage::ClockPong {
+                sent_at,
+                received_at,
+            } => {
+                let now = control::now_ms();
+                self.link_probe.lock().unwrap().on_pong(sent_at, now);
+                self.start_sync
+                    .lock()
+                    .await
+                    .clock
+                    .record(sent_at, received_at, now);
+            }
+            ControlMessage::Ready { features } => {
+                let mut sync = self.start_sync.lock().await;
+                sync.peer_ready = true;
+                sync.peer_features = features;
+            }
+            ControlMessage::Start { at } => {
+                if self.is_start_leader() {
+                    return;
+                }
+                let _ = self.send_control(&ControlMessage::StartAck { at }).await;
+                let mut sync = self.start_sync.lock().await;
+                if sync.start_at.is_none() {
+                    let local_at = sync.clock.to_local(at);
+                    sync.start_at = Some(local_at);
+                    drop(sync);
+                    self.emit_start_schedule(local_at).await;
+                }
+            }
+            ControlMessage::StartAck { at } => {
+                let mut sync = self.start_sync.lock().await;
+                if sync.proposed_at == Some(at) {
+                    sync.peer_acked = true;
+                }
+            }
+            ControlMessage::RematchOptIn { enabled } => {
+                self.rematch.lock().await.peer_opt_in = enabled;
+            }
+            ControlMessage::Rematch {
+                id,
+                swap_sides,
+                delay,
+            } => {
+                self.handle_rematch_request(id, RematchOptions { swap_sides, delay })
+                    .await;
+            }
+            ControlMessage::RematchAccept { id } => {
+                if let Some(pending) = self.take_pending_rematch(id).await {
+                    self.begin_rematch(pending.options, false).await;
+                    let _ = pending.answer.send(Ok(()));
+                }
+            }
+            ControlMessage::RematchDecline { id, reason } => {
+                if let Some(pending) = self.take_pending_rematch(id).await {
+                    let _ = pending.answer.send(Err(reason));
+                }
+            }
+            ControlMessage::Manifest { manifest } => {
+                self.compat.lock().unwrap().peer = Some(manifest);
+            }
+            ControlMessage::Bye { reason } => {
+                self.rematch.lock().await.peer_opt_in = false;
+                let _ = self.app.emit_to(
+                    EventTarget::any(),
+                    "proxy:peer-left",
+                    json!({ "sessionId": self.session_id, "reason": reason }),
+                );
+                let _ = self.app.emit_to(EventTarget::any(), "sendAlert", json!({
+                    "type": "info",
+                    "message": { "title": "Opponent left", "description": reason }
+                }));
+            }
+            ControlMessage::UseTunnel => {
+                if self.args.tunnel.is_none() {
+                    let _ = self.app.emit_to(
+                        EventTarget::any(),
+                        "proxy-log",
+                        "peer moved to the relay but no tunnel is configured here".to_string(),
+                    );
+                    return;
+                }
+                // connecting can take a while, keep the reader going meanwhile.
+                // start_tunnel does nothing if we're already on the relay.
+                let this = Arc::clone(self);
+                tokio::spawn(async move {
+                    if let Err(e) = this.start_tunnel().await {
+                        let _ = this.app.emit_to(
+                            EventTarget::any(),
+                            "proxy-log",
+                            format!("following the peer to the relay failed: {e}"),
+                        );
+                    }
+                });
+            }
+            ControlMessage::Incompatible { reason } => {
+                if self.start_sync.lock().await.launched {
+                    return;
+                }
+                self.emit_compat_report();
+                let _ = self.app.emit_to(EventTarget::any(), "sendAlert", json!({
+                    "type": "error",
+                    "message": { "title": "Opponent blocked the match", "description": reason }
+                }));
+                let _ = self.send_to_server(true).await;
+                self.shutdown().await;
+            }
+            ControlMessage::SetScore {
+                set_id,
+                first_to,
+                games,
+                local_wins,
+                peer_wins,
+            } => {
+                let view = ScoreView {
+                    games,
+                    local_wins,
+                    peer_wins,
+                };
+                self.handle_peer_set_score(set_id, first_to, view).await;
+            }
+        }
+    }
+
+    pub async fn start_set(&self, first_to: u32) -> SetStatus {
+        let status = {
+            let mut set = self.set.lock().await;
+            // newer than anything either side started before, the peer
+            // replaces its set when it sees this id
+            let id = set
+                .as_ref()
+                .map_or(0, |tracker| tracker.id + 1)
+                .max(control::now_ms());
+            let tracker = set.insert(SetTracker::new(first_to, id));
+            tracker.status(&self.session_id, self.args.match_id.clone())
+        };
+        self.send_set_score().await;
+        self.emit_set_event("set:update", &status);
+        status
+    }
+
+    // Counts one finished game from the stats the Lua script wrote. Returns
+    // None when no set is running for this session.
+    pub async fn record_set_game(
+        &self,
+        stats: &serde_json::Value,
+    ) -> Result<Option<SetStatus>, String> {
+        let local_player = self.launch.lock().unwrap().player;
+        let (status, events) = {
+            let mut set = self.set.lock().await;
+            let Some(tracker) = set.as_mut() else {
+                return Ok(None);
+            };
+            let events = set_tracker::record_stats(tracker, stats, local_player)?;
+            (tracker.status(&self.session_id, self.args.match_id.clone()), events)
+        };
+        if !events.is_empty() {
+            self.send_set_score().await;
+        }
+        for event in events {
+            self.emit_set_event(event, &status);
+        }
+        Ok(Some(status))
+    }
+
+    pub async fn set_status(&self) -> Option<SetStatus> {
+        self.set
+            .lock()
+            .await
+            .as_ref()
+            .map(|tracker| tracker.status(&self.session_id, self.args.match_id.clone()))
+    }
+
+    async fn send_set_score(&self) {
+        let msg = {
+            let set = self.set.lock().await;
+            let Some(tracker) = set.as_ref() else {
+                return;
+            };
+            let view = tracker.view();
+            ControlMessage::SetScore {
+                set_id: tracker.id,
+                first_to: tracker.first_to,
+                games: view.games,
+                local_wins: view.local_wins,
+                peer_wins: view.peer_wins,
+            }
+        };
+        let _ = self.send_control(&msg).await;
+    }
+
+    async fn handle_peer_set_score(&self, set_id: u64, first_to: u32, view: ScoreView) {
+        let status = {
+            let mut set = self.set.lock().await;
+            // the peer started a set we don't know about yet (or restarted
+            // one), follow it
+            let adopted = match set_tracker::follow_peer(&mut set, first_to, set_id) {
+                PeerSet::Outdated => return,
+                PeerSet::Adopted => true,
+                PeerSet::Current => false,
+            };
+            let Some(tracker) = set.as_mut() else {
+                return;
+            };
+            if tracker.first_to != first_to {
+                let _ = self.app.emit_to(
+                    EventTarget::any(),
+                    "proxy-log",
+                    format!("set format mismatch: ours FT{}, peer FT{first_to}", tracker.first_to),
+                );
+            }
+            (tracker.set_peer_view(view) || adopted)
+                .then(|| tracker.status(&self.session_id, self.args.match_id.clone()))
+        };
+        if let Some(status) = status {
+            self.emit_set_event("set:update", &status);
+        }
+        self.check_set_completion().await;
+    }
+
+    async fn check_set_completion(&self) {
+        let status = {
+            let mut set = self.set.lock().await;
+            let Some(tracker) = set.as_mut() else {
+                return;
+            };
+            if !tracker.take_completion() {
+                return;
+            }
+            tracker.status(&self.session_id, self.args.match_id.clone())
+        };
+        self.emit_set_event("set:complete", &status);
+    }
+
+    fn emit_set_event(&self, event: &str, status: &SetStatus) {
+        let _ = self.app.emit_to(EventTarget::any(), event, status.clone());
+    }
+
+    pub async fn set_rematch_opt_in(&self, enabled: bool) {
+        self.rematch.lock().await.local_opt_in = enabled;
+        let _ = self
+            .send_control(&ControlMessage::RematchOptIn { enabled })
+            .await;
+    }
+
+    async fn rematch_blocker(&self) -> Option<String> {
+        let state = self.rematch.lock().await;
+        if !state.local_opt_in {
+            return Some("rematch not enabled on this side".to_string());
+        }
+        if !state.peer_opt_in {
+            return Some("opponent has not opted in to rematches".to_string());
+        }
+        drop(state);
+        if self.emulator.lock().await.is_some() {
+            return Some("emulator is still running".to_string());
+        }
+        None
+    }
+
+    // Asks the peer for a rematch and waits for the answer. The request is
+    // resent until answered since control messages ride on plain UDP.
+    pub async fn request_rematch(&self, options: RematchOptions) -> Result<(), String> {
+        if let Some(reason) = self.rematch_blocker().await {
+            return Err(reason);
+        }
+        let (answer, mut rx) = oneshot::channel();
+        let id = {
+            let mut state = self.rematch.lock().await;
+            state.next_id += 1;
+            // the options are applied locally once the peer accepts
+            state.pending = Some(PendingRematch {
+                id: state.next_id,
+                options,
+                answer,
+            });
+            state.next_id
+        };
+        let request = ControlMessage::Rematch {
+            id,
+            swap_sides: options.swap_sides,
+            delay: options.delay,
+        };
+
+        let deadline = tokio::time::sleep(REMATCH_TIMEOUT);
+        tokio::pin!(deadline);
+        let mut resend = interval(REMATCH_RESEND);
+        loop {
+            tokio::select! {
+                answer = &mut rx => {
+                    return answer.unwrap_or_else(|_| Err("rematch request dropped".to_string()));
+                }
+                _ = resend.tick() => {
+                    let _ = self.send_control(&request).await;
+                }
+                _ = &mut deadline => {
+                    self.take_pending_rematch(id).await;
+                    return Err("opponent did not answer the rematch request".to_string());
+                }
+            }
+        }
+    }
+
+    async fn take_pending_rematch(&self, id: u64) -> Option<PendingRematch> {
+        let mut state = self.rematch.lock().await;
+        if state.pending.as_ref().is_none_or(|pending| pending.id != id) {
+            return None;
+        }
+        state.pending.take()
+    }
+
+    async fn handle_rematch_request(self: &Arc<Self>, id: u64, options: RematchOptions) {
+        if self.rematch.lock().await.last_accepted == Some(id) {
+            let _ = self.send_control(&ControlMessage::RematchAccept { id }).await;
+            return;
+        }
+        if let Some(reason) = self.rematch_blocker().await {
+            let _ = self
+                .send_control(&ControlMessage::RematchDecline { id, reason })
+                .await;
+            return;
+        }
+        self.rematch.lock().await.last_accepted = Some(id);
+        let _ = self.send_control(&ControlMessage::RematchAccept { id }).await;
+        self.begin_rematch(options, true).await;
+    }
+
+    // Applies the new sides / delay and reruns the start handshake, which
+    // launches the emulator again at the agreed instant.
+    async fn begin_rematch(self: &Arc<Self>, options: RematchOptions, requested_by_peer: bool) {
+        let launch = {
+            let mut launch = self.launch.lock().unwrap();
+            launch.apply(&options);
+            *launch
+        };
+        self.start_sync.lock().await.reset_for_rematch();
+        if let Some(h) = self.sync_task.lock().await.take() {
+            h.abort();
+        }
+        let _ = self.app.emit_to(
+            EventTarget::any(),
+            "proxy:rematch",
+            RematchEvent {
+                session_id: self.session_id.clone(),
+                player: launch.player,
+                delay: launch.delay,
+                requested_by_peer,
+            },
+        );
+        self.spawn_start_sync().await;
+    }
+
+    // Follows the emulator so the UI learns about exits and can offer a rematch
+    fn spawn_exit_watcher(self: &Arc<Self>, mut handle: EmulatorHandle) -> JoinHandle<()> {
+        let this = Arc::clone(self);
+        tokio::spawn(async move {
+            let exit = handle.wait().await;
+            {
+                let mut current = this.emulator.lock().await;
+                if *current != Some(handle.id) {
+                    // killed through stop / kill_emulator_only
+                    return;
+                }
+                current.take();
+            }
+            let rematch_available = this.rematch.lock().await.available();
+            let _ = this.app.emit_to(
+                EventTarget::any(),
+                "proxy:emulator-exited",
+                json!({
+                    "sessionId": this.session_id,
+                    "code": exit.and_then(|e| e.code),
+                    "rematchAvailable": rematch_available,
+                }),
+            );
+        })
+    }
+
+    // Hashes the emulator, ROM zip and Lua scripts off the runtime, the start
+    // sync holds back Ready until this lands.
+    fn spawn_manifest_build(self: &Arc<Self>) {
+        let this = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut args = this.args.emulator_args.clone();
+            let _ = resolve_lua_args(&this.app, &mut args);
+            let emulator = resolve_emulator_path(&this.app, &this.args.emulator_path)
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| this.args.emulator_path.clone());
+            let game = this.args.game_name.clone();
+            let built = tokio::task::spawn_blocking(move || {
+                compat::build_manifest(&emulator, &args, game.as_deref())
+            })
+            .await;
+            if let Ok(manifest) = built {
+                this.compat.lock().unwrap().local = Some(manifest.clone());
+                // straight out instead of waiting for the next sync tick, the
+                // sync loop repeats it until the start is agreed on
+                let _ = this.send_control(&ControlMessage::Manifest { manifest }).await;
+            }
+        });
+    }
+
+    fn compat_gate(&self, timed_out: bool) -> CompatGate {
+        self.compat
+            .lock()
+            .unwrap()
+            .gate(self.args.compat_policy, timed_out)
+    }
+
+    // Emits the manifest diff once per session, mismatches under the warn
+    // policy also raise an alert since the match goes ahead anyway.
+    fn emit_compat_report(&self) {
+        let policy = self.args.compat_policy;
+        if policy == CompatPolicy::Off {
+            return;
+        }
+        let report = {
+            let mut compat = self.compat.lock().unwrap();
+            if compat.reported {
+                return;
+            }
+            let Some(report) = compat.report(&self.session_id, policy) else {
+                return;
+            };
+            compat.reported = true;
+            report
+        };
+        if !report.compatible && !report.blocked {
+            let description = if report.peer.is_none() {
+                "Opponent did not send a compatibility manifest".to_string()
+            } else {
+                let fields: Vec<&str> = report.diffs.iter().map(|d| d.field).collect();
+                format!("Mismatched {}, the match may desync", fields.join(", "))
+            };
+            let _ = self.app.emit_to(EventTarget::any(), "sendAlert", json!({
+                "type": "warning",
+                "message": { "title": "Opponent setup differs", "description": description }
+            }));
+        }
+        let _ = self
+            .app
+            .emit_to(EventTarget::any(), "proxy:compat-report", report);
+    }
+
+    async fn block_launch(&self, reason: String) {
+        self.emit_compat_report();
+        let _ = self
+            .send_control(&ControlMessage::Incompatible {
+                reason: reason.clone(),
+            })
+            .await;
+        let _ = self.app.emit_to(EventTarget::any(), "sendAlert", json!({
+            "type": "error",
+            "message": { "title": "Match blocked", "description": format!("Incompatible with opponent: {reason}") }
+        }));
+        let _ = self.send_to_server(true).await;
+        self.shutdown().await;
+    }
+
+    pub fn compat_report(&self) -> Option<compat::CompatReport> {
+        self.compat
+            .lock()
+            .unwrap()
+            .report(&self.session_id, self.args.compat_policy)
+    }
+
+    async fn emit_start_schedule(&self, start_at: u64) {
+        let sample = self.start_sync.lock().await.clock.best();
+        let schedule = StartSchedule {
+            match_id: self.args.match_id.clone(),
+            start_at,
+            countdown_ms: start_at.saturating_sub(control::now_ms()),
+            clock_offset_ms: sample.map(|s| s.offset_ms).unwrap_or(0),
+            rtt_ms: sample.map(|s| s.rtt_ms).unwrap_or(0),
+            leader: self.is_start_leader(),
+        };
+        let _ = self
+            .app
+            .emit_to(EventTarget::any(), "proxy:start-scheduled", schedule);
+    }
+
+    // Runs the ready/start exchange: sample the peer clock, announce readiness,
+    // then the leader (player 1) proposes a start instant the follower converts
+    // into its own clock. Both sides launch the emulator at that instant.
+    async fn spawn_start_sync(self: &Arc<Self>) {
+        let mut guard = self.sync_task.lock().await;
+        if guard.is_some() {
+            return;
+        }
+
+        let this = Arc::clone(self);
+        let handle = tokio::spawn(async move {
+            let began = tokio::time::Instant::now();
+            let mut ticker = interval(Duration::from_millis(100));
+            loop {
+                ticker.tick().await;
+                let now = control::now_ms();
+
+                let (samples, peer_ready, start_at) = {
+                    let sync = this.start_sync.lock().await;
+                    (sync.clock.samples(), sync.peer_ready, sync.start_at)
+                };
+
+                if let Some(at) = start_at {
+                    // the poll only notices the agreed instant, the launch itself
+                    // waits for it exactly so neither side adds tick jitter
+                    let launch_at = tokio::time::Instant::now()
+                        + Duration::from_millis(at.saturating_sub(control::now_ms()));
+                    while tokio::time::Instant::now() < launch_at {
+                        let resend = {
+                            let sync = this.start_sync.lock().await;
+                            sync.proposed_at.filter(|_| !sync.peer_acked)
+                        };
+                        let Some(proposed) = resend else {
+                            break;
+                        };
+                        let _ = this.send_control(&ControlMessage::Start { at: proposed }).await;
+                        sleep_until(launch_at.min(tokio::time::Instant::now() + START_RESEND)).await;
+                    }
+                    sleep_until(launch_at).await;
+                    break;
+                }
+
+                if began.elapsed() >= START_SYNC_TIMEOUT {
+                    if let CompatGate::Blocked(reason) = this.compat_gate(true) {
+                        this.block_launch(reason).await;
+                        return;
+                    }
+                    this.emit_compat_report();
+                    let _ = this.app.emit_to(
+                        EventTarget::any(),
+                        "proxy-log",
+                        "start sync timed out, launching emulator now".to_string(),
+                    );
+                    break;
+                }
+
+                // clock samples come from the pings sent by spawn_link_probe
+                if samples < MIN_CLOCK_SAMPLES {
+                    continue;
+                }
+
+                // manifests go out until the start instant is agreed on, the
+                // peer only answers Ready once it has ours
+                let manifest = this.compat.lock().unwrap().local.clone();
+                // the deadline only runs against the peer, our own hashing
+                // may take until the start sync gives up
+                let manifest_overdue = manifest.is_some() && began.elapsed() >= MANIFEST_TIMEOUT;
+                if let Some(manifest) = manifest {
+                    let _ = this.send_control(&ControlMessage::Manifest { manifest }).await;
+                }
+                match this.compat_gate(manifest_overdue) {
+                    CompatGate::Waiting => continue,
+                    CompatGate::Blocked(reason) => {
+                        this.block_launch(reason).await;
+                        return;
+                    }
+                    CompatGate::Clear => this.emit_compat_report(),
+                }
+
+                let ready = ControlMessage::Ready {
+                    features: vec![redundancy::FEATURE.to_string()],
+                };
+                let _ = this.send_control(&ready).await;
+
+                if this.is_start_leader() && peer_ready {
+                    let rtt = this
+                        .start_sync
+                        .lock()
+                        .await
+                        .clock
+                        .best()
+                        .map(|s| s.rtt_ms)
+                        .unwrap_or(0);
+                    let at = now + START_LEAD_MS.max(rtt * 4);
+                    {
+                        let mut sync = this.start_sync.lock().await;
+                        sync.proposed_at = Some(at);
+                        sync.start_at = Some(at);
+                    }
+                    let _ = this.send_control(&ControlMessage::Start { at }).await;
+                    this.emit_start_schedule(at).await;
+                }
+            }
+
+            this.launch_scheduled_emulator().await;
+        });
+        *guard = Some(handle);
+    }
+
+    // Clock pings double as link probes: they feed the start handshake's clock
+    // sync and give us RTT / loss for the stats and the redundancy auto mode.
+    async fn spawn_link_probe(self: &Arc<Self>) {
+        let mut guard = self.probe_task.lock().await;
+        if guard.is_some() {
+            return;
+        }
+
+        let this = Arc::clone(self);
+        let handle = tokio::spawn(async move {
+            let mut probes = 0u64;
+            loop {
+                probes += 1;
+                let launched = this.start_sync.lock().await.launched;
+                tokio::time::sleep(if launched {
+                    PROBE_INTERVAL
+                } else {
+                    PROBE_INTERVAL_SYNC
+                })
+                .await;
+
+                let now = control::now_ms();
+                this.link_probe.lock().unwrap().on_sent(now);
+                let _ = this
+                    .send_control(&ControlMessage::ClockPing { sent_at: now })
+                    .await;
+
+                if probes.is_multiple_of(OPT_IN_RESEND_PROBES) && this.rematch.lock().await.local_opt_in {
+                    let _ = this
+                        .send_control(&ControlMessage::RematchOptIn { enabled: true })
+                        .await;
+                }
+                // the score is resent too, and a finished set the peer never
+                // confirmed completes here once the agreement timeout passes
+                if probes.is_multiple_of(OPT_IN_RESEND_PROBES) {
+                    this.send_set_score().await;
+                    this.check_set_completion().await;
+                }
+
+                if let Some(tx) = &this.redundancy_tx {
+                    let loss = this.link_probe.lock().unwrap().loss_ratio(now);
+                    let changed = tx.lock().unwrap().update_loss(loss);
+                    if let Some(active) = changed {
+                        let _ = this.app.emit_to(
+                            EventTarget::any(),
+                            "proxy-log",
+                            format!(
+                                "redundancy {} (loss {:.1}%)",
+                                if active { "enabled" } else { "disabled" },
+                                loss * 100.0
+                            ),
+                        );
+                    }
+                }
+            }
+        });
+        *guard = Some(handle);
+    }
+
+    async fn launch_scheduled_emulator(self: &Arc<Self>) {
+        {
+            let mut sync = self.start_sync.lock().await;
+            if sync.launched {
+                return;
+            }
+            sync.launched = true;
+        }
+        if let Err(e) = self.start_emulator().await {
+            let _ = self.app.emit_to(EventTarget::any(), "sendAlert", json!({
+                "type": "error",
+                "message": { "title": "Emulator failed to open", "description": e.to_string() }
+            }));
+            // notify server we're killing
+            let _ = self.send_to_server(true).await;
+            self.shutdown().await;
+        }
+    }
+
+    pub async fn stats(&self) -> ProxyStats {
+        let mut stats = ProxyStats::from_counters(&self.counters);
+        stats.session_id = self.session_id.clone();
+        stats.match_id = self.args.match_id.clone();
+        stats.peer = self.opponent.lock().await.map(|addr| addr.to_string());
+        stats.prewarmed = self.prewarmed;
+        stats.uptime_ms = self.started_at.elapsed().as_millis() as u64;
+        stats.transport = match (&*self.tunnel.lock().await, &self.args.tunnel) {
+            (Some(_), Some(config)) => config.transport.into(),
+            _ => "udp",
+        };
+        stats.ggpo = self
+            .ggpo
+            .as_ref()
+            .map(|ggpo| ggpo.lock().unwrap().snapshot());
+        stats.link = Some(self.link_probe.lock().unwrap().stats(control::now_ms()));
+        if let Some(id) = *self.emulator.lock().await {
+            stats.emulator_usage = self
+                .app
+                .state::<EmulatorSupervisor>()
+                .recent_usage(id, resource_usage::STATS_WINDOW)
+                .unwrap_or_default();
+        }
+        // the peer may be sending frames even if we don't have redundancy configured
+        let sending = self.redundancy_tx.as_ref().map(|tx| tx.lock().unwrap().stats());
+        let rx = self.redundancy_rx.lock().unwrap();
+        if sending.is_some() || rx.has_received() {
+            let mut redundancy = sending.unwrap_or_default();
+            rx.fill_stats(&mut redundancy);
+            stats.redundancy = Some(redundancy);
+        }
+        stats
+    }
+
+    async fn spawn_stats_reporter(self: &Arc<Self>) {
+        let this = Arc::clone(self);
+        let handle = tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(1));
+            loop {
+                ticker.tick().await;
+                let stats = this.stats().await;
+                let _ = this.app.emit_to(EventTarget::any(), "proxy:stats", stats);
+            }
+        });
+        *self.stats_task.lock().await = Some(handle);
+    }
+
     async fn ensure_keepalive(self: &Arc<Self>) {
         let mut guard = self.keepalive_task.lock().await;
         if guard.is_some() {
@@ -257,41 +1372,39 @@ impl ProxyRuntime {
     }
 
     async fn send_to_peer(&self, payload: &[u8]) -> anyhow::Result<()> {
-        let opp = self.opponent.lock().await.clone();
+        if let Some(tunnel) = &*self.tunnel.lock().await {
+            tunnel.send(payload)?;
+            self.counters.record_to_peer(payload.len());
+            return Ok(());
+        }
+        let opp = *self.opponent.lock().await;
         if let Some(addr) = opp {
-            // start emulator on first real send if not started
-            if self.child.lock().await.is_none() {
-                if let Err(e) = self.start_emulator().await {
-                    let _ = self.app.emit_to(EventTarget::any(), "sendAlert", serde_json::json!({
-            "type": "error",
-            "message": { "title": "Emulator failed to open", "description": e.to_string() }
-          }));
-                    // notify server we're killing
-                    let _ = self.send_to_server(true).await;
-                    let _ = self.stop().await;
-                    return Ok(());
-                }
-            }
             let _ = self.local_sock.send_to(payload, addr).await?;
+            self.counters.record_to_peer(payload.len());
         }
         Ok(())
     }
 
-    async fn start_emulator(&self) -> anyhow::Result<()> {
+    async fn start_emulator(self: &Arc<Self>) -> anyhow::Result<()> {
         // Your JS called startPlayingOnline with params; here we just show a spawn.
         // You can craft the exact CLI args your emulator expects.
         let emu_listen_port = self.emu_listener.local_addr()?.port();
         let emu_game_port = self.args.emulator_game_port.unwrap_or(7000);
 
-        // self.app.emit_all("proxy-log",
-        //   format!("Starting emulator: {} (listen:{emu_listen_port} game:{emu_game_port})", self.args.emulator_path)
-        // ).ok();
-        let _ = self
-            .app
-            .emit_to(EventTarget::any(), "proxy-log", format!("Port busy"));
+        let _ = self.app.emit_to(
+            EventTarget::any(),
+            "proxy-log",
+            format!(
+                "Starting emulator: {} (listen:{emu_listen_port} game:{emu_game_port})",
+                self.args.emulator_path
+            ),
+        );
 
-        let mut cmd = TokioCommand::new(&self.args.emulator_path);
         let mut provided_args = self.args.emulator_args.clone();
+        let launch = *self.launch.lock().unwrap();
+        if launch.player != self.args.player || launch.delay != self.args.delay {
+            rematch::rewrite_emulator_args(&mut provided_args, launch);
+        }
 
         if provided_args.is_empty() {
             provided_args = vec![
@@ -302,9 +1415,9 @@ impl ProxyRuntime {
                 "--remote-port".to_string(),
                 emu_listen_port.to_string(),
                 "--player".to_string(),
-                self.args.player.to_string(),
+                launch.player.to_string(),
                 "--delay".to_string(),
-                self.args.delay.to_string(),
+                launch.delay.to_string(),
                 "--name".to_string(),
                 self.args.user_name.clone(),
             ];
@@ -313,10 +1426,41 @@ impl ProxyRuntime {
         // Example args - replace with what FBNeo needs in your environment:
         //   --local-port 7000 --remote-ip 127.0.0.1 --remote-port <emu_listener_port> --player N --delay D --name user
         resolve_lua_args(&self.app, &mut provided_args).map_err(|e| anyhow!(e))?;
-        cmd.args(provided_args);
+        let mut command = launch::build_command(
+            &self.app,
+            &self.args.emulator_path,
+            provided_args,
+            &self.args.launch,
+        )
+        .map_err(|e| anyhow!(e))?;
+        // lets the stats the script writes be tied back to this session
+        command
+            .cmd
+            .env(match_watcher::SESSION_ENV, &self.session_id)
+            .env(match_watcher::PLAYER_ENV, launch.player.to_string());
 
-        let child = cmd.spawn()?;
-        *self.child.lock().await = Some(child);
+        let handle = self
+            .app
+            .state::<EmulatorSupervisor>()
+            .spawn(
+                &self.app,
+                self.args.emulator_purpose,
+                Some(self.session_id.clone()),
+                command,
+            )
+            .map_err(|e| anyhow!(e))?;
+        *self.emulator.lock().await = Some(handle.id);
+        for failure in handle.scheduling.failures() {
+            let _ = self.app.emit_to(
+                EventTarget::any(),
+                "proxy-log",
+                format!("Emulator scheduling not applied, {failure}"),
+            );
+        }
+        let watcher = self.spawn_exit_watcher(handle);
+        if let Some(old) = self.exit_watch.lock().await.replace(watcher) {
+            old.abort();
+        }
         let _ = self.app.emit_to(
             EventTarget::any(),
             "sendAlert",
@@ -340,10 +1484,11 @@ impl ProxyRuntime {
         let server = format!("{}:{}", self.args.server_host, self.args.server_port);
         let server_addr: SocketAddr = server.parse()?;
         self.local_sock.send_to(&msg, server_addr).await?;
-        // self.app.emit_all("proxy-log", format!("Sent punch to {server} kill={kill}")).ok();
-        let _ = self
-            .app
-            .emit_to(EventTarget::any(), "proxy-log", format!("Port busy"));
+        let _ = self.app.emit_to(
+            EventTarget::any(),
+            "proxy-log",
+            format!("Sent punch to {server} kill={kill}"),
+        );
         Ok(())
     }
 
@@ -355,66 +1500,441 @@ impl ProxyRuntime {
         if let Some(h) = self.keepalive_task.lock().await.take() {
             h.abort();
         }
+        if let Some(h) = self.stats_task.lock().await.take() {
+            h.abort();
+        }
+        if let Some(h) = self.probe_task.lock().await.take() {
+            h.abort();
+        }
+        if let Some(h) = self.exit_watch.lock().await.take() {
+            h.abort();
+        }
+        if let Some(h) = self.tunnel_task.lock().await.take() {
+            h.abort();
+        }
+        if let Some(link) = self.tunnel.lock().await.take() {
+            link.close();
+        }
         // Kill emulator
-        if let Some(mut child) = self.child.lock().await.take() {
-            // try graceful
-            let _ = child.start_kill(); // sends SIGKILL on Unix; on Windows, terminates the process
-            let _ = child.wait().await;
+        self.kill_emulator().await;
+        // last, since shutdown() calls stop() from inside the sync task itself;
+        // nothing after this point may await
+        if let Some(h) = self.sync_task.lock().await.take() {
+            h.abort();
         }
         Ok(())
     }
+
+    // Leaving for good: the peer and the server hear about it before teardown
+    pub async fn leave(&self, reason: &str) -> anyhow::Result<()> {
+        let bye = ControlMessage::Bye {
+            reason: reason.to_string(),
+        };
+        // control messages ride on plain UDP, a second copy rarely hurts
+        for _ in 0..2 {
+            let _ = self.send_control(&bye).await;
+        }
+        let _ = self.send_to_server(true).await;
+        self.stop().await
+    }
+
+    // stop() for failures detected inside the runtime: also drops the session
+    // from the manager so it doesn't linger in list_proxy_sessions. That has to
+    // come first, since stop() aborts the sync task this usually runs on.
+    async fn shutdown(&self) {
+        self.app
+            .state::<ProxyManager<R>>()
+            .remove_if_current(&self.session_id, self)
+            .await;
+        let _ = self.stop().await;
+    }
+
+    async fn kill_emulator(&self) -> Option<EmulatorExit> {
+        let id = self.emulator.lock().await.take()?;
+        let exit = self
+            .app
+            .state::<EmulatorSupervisor>()
+            .stop(id, self.args.emulator_shutdown)
+            .await?;
+        let _ = self.app.emit_to(
+            EventTarget::any(),
+            "proxy-log",
+            format!(
+                "Emulator stopped by {}",
+                exit.stopped_by.map_or("exiting on its own", ShutdownStage::as_str)
+            ),
+        );
+        Some(exit)
+    }
+
+    // Snapshot for the match history when the watcher picks up stats
+    fn match_context(&self) -> MatchContext {
+        let launch = *self.launch.lock().unwrap();
+        let link = self.link_probe.lock().unwrap().stats(control::now_ms());
+        MatchContext {
+            session_id: Some(self.session_id.clone()),
+            match_id: self.args.match_id.clone(),
+            opponent_uid: Some(self.args.peer_uid.clone()),
+            player_slot: Some(launch.player),
+            delay: Some(launch.delay),
+            rtt_ms: link.rtt_ms,
+            rtt_avg_ms: link.rtt_avg_ms,
+            loss_ratio: (link.probes_sent > 0).then_some(link.loss_ratio),
+        }
+    }
+
+    pub(crate) async fn info(&self) -> ProxySessionInfo {
+        ProxySessionInfo {
+            session_id: self.session_id.clone(),
+            match_id: self.args.match_id.clone(),
+            peer_uid: self.args.peer_uid.clone(),
+            player: self.args.player,
+            local_addr: self.local_sock.local_addr().ok().map(|a| a.to_string()),
+            peer: self.opponent.lock().await.map(|addr| addr.to_string()),
+            emulator_running: self.emulator.lock().await.is_some(),
+            uptime_ms: self.started_at.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxySessionInfo {
+    pub session_id: String,
+    pub match_id: Option<String>,
+    pub peer_uid: String,
+    pub player: u8,
+    pub local_addr: Option<String>,
+    pub peer: Option<String>,
+    pub emulator_running: bool,
+    pub uptime_ms: u64,
 }
 
 // ---- Global manager so we can have start/stop commands ----
-pub struct ProxyManager {
-    inner: Mutex<Option<Arc<ProxyRuntime>>>,
+static NEXT_SESSION: AtomicU64 = AtomicU64::new(1);
+
+// Sessions are keyed by match_id when the frontend has one, otherwise by a
+// generated id which start_proxy hands back. Generic over the runtime only so
+// tests can run sessions on tauri's mock runtime.
+pub struct ProxyManager<R: Runtime = Wry> {
+    sessions: Mutex<HashMap<String, Arc<ProxyRuntime<R>>>>,
+    // ids a start has claimed but not registered yet, see claim_session
+    starting: std::sync::Mutex<HashSet<String>>,
 }
 
-impl ProxyManager {
+impl<R: Runtime> ProxyManager<R> {
     pub fn new() -> Self {
         Self {
-            inner: Mutex::new(None),
+            sessions: Mutex::new(HashMap::new()),
+            starting: std::sync::Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub async fn get(&self, session_id: &str) -> Option<Arc<ProxyRuntime<R>>> {
+        self.sessions.lock().await.get(session_id).cloned()
+    }
+
+    pub(crate) async fn remove(&self, session_id: &str) -> Option<Arc<ProxyRuntime<R>>> {
+        self.sessions.lock().await.remove(session_id)
+    }
+
+    // App exit: every session leaves at once
+    pub async fn leave_all(&self, reason: &str) {
+        let sessions: Vec<_> = self.sessions.lock().await.drain().map(|(_, rt)| rt).collect();
+        join_all(sessions.iter().map(|rt| rt.leave(reason))).await;
+    }
+
+    // A replaced session may still be shutting down; make sure it only removes itself
+    async fn remove_if_current(&self, session_id: &str, rt: &ProxyRuntime<R>) {
+        let mut sessions = self.sessions.lock().await;
+        if sessions
+            .get(session_id)
+            .is_some_and(|current| std::ptr::eq(current.as_ref(), rt))
+        {
+            sessions.remove(session_id);
+        }
+    }
+
+    // Context for stats the session's emulator wrote, None once it's gone
+    pub async fn match_context(&self, session_id: &str) -> Option<MatchContext> {
+        Some(self.get(session_id).await?.match_context())
+    }
+
+    async fn get_or_err(&self, session_id: &str) -> Result<Arc<ProxyRuntime<R>>, String> {
+        self.get(session_id)
+            .await
+            .ok_or_else(|| format!("No proxy session '{session_id}'"))
+    }
+}
+
+// Held while a start owns an id, released however the start ends (including
+// the future being dropped)
+struct Reservation<'a> {
+    starting: &'a std::sync::Mutex<HashSet<String>>,
+    session_id: String,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.starting.lock().unwrap().remove(&self.session_id);
+    }
+}
+
+// The existing-session check and the reservation happen under the sessions
+// lock, so two concurrent starts can't both get past Reject and a Replace
+// can't race another start for the same id. Stopping the displaced session
+// and `create` run without the lock; the other sessions stay usable meanwhile.
+async fn claim_session<T, Stop, StopFut, Create, CreateFut>(
+    sessions: &Mutex<HashMap<String, Arc<T>>>,
+    starting: &std::sync::Mutex<HashSet<String>>,
+    session_id: &str,
+    policy: ExistingSessionPolicy,
+    stop: Stop,
+    create: Create,
+) -> Result<Arc<T>, String>
+where
+    Stop: FnOnce(Arc<T>) -> StopFut,
+    StopFut: Future<Output = ()>,
+    Create: FnOnce() -> CreateFut,
+    CreateFut: Future<Output = Result<Arc<T>, String>>,
+{
+    let (reservation, existing) = {
+        let mut sessions = sessions.lock().await;
+        let mut reserved = starting.lock().unwrap();
+        if reserved.contains(session_id) {
+            return Err(format!("Proxy session '{session_id}' is already starting"));
+        }
+        if policy == ExistingSessionPolicy::Reject && sessions.contains_key(session_id) {
+            return Err(format!("Proxy session '{session_id}' is already running"));
         }
+        reserved.insert(session_id.to_string());
+        let reservation = Reservation {
+            starting,
+            session_id: session_id.to_string(),
+        };
+        (reservation, sessions.remove(session_id))
+    };
+    if let Some(existing) = existing {
+        // stopped first so the new runtime can take over its ports
+        stop(existing).await;
     }
+    let created = create().await?;
+    // nothing else inserts a reserved id, so this never displaces a session
+    let mut sessions = sessions.lock().await;
+    sessions.insert(session_id.to_string(), Arc::clone(&created));
+    drop(reservation);
+    Ok(created)
+}
+
+// Creates, registers and starts a runtime. Shared by start_proxy and the
+// loopback match, which picks its own session ids. The prewarmed socket is
+// only taken once the session id is ours.
+pub(crate) async fn spawn_session<R: Runtime>(
+    app: &AppHandle<R>,
+    state: &ProxyManager<R>,
+    session_id: String,
+    args: StartArgs,
+    prewarm: Option<&PrewarmManager>,
+) -> Result<Arc<ProxyRuntime<R>>, String> {
+    let rt = claim_session(
+        &state.sessions,
+        &state.starting,
+        &session_id,
+        args.on_existing,
+        |existing| async move {
+            let _ = existing.stop().await;
+        },
+        || async {
+            let server = format!("{}:{}", args.server_host, args.server_port).parse::<SocketAddr>();
+            let warm = match (prewarm, server) {
+                (Some(prewarm), Ok(server)) => prewarm.take_for(server, &args.peer_uid).await,
+                _ => None,
+            };
+            ProxyRuntime::new(app.clone(), session_id.clone(), args, warm)
+                .await
+                .map_err(|e| e.to_string())
+        },
+    )
+    .await?;
+    if let Err(e) = rt.start().await {
+        state.remove_if_current(&session_id, &rt).await;
+        let _ = rt.stop().await;
+        return Err(e.to_string());
+    }
+    let _ = app.emit_to(
+        EventTarget::any(),
+        "proxy-log",
+        format!(
+            "proxy {session_id} started: local={} emu_listener={}{}",
+            rt.local_sock.local_addr().map_err(|e| e.to_string())?,
+            rt.emu_listener.local_addr().map_err(|e| e.to_string())?,
+            if rt.prewarmed { " (prewarmed)" } else { "" }
+        ),
+    );
+    Ok(rt)
 }
 
 #[tauri::command]
 pub async fn start_proxy(
     app: AppHandle,
     state: tauri::State<'_, ProxyManager>,
+    prewarm: tauri::State<'_, PrewarmManager>,
     args: StartArgs,
 ) -> Result<String, String> {
     let mut args = args;
     let resolved_path = resolve_emulator_path(&app, &args.emulator_path)?;
     args.emulator_path = resolved_path.to_string_lossy().to_string();
 
-    let rt = ProxyRuntime::new(app, args)
-        .await
-        .map_err(|e| e.to_string())?;
-    rt.start().await.map_err(|e| e.to_string())?;
-    *state.inner.lock().await = Some(rt.clone());
-    Ok(format!(
-        "proxy started: local={} emu_listener={}",
-        rt.local_sock.local_addr().unwrap(),
-        rt.emu_listener.local_addr().unwrap()
-    ))
+    let session_id = args.match_id.clone().unwrap_or_else(|| {
+        format!("proxy-{}", NEXT_SESSION.fetch_add(1, Ordering::Relaxed))
+    });
+    spawn_session(&app, &state, session_id.clone(), args, Some(&prewarm)).await?;
+    Ok(session_id)
+}
+
+#[tauri::command]
+pub async fn list_proxy_sessions(
+    state: tauri::State<'_, ProxyManager>,
+) -> Result<Vec<ProxySessionInfo>, String> {
+    let sessions: Vec<_> = state.sessions.lock().await.values().cloned().collect();
+    let mut infos = Vec::with_capacity(sessions.len());
+    for rt in sessions {
+        infos.push(rt.info().await);
+    }
+    infos.sort_by_key(|info| std::cmp::Reverse(info.uptime_ms));
+    Ok(infos)
+}
+
+#[tauri::command]
+pub async fn get_proxy_stats(
+    state: tauri::State<'_, ProxyManager>,
+    session_id: String,
+) -> Result<Option<ProxyStats>, String> {
+    match state.get(&session_id).await {
+        Some(rt) => Ok(Some(rt.stats().await)),
+        None => Ok(None),
+    }
+}
+
+#[tauri::command]
+pub async fn get_compat_report(
+    state: tauri::State<'_, ProxyManager>,
+    session_id: String,
+) -> Result<Option<compat::CompatReport>, String> {
+    match state.get(&session_id).await {
+        Some(rt) => Ok(rt.compat_report()),
+        None => Ok(None),
+    }
 }
 
 #[tauri::command]
-pub async fn stop_proxy(state: tauri::State<'_, ProxyManager>) -> Result<(), String> {
-    if let Some(rt) = state.inner.lock().await.take() {
+pub async fn stop_proxy(
+    state: tauri::State<'_, ProxyManager>,
+    session_id: String,
+) -> Result<(), String> {
+    if let Some(rt) = state.remove(&session_id).await {
         rt.stop().await.map_err(|e| e.to_string())?;
     }
     Ok(())
 }
 
 #[tauri::command]
-pub async fn kill_emulator_only(state: tauri::State<'_, ProxyManager>) -> Result<(), String> {
-    if let Some(rt) = &*state.inner.lock().await {
-        if let Some(mut child) = rt.child.lock().await.take() {
-            let _ = child.start_kill();
-            let _ = child.wait().await;
-        }
+pub async fn kill_emulator_only(
+    state: tauri::State<'_, ProxyManager>,
+    session_id: String,
+) -> Result<Option<EmulatorExit>, String> {
+    let rt = state.get_or_err(&session_id).await?;
+    Ok(rt.kill_emulator().await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    #[derive(Default)]
+    struct FakeSession {
+        stopped: AtomicBool,
+    }
+
+    #[derive(Default)]
+    struct FakeManager {
+        sessions: Mutex<HashMap<String, Arc<FakeSession>>>,
+        starting: std::sync::Mutex<HashSet<String>>,
+    }
+
+    async fn claim(
+        manager: &FakeManager,
+        policy: ExistingSessionPolicy,
+    ) -> Result<Arc<FakeSession>, String> {
+        claim_session(
+            &manager.sessions,
+            &manager.starting,
+            "match-1",
+            policy,
+            |old: Arc<FakeSession>| async move { old.stopped.store(true, Ordering::SeqCst) },
+            || async {
+                // binding sockets takes a moment, give the other start a chance to run
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok(Arc::new(FakeSession::default()))
+            },
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn racing_starts_only_one_wins_reject() {
+        let manager = FakeManager::default();
+        let (a, b) = tokio::join!(
+            claim(&manager, ExistingSessionPolicy::Reject),
+            claim(&manager, ExistingSessionPolicy::Reject)
+        );
+        assert!(a.is_ok() != b.is_ok(), "exactly one start may win");
+        let winner = a.or(b).unwrap();
+        assert!(Arc::ptr_eq(&manager.sessions.lock().await["match-1"], &winner));
+        assert!(manager.starting.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn racing_replaces_only_one_wins() {
+        let manager = FakeManager::default();
+        let (a, b) = tokio::join!(
+            claim(&manager, ExistingSessionPolicy::Replace),
+            claim(&manager, ExistingSessionPolicy::Replace)
+        );
+        assert!(a.is_ok() != b.is_ok(), "a replace can't displace a start in flight");
+        let winner = a.or(b).unwrap();
+        assert!(Arc::ptr_eq(&manager.sessions.lock().await["match-1"], &winner));
+    }
+
+    #[tokio::test]
+    async fn replace_stops_the_old_session_without_holding_the_lock() {
+        let manager = FakeManager::default();
+        let old = claim(&manager, ExistingSessionPolicy::Reject).await.unwrap();
+        let (new, ()) = tokio::join!(claim(&manager, ExistingSessionPolicy::Replace), async {
+            // the replacement is still creating, other sessions stay reachable
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            assert!(manager.sessions.try_lock().is_ok());
+        });
+        let new = new.unwrap();
+        assert!(old.stopped.load(Ordering::SeqCst));
+        assert!(!new.stopped.load(Ordering::SeqCst));
+        assert!(Arc::ptr_eq(&manager.sessions.lock().await["match-1"], &new));
+    }
+
+    #[tokio::test]
+    async fn a_failed_start_releases_its_id() {
+        let manager = FakeManager::default();
+        let failed = claim_session(
+            &manager.sessions,
+            &manager.starting,
+            "match-1",
+            ExistingSessionPolicy::Reject,
+            |_: Arc<FakeSession>| async {},
+            || async { Err("bind failed".to_string()) },
+        )
+        .await;
+        assert!(failed.is_err());
+        assert!(claim(&manager, ExistingSessionPolicy::Reject).await.is_ok());
     }
-    Ok(())
 }
@@ -0,0 +1,96 @@
+// Traffic counters for a proxy session, snapshotted into `proxy:stats` events.
+use crate::control::LinkStats;
+use crate::ggpo::GgpoStats;
+use crate::redundancy::RedundancyStats;
+use crate::resource_usage::ResourceSample;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Default)]
+pub struct ProxyCounters {
+    pub packets_to_peer: AtomicU64,
+    pub bytes_to_peer: AtomicU64,
+    pub packets_from_peer: AtomicU64,
+    pub bytes_from_peer: AtomicU64,
+    pub packets_from_emulator: AtomicU64,
+    pub packets_to_emulator: AtomicU64,
+    // tunneled datagrams that arrived too late to be worth feeding the emulator
+    pub stale_dropped: AtomicU64,
+}
+
+impl ProxyCounters {
+    pub fn record_to_peer(&self, bytes: usize) {
+        self.packets_to_peer.fetch_add(1, Ordering::Relaxed);
+        self.bytes_to_peer.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_from_peer(&self, bytes: usize) {
+        self.packets_from_peer.fetch_add(1, Ordering::Relaxed);
+        self.bytes_from_peer.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_from_emulator(&self) {
+        self.packets_from_emulator.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_to_emulator(&self) {
+        self.packets_to_emulator.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_stale(&self) {
+        self.stale_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyStats {
+    pub session_id: String,
+    pub match_id: Option<String>,
+    pub peer: Option<String>,
+    // the lobby's prewarmed socket was adopted
+    pub prewarmed: bool,
+    pub uptime_ms: u64,
+    // "udp", or the relay transport once the tunnel fallback kicked in
+    pub transport: &'static str,
+    pub packets_to_peer: u64,
+    pub bytes_to_peer: u64,
+    pub packets_from_peer: u64,
+    pub bytes_from_peer: u64,
+    pub packets_from_emulator: u64,
+    pub packets_to_emulator: u64,
+    pub stale_dropped: u64,
+    // RTT / loss measured with control-channel probes
+    pub link: Option<LinkStats>,
+    // only present when GGPO inspection is enabled for the session
+    pub ggpo: Option<GgpoStats>,
+    // only present when redundancy is configured for the session
+    pub redundancy: Option<RedundancyStats>,
+    // the last few samples of the session's emulator, empty before it
+    // starts. get_emulator_usage has the full history.
+    pub emulator_usage: Vec<ResourceSample>,
+}
+
+impl ProxyStats {
+    pub fn from_counters(counters: &ProxyCounters) -> Self {
+        Self {
+            session_id: String::new(),
+            match_id: None,
+            peer: None,
+            prewarmed: false,
+            uptime_ms: 0,
+            transport: "udp",
+            packets_to_peer: counters.packets_to_peer.load(Ordering::Relaxed),
+            bytes_to_peer: counters.bytes_to_peer.load(Ordering::Relaxed),
+            packets_from_peer: counters.packets_from_peer.load(Ordering::Relaxed),
+            bytes_from_peer: counters.bytes_from_peer.load(Ordering::Relaxed),
+            packets_from_emulator: counters.packets_from_emulator.load(Ordering::Relaxed),
+            packets_to_emulator: counters.packets_to_emulator.load(Ordering::Relaxed),
+            stale_dropped: counters.stale_dropped.load(Ordering::Relaxed),
+            link: None,
+            ggpo: None,
+            redundancy: None,
+            emulator_usage: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,401 @@
+// Redundant sending for lossy links (mostly Wi-Fi). Emulator datagrams are
+// wrapped in a small frame with a sequence number so the receiving proxy can
+// drop duplicates and only hand each datagram to the emulator once.
+//
+// frame: FRAME_PREFIX, seq u32 LE, copy u8, count u8, then `count` entries of
+//        { len u16 LE, bytes }. Entries are ordered oldest first, entry i has
+//        sequence number seq - (count - 1 - i).
+use serde::{Deserialize, Serialize};
+
+pub const FRAME_PREFIX: &[u8] = b"\x00HRR";
+// advertised in the start handshake, frames are only sent to peers that know them
+pub const FEATURE: &str = "redundancy";
+
+const MAX_COPIES: u8 = 4;
+// how far behind the newest sequence number we still accept a datagram, one
+// bit of RedundantReceiver::seen each
+const WINDOW: u32 = u64::BITS;
+// a datagram that came in through a redundant copy only counts as recovered
+// once its primary is this many sequence numbers overdue, before that the
+// copy may just have overtaken it
+const RECOVERY_SETTLE: u32 = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedundancyMode {
+    #[default]
+    Off,
+    // every datagram is sent `copies` times
+    Duplicate,
+    // every datagram carries the previous one along with it
+    Bundle,
+}
+
+fn default_copies() -> u8 {
+    2
+}
+
+fn default_loss_threshold() -> f64 {
+    0.02
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedundancyConfig {
+    #[serde(default)]
+    pub mode: RedundancyMode,
+    #[serde(default = "default_copies")]
+    pub copies: u8,
+    // only kick in once measured loss goes over `loss_threshold`
+    #[serde(default)]
+    pub auto: bool,
+    #[serde(default = "default_loss_threshold")]
+    pub loss_threshold: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedundancyStats {
+    pub mode: RedundancyMode,
+    pub active: bool,
+    pub frames_sent: u64,
+    pub extra_copies_sent: u64,
+    pub frames_received: u64,
+    pub duplicates_dropped: u64,
+    // datagrams we only got thanks to a redundant copy
+    pub recovered: u64,
+}
+
+pub fn is_frame(bytes: &[u8]) -> bool {
+    bytes.starts_with(FRAME_PREFIX)
+}
+
+fn encode_frame(seq: u32, copy: u8, entries: &[&[u8]]) -> Vec<u8> {
+    let body: usize = entries.iter().map(|e| 2 + e.len()).sum();
+    let mut out = Vec::with_capacity(FRAME_PREFIX.len() + 6 + body);
+    out.extend_from_slice(FRAME_PREFIX);
+    out.extend_from_slice(&seq.to_le_bytes());
+    out.push(copy);
+    out.push(entries.len() as u8);
+    for entry in entries {
+        out.extend_from_slice(&(entry.len() as u16).to_le_bytes());
+        out.extend_from_slice(entry);
+    }
+    out
+}
+
+struct DecodedFrame<'a> {
+    seq: u32,
+    copy: u8,
+    entries: Vec<&'a [u8]>,
+}
+
+fn decode_frame(bytes: &[u8]) -> Option<DecodedFrame<'_>> {
+    let rest = bytes.strip_prefix(FRAME_PREFIX)?;
+    let seq = u32::from_le_bytes(rest.get(0..4)?.try_into().ok()?);
+    let copy = *rest.get(4)?;
+    let count = *rest.get(5)? as usize;
+    let mut at = 6;
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let len = u16::from_le_bytes(rest.get(at..at + 2)?.try_into().ok()?) as usize;
+        at += 2;
+        entries.push(rest.get(at..at + len)?);
+        at += len;
+    }
+    Some(DecodedFrame { seq, copy, entries })
+}
+
+pub struct RedundantSender {
+    config: RedundancyConfig,
+    active: bool,
+    next_seq: u32,
+    previous: Option<Vec<u8>>,
+    frames_sent: u64,
+    extra_copies_sent: u64,
+}
+
+impl RedundantSender {
+    pub fn new(config: RedundancyConfig) -> Self {
+        let active = config.mode != RedundancyMode::Off && !config.auto;
+        Self {
+            config,
+            active,
+            next_seq: 0,
+            previous: None,
+            frames_sent: 0,
+            extra_copies_sent: 0,
+        }
+    }
+
+    // Feeds the latest measured loss ratio. Returns the new state when auto mode
+    // flips it; the gap between the on/off thresholds keeps it from flapping.
+    pub fn update_loss(&mut self, loss_ratio: f64) -> Option<bool> {
+        if !self.config.auto || self.config.mode == RedundancyMode::Off {
+            return None;
+        }
+        let next = if self.active {
+            loss_ratio >= self.config.loss_threshold / 2.0
+        } else {
+            loss_ratio >= self.config.loss_threshold
+        };
+        if next == self.active {
+            return None;
+        }
+        self.active = next;
+        if !next {
+            self.previous = None;
+        }
+        Some(next)
+    }
+
+    // Datagrams to put on the wire for one emulator payload.
+    pub fn wrap(&mut self, payload: &[u8]) -> Vec<Vec<u8>> {
+        if !self.active {
+            return vec![payload.to_vec()];
+        }
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        self.frames_sent += 1;
+
+        match self.config.mode {
+            RedundancyMode::Off => vec![payload.to_vec()],
+            RedundancyMode::Duplicate => {
+                let copies = self.config.copies.clamp(1, MAX_COPIES);
+                self.extra_copies_sent += (copies - 1) as u64;
+                (0..copies)
+                    .map(|copy| encode_frame(seq, copy, &[payload]))
+                    .collect()
+            }
+            RedundancyMode::Bundle => {
+                let frame = match &self.previous {
+                    Some(prev) => {
+                        self.extra_copies_sent += 1;
+                        encode_frame(seq, 0, &[prev.as_slice(), payload])
+                    }
+                    None => encode_frame(seq, 0, &[payload]),
+                };
+                self.previous = Some(payload.to_vec());
+                vec![frame]
+            }
+        }
+    }
+
+    pub fn stats(&self) -> RedundancyStats {
+        RedundancyStats {
+            mode: self.config.mode,
+            active: self.active,
+            frames_sent: self.frames_sent,
+            extra_copies_sent: self.extra_copies_sent,
+            ..Default::default()
+        }
+    }
+}
+
+// Replay-window style de-duplication: `seen` bit i is sequence `highest - i`.
+// `pending` uses the same layout for datagrams delivered from a copy whose
+// primary hasn't shown up (yet).
+#[derive(Default)]
+pub struct RedundantReceiver {
+    highest: Option<u32>,
+    seen: u64,
+    pending: u64,
+    frames_received: u64,
+    duplicates_dropped: u64,
+    recovered: u64,
+}
+
+impl RedundantReceiver {
+    // Returns the payloads that should be handed to the emulator, oldest first.
+    pub fn unwrap<'a>(&mut self, bytes: &'a [u8]) -> Vec<&'a [u8]> {
+        let Some(frame) = decode_frame(bytes) else {
+            return Vec::new();
+        };
+        self.frames_received += 1;
+        let count = frame.entries.len() as u32;
+        let mut out = Vec::with_capacity(frame.entries.len());
+        for (i, entry) in frame.entries.into_iter().enumerate() {
+            let seq = frame.seq.wrapping_sub(count - 1 - i as u32);
+            let is_primary = i as u32 == count - 1 && frame.copy == 0;
+            if self.mark(seq) {
+                if !is_primary {
+                    self.await_primary(seq);
+                }
+                out.push(entry);
+            } else {
+                if is_primary {
+                    // the copy was only reordered ahead of it
+                    self.clear_pending(seq);
+                }
+                self.duplicates_dropped += 1;
+            }
+        }
+        out
+    }
+
+    // true if `seq` has not been seen before
+    fn mark(&mut self, seq: u32) -> bool {
+        let Some(highest) = self.highest else {
+            self.highest = Some(seq);
+            self.seen = 1;
+            return true;
+        };
+        let ahead = seq.wrapping_sub(highest);
+        if ahead != 0 && ahead < u32::MAX / 2 {
+            self.settle_pending(ahead);
+            self.seen = if ahead >= WINDOW { 0 } else { self.seen << ahead };
+            self.seen |= 1;
+            self.highest = Some(seq);
+            return true;
+        }
+        let behind = highest.wrapping_sub(seq);
+        if behind >= WINDOW {
+            return false;
+        }
+        let bit = 1u64 << behind;
+        if self.seen & bit != 0 {
+            return false;
+        }
+        self.seen |= bit;
+        true
+    }
+
+    fn await_primary(&mut self, seq: u32) {
+        let Some(highest) = self.highest else {
+            return;
+        };
+        let behind = highest.wrapping_sub(seq);
+        if behind < RECOVERY_SETTLE {
+            self.pending |= 1 << behind;
+        } else {
+            self.recovered += 1;
+        }
+    }
+
+    fn clear_pending(&mut self, seq: u32) {
+        let Some(highest) = self.highest else {
+            return;
+        };
+        let behind = highest.wrapping_sub(seq);
+        if behind < RECOVERY_SETTLE {
+            self.pending &= !(1 << behind);
+        }
+    }
+
+    // The window moves `ahead`: pending datagrams that end up RECOVERY_SETTLE
+    // or more behind never got their primary.
+    fn settle_pending(&mut self, ahead: u32) {
+        let keep = RECOVERY_SETTLE.saturating_sub(ahead);
+        let keep_mask = (1u64 << keep) - 1;
+        self.recovered += (self.pending & !keep_mask).count_ones() as u64;
+        self.pending = if keep == 0 {
+            0
+        } else {
+            (self.pending & keep_mask) << ahead
+        };
+    }
+
+    pub fn has_received(&self) -> bool {
+        self.frames_received > 0
+    }
+
+    pub fn fill_stats(&self, stats: &mut RedundancyStats) {
+        stats.frames_received = self.frames_received;
+        stats.duplicates_dropped = self.duplicates_dropped;
+        stats.recovered = self.recovered;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sender(mode: RedundancyMode) -> RedundantSender {
+        RedundantSender::new(RedundancyConfig {
+            mode,
+            copies: 2,
+            auto: false,
+            loss_threshold: default_loss_threshold(),
+        })
+    }
+
+    fn stats(receiver: &RedundantReceiver) -> RedundancyStats {
+        let mut stats = RedundancyStats::default();
+        receiver.fill_stats(&mut stats);
+        stats
+    }
+
+    #[test]
+    fn window_handles_duplicates_wraparound_and_big_jumps() {
+        let mut rx = RedundantReceiver::default();
+        assert!(rx.mark(u32::MAX - 1));
+        assert!(rx.mark(u32::MAX));
+        // wraps to 0 and keeps going
+        assert!(rx.mark(0));
+        assert!(rx.mark(1));
+        assert!(!rx.mark(u32::MAX));
+        assert!(!rx.mark(1));
+        // late but inside the window
+        assert!(rx.mark(u32::MAX - 5));
+        assert!(!rx.mark(u32::MAX - 5));
+
+        // jumping a whole window ahead forgets everything before it
+        assert!(rx.mark(1 + WINDOW));
+        assert!(!rx.mark(1));
+        // the oldest sequence still inside the new window is fresh
+        assert!(rx.mark(2));
+        assert!(!rx.mark(2));
+    }
+
+    #[test]
+    fn duplicate_copies_are_dropped_and_reorders_are_not_recoveries() {
+        let mut tx = sender(RedundancyMode::Duplicate);
+        let mut rx = RedundantReceiver::default();
+        let first = tx.wrap(b"a");
+        assert_eq!(first.len(), 2);
+        assert_eq!(rx.unwrap(&first[0]), [b"a".as_slice()]);
+        assert!(rx.unwrap(&first[1]).is_empty());
+
+        // copy 1 overtakes copy 0
+        let second = tx.wrap(b"b");
+        assert_eq!(rx.unwrap(&second[1]), [b"b".as_slice()]);
+        assert!(rx.unwrap(&second[0]).is_empty());
+        for n in 0..RECOVERY_SETTLE {
+            for frame in tx.wrap(&[n as u8]) {
+                rx.unwrap(&frame);
+            }
+        }
+        let stats = stats(&rx);
+        assert_eq!(stats.recovered, 0);
+        assert_eq!(stats.duplicates_dropped, 2 + RECOVERY_SETTLE as u64);
+    }
+
+    #[test]
+    fn lost_primaries_count_as_recovered_once_overdue() {
+        let mut tx = sender(RedundancyMode::Bundle);
+        let mut rx = RedundantReceiver::default();
+        assert_eq!(rx.unwrap(&tx.wrap(b"a")[0]), [b"a".as_slice()]);
+        // "b" is lost, "c" brings it along
+        let _lost = tx.wrap(b"b");
+        assert_eq!(rx.unwrap(&tx.wrap(b"c")[0]), [b"b".as_slice(), b"c".as_slice()]);
+        assert_eq!(stats(&rx).recovered, 0);
+        for n in 0..RECOVERY_SETTLE {
+            rx.unwrap(&tx.wrap(&[n as u8])[0]);
+        }
+        assert_eq!(stats(&rx).recovered, 1);
+        // a copy arriving after the settle distance counts right away
+        let mut rx = RedundantReceiver::default();
+        rx.mark(100);
+        let frame = encode_frame(100 - RECOVERY_SETTLE, 1, &[b"x"]);
+        assert_eq!(rx.unwrap(&frame).len(), 1);
+        assert_eq!(stats(&rx).recovered, 1);
+    }
+
+    #[test]
+    fn truncated_frames_are_ignored() {
+        let frame = encode_frame(5, 0, &[b"hello"]);
+        let mut rx = RedundantReceiver::default();
+        assert!(rx.unwrap(&frame[..frame.len() - 1]).is_empty());
+        assert!(!rx.has_received());
+        assert_eq!(rx.unwrap(&frame), [b"hello".as_slice()]);
+    }
+}
@@ -0,0 +1,168 @@
+// Runbacks over an already punched link. Both players opt in, then either side
+// asks for a rematch; the proxies rerun the start handshake and relaunch the
+// emulators, optionally with sides swapped or a new delay.
+use crate::proxy::ProxyManager;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RematchOptions {
+    #[serde(default)]
+    pub swap_sides: bool,
+    #[serde(default)]
+    pub delay: Option<u16>,
+}
+
+// Player slot / delay for the next launch, starts out as the StartArgs values
+#[derive(Debug, Clone, Copy)]
+pub struct LaunchOverrides {
+    pub player: u8,
+    pub delay: u16,
+}
+
+impl LaunchOverrides {
+    pub fn apply(&mut self, options: &RematchOptions) {
+        if options.swap_sides {
+            self.player = if self.player == 1 { 2 } else { 1 };
+        }
+        if let Some(delay) = options.delay {
+            self.delay = delay;
+        }
+    }
+}
+
+// Our outstanding request, resolved with the peer's answer
+pub struct PendingRematch {
+    pub id: u64,
+    pub options: RematchOptions,
+    pub answer: oneshot::Sender<Result<(), String>>,
+}
+
+#[derive(Default)]
+pub struct RematchState {
+    pub local_opt_in: bool,
+    pub peer_opt_in: bool,
+    pub pending: Option<PendingRematch>,
+    // last request id we accepted from the peer, retransmits just get re-acked
+    pub last_accepted: Option<u64>,
+    pub next_id: u64,
+}
+
+impl RematchState {
+    pub fn available(&self) -> bool {
+        self.local_opt_in && self.peer_opt_in
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RematchEvent {
+    pub session_id: String,
+    pub player: u8,
+    pub delay: u16,
+    pub requested_by_peer: bool,
+}
+
+// Rewrites the player slot and delay in the exact CLI args the frontend built.
+// Handles both the fs-fbneo flags (`--player N`, `-d N`) and fcadefbneo's
+// `quark:direct,rom,local,ip,remote,player,delay,0` connection string.
+pub fn rewrite_emulator_args(args: &mut [String], overrides: LaunchOverrides) {
+    let mut idx = 0;
+    while idx < args.len() {
+        let flag = args[idx].to_ascii_lowercase();
+        if idx + 1 < args.len() && flag == "--player" {
+            args[idx + 1] = overrides.player.to_string();
+            idx += 2;
+            continue;
+        }
+        if idx + 1 < args.len() && (flag == "--delay" || flag == "-d") {
+            args[idx + 1] = overrides.delay.to_string();
+            idx += 2;
+            continue;
+        }
+        if flag.starts_with("quark:direct,") {
+            let mut parts: Vec<String> = args[idx].split(',').map(str::to_string).collect();
+            if parts.len() >= 7 {
+                parts[5] = overrides.player.to_string();
+                parts[6] = overrides.delay.to_string();
+                args[idx] = parts.join(",");
+            }
+        }
+        idx += 1;
+    }
+}
+
+#[tauri::command]
+pub async fn set_rematch_opt_in(
+    state: tauri::State<'_, ProxyManager>,
+    session_id: String,
+    enabled: bool,
+) -> Result<(), String> {
+    let rt = state
+        .get(&session_id)
+        .await
+        .ok_or_else(|| format!("No proxy session '{session_id}'"))?;
+    rt.set_rematch_opt_in(enabled).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn request_rematch(
+    state: tauri::State<'_, ProxyManager>,
+    session_id: String,
+    options: Option<RematchOptions>,
+) -> Result<(), String> {
+    let rt = state
+        .get(&session_id)
+        .await
+        .ok_or_else(|| format!("No proxy session '{session_id}'"))?;
+    rt.request_rematch(options.unwrap_or_default()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn rewrites_fs_fbneo_flags() {
+        let mut overrides = LaunchOverrides { player: 1, delay: 2 };
+        overrides.apply(&RematchOptions {
+            swap_sides: true,
+            delay: Some(4),
+        });
+        let mut args = strings(&["-w", "sfiii3nr1", "--PLAYER", "1", "-d", "2", "--lua", "x.lua"]);
+        rewrite_emulator_args(&mut args, overrides);
+        assert_eq!(args, strings(&["-w", "sfiii3nr1", "--PLAYER", "2", "-d", "4", "--lua", "x.lua"]));
+
+        let mut args = strings(&["--delay", "1", "--player", "2"]);
+        rewrite_emulator_args(&mut args, LaunchOverrides { player: 1, delay: 3 });
+        assert_eq!(args, strings(&["--delay", "3", "--player", "1"]));
+
+        // a trailing flag without a value is left alone
+        let mut args = strings(&["--player"]);
+        rewrite_emulator_args(&mut args, overrides);
+        assert_eq!(args, strings(&["--player"]));
+    }
+
+    #[test]
+    fn rewrites_the_fcadefbneo_connection_string() {
+        let mut args = strings(&[
+            "quark:direct,sfiii3nr1,7000,127.0.0.1,7001,1,2,0",
+            "--lua",
+            "x.lua",
+        ]);
+        rewrite_emulator_args(&mut args, LaunchOverrides { player: 2, delay: 5 });
+        assert_eq!(args[0], "quark:direct,sfiii3nr1,7000,127.0.0.1,7001,2,5,0");
+        assert_eq!(args[1..], strings(&["--lua", "x.lua"])[..]);
+
+        // too short to carry player and delay, untouched
+        let mut args = strings(&["quark:direct,sfiii3nr1,7000"]);
+        rewrite_emulator_args(&mut args, LaunchOverrides { player: 2, delay: 5 });
+        assert_eq!(args, strings(&["quark:direct,sfiii3nr1,7000"]));
+    }
+}
@@ -0,0 +1,316 @@
+// Periodic CPU, memory and thread samples for every running emulator, so a
+// "my game stutters" report can show whether FBNeo was starved or pegged a
+// core. A minute of history is kept per process for get_emulator_usage and
+// crash reports, `proxy:stats` carries the last few samples during matches.
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tauri::async_runtime::JoinHandle;
+use tokio::{sync::watch, time::interval};
+
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+// a minute at one sample a second
+pub const HISTORY_LEN: usize = 60;
+// what goes out with every `proxy:stats` event
+pub const STATS_WINDOW: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceSample {
+    pub at: u64,
+    // of one core, so a busy multithreaded process can go past 100
+    pub cpu_percent: f32,
+    pub rss_bytes: u64,
+    pub threads: u32,
+}
+
+// What a probe reads for a process at one point in time
+#[derive(Debug, Clone, Copy)]
+pub struct RawUsage {
+    // user + system time used so far
+    pub cpu_time: Duration,
+    pub rss_bytes: u64,
+    pub threads: u32,
+}
+
+// Per-platform reader. None when the process is gone or can't be read.
+pub trait UsageProbe: Send {
+    fn read(&mut self, pid: u32) -> Option<RawUsage>;
+}
+
+#[cfg(target_os = "linux")]
+pub struct ProcfsProbe {
+    ticks_per_sec: u64,
+    page_size: u64,
+}
+
+#[cfg(target_os = "linux")]
+impl ProcfsProbe {
+    pub fn new() -> Self {
+        // SAFETY: sysconf only reads configuration values
+        let (ticks, page) = unsafe {
+            (
+                libc::sysconf(libc::_SC_CLK_TCK),
+                libc::sysconf(libc::_SC_PAGESIZE),
+            )
+        };
+        Self {
+            ticks_per_sec: if ticks > 0 { ticks as u64 } else { 100 },
+            page_size: if page > 0 { page as u64 } else { 4096 },
+        }
+    }
+
+    fn parse(&self, stat: &str) -> Option<RawUsage> {
+        let fields = crate::orphans::stat_fields(stat)?;
+        // utime, stime, num_threads and rss are fields 14, 15, 20 and 24
+        // overall, counted here from the state (field 3)
+        let field = |n: usize| -> Option<u64> { fields.get(n)?.parse().ok() };
+        let ticks = field(11)? + field(12)?;
+        Some(RawUsage {
+            cpu_time: Duration::from_millis(ticks * 1000 / self.ticks_per_sec),
+            rss_bytes: field(21)? * self.page_size,
+            threads: field(17)? as u32,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl UsageProbe for ProcfsProbe {
+    fn read(&mut self, pid: u32) -> Option<RawUsage> {
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+        self.parse(&stat)
+    }
+}
+
+#[cfg(windows)]
+pub struct WindowsProbe;
+
+#[cfg(windows)]
+impl WindowsProbe {
+    // Thread counts only come with a process snapshot
+    fn threads(pid: u32) -> Option<u32> {
+        use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+        use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+            CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+            TH32CS_SNAPPROCESS,
+        };
+        // SAFETY: the snapshot handle is closed below, the entry is plain
+        // data with its size set as the API requires
+        unsafe {
+            let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+            if snapshot == INVALID_HANDLE_VALUE {
+                return None;
+            }
+            let mut entry: PROCESSENTRY32W = std::mem::zeroed();
+            entry.dwSize = std::mem::size_of::<PROCESSENTRY32W>() as u32;
+            let mut threads = None;
+            let mut more = Process32FirstW(snapshot, &mut entry) != 0;
+            while more {
+                if entry.th32ProcessID == pid {
+                    threads = Some(entry.cntThreads);
+                    break;
+                }
+                more = Process32NextW(snapshot, &mut entry) != 0;
+            }
+            CloseHandle(snapshot);
+            threads
+        }
+    }
+}
+
+#[cfg(windows)]
+impl UsageProbe for WindowsProbe {
+    fn read(&mut self, pid: u32) -> Option<RawUsage> {
+        use windows_sys::Win32::Foundation::{CloseHandle, FILETIME};
+        use windows_sys::Win32::System::ProcessStatus::{
+            GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS,
+        };
+        use windows_sys::Win32::System::Threading::{
+            GetProcessTimes, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_VM_READ,
+        };
+        // FILETIMEs count 100ns units
+        let hundred_ns =
+            |t: FILETIME| (u64::from(t.dwHighDateTime) << 32) | u64::from(t.dwLowDateTime);
+        // SAFETY: OpenProcess/CloseHandle on a pid we spawned, the out
+        // parameters are plain data we own
+        let (cpu, rss) = unsafe {
+            let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION | PROCESS_VM_READ, 0, pid);
+            if handle.is_null() {
+                return None;
+            }
+            let zero = FILETIME {
+                dwLowDateTime: 0,
+                dwHighDateTime: 0,
+            };
+            let (mut created, mut exited, mut kernel, mut user) = (zero, zero, zero, zero);
+            let times = GetProcessTimes(handle, &mut created, &mut exited, &mut kernel, &mut user);
+            let size = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+            let mut memory: PROCESS_MEMORY_COUNTERS = std::mem::zeroed();
+            memory.cb = size;
+            let counters = GetProcessMemoryInfo(handle, &mut memory, size);
+            CloseHandle(handle);
+            if times == 0 || counters == 0 {
+                return None;
+            }
+            (hundred_ns(kernel) + hundred_ns(user), memory.WorkingSetSize as u64)
+        };
+        Some(RawUsage {
+            cpu_time: Duration::from_nanos(cpu * 100),
+            rss_bytes: rss,
+            threads: Self::threads(pid).unwrap_or(0),
+        })
+    }
+}
+
+// Platforms without a probe yet (macOS), history just stays empty
+#[cfg(not(any(target_os = "linux", windows)))]
+pub struct NoProbe;
+
+#[cfg(not(any(target_os = "linux", windows)))]
+impl UsageProbe for NoProbe {
+    fn read(&mut self, _pid: u32) -> Option<RawUsage> {
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn default_probe() -> Box<dyn UsageProbe> {
+    Box::new(ProcfsProbe::new())
+}
+
+#[cfg(windows)]
+pub fn default_probe() -> Box<dyn UsageProbe> {
+    Box::new(WindowsProbe)
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn default_probe() -> Box<dyn UsageProbe> {
+    Box::new(NoProbe)
+}
+
+#[derive(Debug)]
+pub struct UsageHistory {
+    samples: VecDeque<ResourceSample>,
+    capacity: usize,
+    // previous reading, CPU% is the difference between two
+    last: Option<(Instant, Duration)>,
+}
+
+impl UsageHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            last: None,
+        }
+    }
+
+    // The first reading only sets the baseline
+    pub fn record(&mut self, raw: RawUsage, now: Instant, at: u64) {
+        let previous = self.last.replace((now, raw.cpu_time));
+        let Some((then, cpu_then)) = previous else {
+            return;
+        };
+        let wall = now.duration_since(then).as_secs_f32();
+        if wall <= 0.0 {
+            return;
+        }
+        let cpu = raw.cpu_time.saturating_sub(cpu_then).as_secs_f32();
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(ResourceSample {
+            at,
+            cpu_percent: cpu / wall * 100.0,
+            rss_bytes: raw.rss_bytes,
+            threads: raw.threads,
+        });
+    }
+
+    // Oldest first
+    pub fn samples(&self) -> Vec<ResourceSample> {
+        self.samples.iter().copied().collect()
+    }
+
+    // The newest `n`, oldest first
+    pub fn recent(&self, n: usize) -> Vec<ResourceSample> {
+        let skip = self.samples.len().saturating_sub(n);
+        self.samples.iter().skip(skip).copied().collect()
+    }
+}
+
+pub type SharedUsage = Arc<Mutex<UsageHistory>>;
+
+// Samples `pid` until the exit channel reports the process gone
+pub fn spawn_sampler<T: Send + Sync + 'static>(
+    pid: u32,
+    history: SharedUsage,
+    mut probe: Box<dyn UsageProbe>,
+    mut exit: watch::Receiver<Option<T>>,
+) -> JoinHandle<()> {
+    tauri::async_runtime::spawn(async move {
+        let mut ticker = interval(SAMPLE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                changed = exit.changed() => {
+                    if changed.is_err() || exit.borrow().is_some() {
+                        break;
+                    }
+                    continue;
+                }
+            }
+            let Some(raw) = probe.read(pid) else {
+                break;
+            };
+            history
+                .lock()
+                .unwrap()
+                .record(raw, Instant::now(), crate::control::now_ms());
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(cpu_ms: u64) -> RawUsage {
+        RawUsage {
+            cpu_time: Duration::from_millis(cpu_ms),
+            rss_bytes: 64 << 20,
+            threads: 4,
+        }
+    }
+
+    #[test]
+    fn cpu_percent_comes_from_consecutive_readings() {
+        let mut history = UsageHistory::new(2);
+        let start = Instant::now();
+        history.record(raw(1000), start, 0);
+        assert!(history.samples().is_empty());
+        // half a core over one second
+        history.record(raw(1500), start + Duration::from_secs(1), 1000);
+        // two cores over one second
+        history.record(raw(3500), start + Duration::from_secs(2), 2000);
+        history.record(raw(3500), start + Duration::from_secs(3), 3000);
+        let cpu: Vec<f32> = history.samples().iter().map(|s| s.cpu_percent).collect();
+        assert_eq!(history.samples()[0].at, 2000);
+        let recent: Vec<u64> = history.recent(1).iter().map(|s| s.at).collect();
+        assert_eq!(recent, [3000]);
+        assert_eq!(history.recent(10).len(), 2);
+        assert!((cpu[0] - 200.0).abs() < 0.1 && cpu[1] == 0.0, "{cpu:?}");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn procfs_probe_reads_this_process() {
+        let mut probe = ProcfsProbe::new();
+        let usage = probe.read(std::process::id()).unwrap();
+        assert!(usage.rss_bytes > 0 && usage.threads >= 1);
+        assert!(probe.read(u32::MAX).is_none());
+    }
+}
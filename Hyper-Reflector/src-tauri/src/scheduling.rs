@@ -0,0 +1,356 @@
+// Process priority and CPU affinity for emulators. On a weak laptop FBNeo
+// fights the webview and browser tabs for CPU, and the dropped frames look
+// like netcode trouble. Settings are applied by the supervisor right after
+// spawn and the outcome of each one is reported back, since raising priority
+// usually needs privileges the app doesn't have.
+use crate::emulator_supervisor::EmulatorPurpose;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Priority {
+    Low,
+    BelowNormal,
+    Normal,
+    AboveNormal,
+    High,
+}
+
+impl Priority {
+    // nice level on unix
+    #[cfg_attr(not(unix), allow(dead_code))]
+    fn nice(self) -> i32 {
+        match self {
+            Priority::Low => 10,
+            Priority::BelowNormal => 5,
+            Priority::Normal => 0,
+            Priority::AboveNormal => -5,
+            Priority::High => -10,
+        }
+    }
+
+    // Whether asking for it can succeed without extra privileges. Raising
+    // priority on unix needs root, CAP_SYS_NICE or a high enough RLIMIT_NICE.
+    #[cfg(unix)]
+    fn allowed(self) -> bool {
+        // SAFETY: geteuid(2) always succeeds
+        if self.nice() >= 0 || unsafe { libc::geteuid() } == 0 {
+            return true;
+        }
+        #[cfg(target_os = "linux")]
+        {
+            let mut limit = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            // SAFETY: getrlimit(2) into a struct we own
+            if unsafe { libc::getrlimit(libc::RLIMIT_NICE, &mut limit) } == 0 {
+                return nice_floor(limit.rlim_cur) <= i64::from(self.nice());
+            }
+        }
+        false
+    }
+
+    #[cfg(not(unix))]
+    fn allowed(self) -> bool {
+        true
+    }
+}
+
+// Lowest nice RLIMIT_NICE lets us set, the limit counts down from 20
+#[cfg(target_os = "linux")]
+fn nice_floor(rlimit_nice: libc::rlim_t) -> i64 {
+    20 - rlimit_nice.min(40) as i64
+}
+
+// Part of the launch options. Left out entirely, the defaults for the
+// emulator's purpose apply.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SchedulingOptions {
+    pub priority: Option<Priority>,
+    // CPU indices the emulator may run on
+    pub affinity: Option<Vec<usize>>,
+}
+
+impl SchedulingOptions {
+    // Netplay is where a late frame costs a desync or a rollback, so match
+    // emulators ask for a bit more priority where they'd get it (always on
+    // Windows, rarely for an unprivileged unix user, who would only collect a
+    // failure per match). Nothing is pinned by default, the scheduler knows
+    // better than a guess about someone's laptop.
+    pub fn for_purpose(purpose: EmulatorPurpose) -> Self {
+        let priority = match purpose {
+            EmulatorPurpose::Match if Priority::AboveNormal.allowed() => {
+                Some(Priority::AboveNormal)
+            }
+            _ => None,
+        };
+        Self {
+            priority,
+            affinity: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum Outcome {
+    Applied,
+    Failed { message: String },
+    Unsupported,
+}
+
+// One entry per requested setting, None when it wasn't asked for
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchedulingReport {
+    pub priority: Option<Outcome>,
+    pub affinity: Option<Outcome>,
+}
+
+impl SchedulingReport {
+    // Human readable failures, for the proxy log
+    pub fn failures(&self) -> Vec<String> {
+        [("priority", &self.priority), ("affinity", &self.affinity)]
+            .into_iter()
+            .filter_map(|(what, outcome)| match outcome {
+                Some(Outcome::Failed { message }) => Some(format!("{what}: {message}")),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+// CPUs this process may run on, which the emulator inherits. Containers,
+// cgroups and taskset leave holes, so the count alone isn't enough.
+#[cfg(target_os = "linux")]
+fn usable_cpus() -> Vec<usize> {
+    // SAFETY: cpu_set_t is plain data, zeroed is an empty set
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    // SAFETY: sched_getaffinity(2) for ourselves into a set of the size we pass
+    let rc = unsafe { libc::sched_getaffinity(0, std::mem::size_of_val(&set), &mut set) };
+    if rc != 0 {
+        return (0..available_cpus()).collect();
+    }
+    (0..libc::CPU_SETSIZE as usize)
+        // SAFETY: cpu stays below CPU_SETSIZE
+        .filter(|cpu| unsafe { libc::CPU_ISSET(*cpu, &set) })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn usable_cpus() -> Vec<usize> {
+    (0..available_cpus()).collect()
+}
+
+fn available_cpus() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+fn check_cpus(cpus: &[usize]) -> Result<(), String> {
+    if cpus.is_empty() {
+        return Err("no CPUs given".to_string());
+    }
+    let usable = usable_cpus();
+    if let Some(cpu) = cpus.iter().find(|cpu| !usable.contains(cpu)) {
+        return Err(format!("CPU {cpu} isn't available (usable: {usable:?})"));
+    }
+    Ok(())
+}
+
+fn outcome(result: Result<(), String>) -> Outcome {
+    match result {
+        Ok(()) => Outcome::Applied,
+        Err(message) => Outcome::Failed { message },
+    }
+}
+
+pub fn apply(pid: u32, options: &SchedulingOptions) -> SchedulingReport {
+    SchedulingReport {
+        priority: options
+            .priority
+            .map(|priority| outcome(set_priority(pid, priority))),
+        affinity: options.affinity.as_deref().map(|cpus| {
+            if let Err(message) = check_cpus(cpus) {
+                return Outcome::Failed { message };
+            }
+            set_affinity(pid, cpus).map_or(Outcome::Unsupported, outcome)
+        }),
+    }
+}
+
+#[cfg(unix)]
+fn set_priority(pid: u32, priority: Priority) -> Result<(), String> {
+    // SAFETY: setpriority(2) on a pid we just spawned
+    let rc = unsafe { libc::setpriority(libc::PRIO_PROCESS, pid as libc::id_t, priority.nice()) };
+    if rc == 0 {
+        return Ok(());
+    }
+    let e = std::io::Error::last_os_error();
+    if priority.nice() < 0 && e.raw_os_error() == Some(libc::EACCES) {
+        return Err(format!("{e} (raising priority needs CAP_SYS_NICE or a higher RLIMIT_NICE)"));
+    }
+    Err(e.to_string())
+}
+
+#[cfg(windows)]
+fn set_priority(pid: u32, priority: Priority) -> Result<(), String> {
+    use windows_sys::Win32::System::Threading::{
+        SetPriorityClass, ABOVE_NORMAL_PRIORITY_CLASS, BELOW_NORMAL_PRIORITY_CLASS,
+        HIGH_PRIORITY_CLASS, NORMAL_PRIORITY_CLASS, PROCESS_SET_INFORMATION,
+    };
+    // IDLE_PRIORITY_CLASS only runs when nothing else wants the CPU, which
+    // starves a game outright, so Low is the same as BelowNormal here
+    let class = match priority {
+        Priority::Low | Priority::BelowNormal => BELOW_NORMAL_PRIORITY_CLASS,
+        Priority::Normal => NORMAL_PRIORITY_CLASS,
+        Priority::AboveNormal => ABOVE_NORMAL_PRIORITY_CLASS,
+        Priority::High => HIGH_PRIORITY_CLASS,
+    };
+    // SAFETY: the handle is closed by with_process
+    windows::with_process(pid, PROCESS_SET_INFORMATION, |handle| unsafe {
+        SetPriorityClass(handle, class)
+    })
+}
+
+#[cfg(not(any(unix, windows)))]
+fn set_priority(_pid: u32, _priority: Priority) -> Result<(), String> {
+    Err("not supported on this platform".to_string())
+}
+
+// None where the platform has no way to pin a process
+#[cfg(target_os = "linux")]
+fn set_affinity(pid: u32, cpus: &[usize]) -> Option<Result<(), String>> {
+    // SAFETY: cpu_set_t is plain data, zeroed is an empty set
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for cpu in cpus {
+        // SAFETY: check_cpus only lets through CPUs from our own set
+        unsafe { libc::CPU_SET(*cpu, &mut set) };
+    }
+    // SAFETY: sched_setaffinity(2) with a set of the size we pass
+    let rc = unsafe {
+        libc::sched_setaffinity(pid as libc::pid_t, std::mem::size_of_val(&set), &set)
+    };
+    Some(if rc == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().to_string())
+    })
+}
+
+#[cfg(windows)]
+fn set_affinity(pid: u32, cpus: &[usize]) -> Option<Result<(), String>> {
+    use windows_sys::Win32::System::Threading::{
+        SetProcessAffinityMask, PROCESS_QUERY_INFORMATION, PROCESS_SET_INFORMATION,
+    };
+    if cpus.iter().any(|cpu| *cpu >= usize::BITS as usize) {
+        return Some(Err("CPUs past the first group can't be selected".to_string()));
+    }
+    let mask = cpus.iter().fold(0usize, |mask, cpu| mask | (1 << cpu));
+    // SAFETY: the handle is closed by with_process
+    Some(windows::with_process(
+        pid,
+        PROCESS_SET_INFORMATION | PROCESS_QUERY_INFORMATION,
+        |handle| unsafe { SetProcessAffinityMask(handle, mask) },
+    ))
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn set_affinity(_pid: u32, _cpus: &[usize]) -> Option<Result<(), String>> {
+    // macOS only takes affinity hints per thread, from inside the process
+    None
+}
+
+#[cfg(windows)]
+mod windows {
+    use windows_sys::Win32::Foundation::{CloseHandle, BOOL, HANDLE};
+    use windows_sys::Win32::System::Threading::{OpenProcess, PROCESS_ACCESS_RIGHTS};
+
+    pub fn with_process(
+        pid: u32,
+        access: PROCESS_ACCESS_RIGHTS,
+        f: impl FnOnce(HANDLE) -> BOOL,
+    ) -> Result<(), String> {
+        // SAFETY: OpenProcess/CloseHandle on a pid we just spawned
+        unsafe {
+            let handle = OpenProcess(access, 0, pid);
+            if handle.is_null() {
+                return Err(std::io::Error::last_os_error().to_string());
+            }
+            let ok = f(handle) != 0;
+            let result = if ok {
+                Ok(())
+            } else {
+                Err(std::io::Error::last_os_error().to_string())
+            };
+            CloseHandle(handle);
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn netplay_defaults_only_touch_matches() {
+        assert_eq!(
+            SchedulingOptions::for_purpose(EmulatorPurpose::Match).priority,
+            Priority::AboveNormal
+                .allowed()
+                .then_some(Priority::AboveNormal)
+        );
+        assert!(Priority::BelowNormal.allowed());
+        assert_eq!(
+            SchedulingOptions::for_purpose(EmulatorPurpose::Training),
+            SchedulingOptions::default()
+        );
+        let options: SchedulingOptions =
+            serde_json::from_str(r#"{"priority":"below-normal","affinity":[0]}"#).unwrap();
+        assert_eq!(options.priority, Some(Priority::BelowNormal));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn rlimit_nice_sets_how_far_priority_can_go() {
+        assert_eq!(nice_floor(0), 20);
+        assert_eq!(nice_floor(20), 0);
+        assert_eq!(nice_floor(25), -5);
+        assert_eq!(nice_floor(libc::RLIM_INFINITY), -20);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn lowers_priority_and_pins_a_child() {
+        let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let usable = usable_cpus();
+        let report = apply(
+            child.id(),
+            &SchedulingOptions {
+                priority: Some(Priority::BelowNormal),
+                affinity: Some(vec![usable[0]]),
+            },
+        );
+        assert_eq!(report.priority, Some(Outcome::Applied));
+        assert_eq!(report.affinity, Some(Outcome::Applied));
+        assert!(report.failures().is_empty());
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", child.id())).unwrap();
+        // nice is field 19 overall, 17th after the command name
+        let fields = crate::orphans::stat_fields(&stat).unwrap();
+        assert_eq!(fields[16], "5");
+
+        let bogus = apply(
+            child.id(),
+            &SchedulingOptions {
+                priority: None,
+                affinity: Some(vec![usable.last().unwrap() + 1]),
+            },
+        );
+        assert!(matches!(bogus.affinity, Some(Outcome::Failed { .. })));
+        assert_eq!(bogus.failures().len(), 1);
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
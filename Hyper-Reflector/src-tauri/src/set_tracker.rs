@@ -0,0 +1,393 @@
+// Best-of-N sets over a proxy session. Each stats payload the Lua script writes
+// is one game; the tracker accumulates them until someone reaches the target,
+// and both proxies compare their running score over the control channel.
+use crate::match_stats::{MatchStats, Winner};
+use crate::proxy::ProxyManager;
+use serde::Serialize;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+// How long a finished set waits for the peer's score before completing unconfirmed
+pub const AGREEMENT_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_FIRST_TO: u32 = 20;
+
+// "FT3", "ft5" or a plain number, meaning first to N wins
+pub fn parse_format(raw: &str) -> Result<u32, String> {
+    let trimmed = raw.trim();
+    let digits = trimmed
+        .strip_prefix("FT")
+        .or_else(|| trimmed.strip_prefix("ft"))
+        .unwrap_or(trimmed);
+    match digits.parse::<u32>() {
+        Ok(n) if (1..=MAX_FIRST_TO).contains(&n) => Ok(n),
+        _ => Err(format!("Invalid set format '{raw}', expected FT1-FT{MAX_FIRST_TO}")),
+    }
+}
+
+impl SetGame {
+    // One finished game from the validated stats the Lua script wrote
+    pub fn from_stats(stats: &MatchStats, local_player: u8) -> Self {
+        let winner_slot = match stats.winner {
+            Winner::Player1 => 1,
+            Winner::Player2 => 2,
+        };
+        Self {
+            match_uuid: Some(stats.match_uuid.clone()),
+            local_player,
+            winner_slot,
+            local_won: winner_slot == local_player,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetGame {
+    pub match_uuid: Option<String>,
+    pub local_player: u8,
+    pub winner_slot: u8,
+    pub local_won: bool,
+}
+
+// A side's view of the score, as exchanged with the peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScoreView {
+    pub games: u32,
+    pub local_wins: u32,
+    pub peer_wins: u32,
+}
+
+impl ScoreView {
+    // The peer's view agrees when it is ours seen from the other side
+    fn mirrors(&self, peer: &ScoreView) -> bool {
+        self.games == peer.games
+            && self.local_wins == peer.peer_wins
+            && self.peer_wins == peer.local_wins
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetStatus {
+    pub session_id: String,
+    pub match_id: Option<String>,
+    pub first_to: u32,
+    pub local_wins: u32,
+    pub peer_wins: u32,
+    pub games: Vec<SetGame>,
+    // the peer reported the same score for the same number of games
+    pub agreed: bool,
+    // the peer reported a different score for the same number of games
+    pub disputed: bool,
+    pub complete: bool,
+    pub local_won_set: Option<bool>,
+}
+
+pub struct SetTracker {
+    // picked by whoever started the set, the newest start wins on both sides
+    pub id: u64,
+    pub first_to: u32,
+    games: Vec<SetGame>,
+    peer_view: Option<ScoreView>,
+    completed_at: Option<Instant>,
+    complete_emitted: bool,
+}
+
+impl SetTracker {
+    pub fn new(first_to: u32, id: u64) -> Self {
+        Self {
+            id,
+            first_to,
+            games: Vec::new(),
+            peer_view: None,
+            completed_at: None,
+            complete_emitted: false,
+        }
+    }
+
+    pub fn view(&self) -> ScoreView {
+        let local_wins = self.games.iter().filter(|g| g.local_won).count() as u32;
+        ScoreView {
+            games: self.games.len() as u32,
+            local_wins,
+            peer_wins: self.games.len() as u32 - local_wins,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        let view = self.view();
+        view.local_wins >= self.first_to || view.peer_wins >= self.first_to
+    }
+
+    // Returns false when the game was already counted or the set is over
+    pub fn record(&mut self, game: SetGame) -> bool {
+        if self.is_complete() {
+            return false;
+        }
+        if game.match_uuid.is_some()
+            && self
+                .games
+                .iter()
+                .any(|g| g.match_uuid == game.match_uuid)
+        {
+            return false;
+        }
+        self.games.push(game);
+        if self.is_complete() {
+            self.completed_at = Some(Instant::now());
+        }
+        true
+    }
+
+    // Returns false for a retransmit of the view we already have
+    pub fn set_peer_view(&mut self, view: ScoreView) -> bool {
+        self.peer_view.replace(view) != Some(view)
+    }
+
+    fn agreed(&self) -> bool {
+        self.peer_view.is_some_and(|peer| self.view().mirrors(&peer))
+    }
+
+    fn disputed(&self) -> bool {
+        self.peer_view
+            .is_some_and(|peer| peer.games == self.view().games && !self.view().mirrors(&peer))
+    }
+
+    // True exactly once, when set:complete should go out: as soon as the peer
+    // confirms, or after AGREEMENT_TIMEOUT if it never does.
+    pub fn take_completion(&mut self) -> bool {
+        if self.complete_emitted || !self.is_complete() {
+            return false;
+        }
+        let waited = self
+            .completed_at
+            .is_some_and(|at| at.elapsed() >= AGREEMENT_TIMEOUT);
+        if self.agreed() || self.disputed() || waited {
+            self.complete_emitted = true;
+            return true;
+        }
+        false
+    }
+
+    pub fn status(&self, session_id: &str, match_id: Option<String>) -> SetStatus {
+        let view = self.view();
+        let complete = self.is_complete();
+        SetStatus {
+            session_id: session_id.to_string(),
+            match_id,
+            first_to: self.first_to,
+            local_wins: view.local_wins,
+            peer_wins: view.peer_wins,
+            games: self.games.clone(),
+            agreed: self.agreed(),
+            disputed: self.disputed(),
+            complete,
+            local_won_set: complete.then_some(view.local_wins >= self.first_to),
+        }
+    }
+}
+
+// Counts one game from the stats the Lua script wrote and returns the events
+// it produces, in order: set:update when the score moved, then set:complete
+// once the set is over and settled with the peer.
+pub fn record_stats(
+    tracker: &mut SetTracker,
+    stats: &Value,
+    local_player: u8,
+) -> Result<Vec<&'static str>, String> {
+    let stats = MatchStats::from_value(stats).map_err(|errors| {
+        let fields: Vec<String> = errors
+            .iter()
+            .map(|e| format!("{}: {}", e.field, e.message))
+            .collect();
+        format!("Invalid match stats: {}", fields.join("; "))
+    })?;
+    let mut events = Vec::new();
+    if !tracker.record(SetGame::from_stats(&stats, local_player)) {
+        return Ok(events);
+    }
+    events.push("set:update");
+    if tracker.take_completion() {
+        events.push("set:complete");
+    }
+    Ok(events)
+}
+
+// How a SetScore from the peer relates to our set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerSet {
+    Current,
+    // the peer started a newer set (or we had none), ours was replaced
+    Adopted,
+    // the peer is still on a set we've since replaced, ignore it until our
+    // score reaches it
+    Outdated,
+}
+
+pub fn follow_peer(set: &mut Option<SetTracker>, first_to: u32, id: u64) -> PeerSet {
+    match set {
+        Some(tracker) if tracker.id == id => PeerSet::Current,
+        Some(tracker) if tracker.id > id => PeerSet::Outdated,
+        _ => {
+            *set = Some(SetTracker::new(first_to, id));
+            PeerSet::Adopted
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn start_set(
+    state: tauri::State<'_, ProxyManager>,
+    session_id: String,
+    format: String,
+) -> Result<SetStatus, String> {
+    let first_to = parse_format(&format)?;
+    let rt = state
+        .get(&session_id)
+        .await
+        .ok_or_else(|| format!("No proxy session '{session_id}'"))?;
+    Ok(rt.start_set(first_to).await)
+}
+
+#[tauri::command]
+pub async fn record_set_game(
+    state: tauri::State<'_, ProxyManager>,
+    session_id: String,
+    stats: Value,
+) -> Result<Option<SetStatus>, String> {
+    let rt = state
+        .get(&session_id)
+        .await
+        .ok_or_else(|| format!("No proxy session '{session_id}'"))?;
+    rt.record_set_game(&stats).await
+}
+
+#[tauri::command]
+pub async fn get_set_status(
+    state: tauri::State<'_, ProxyManager>,
+    session_id: String,
+) -> Result<Option<SetStatus>, String> {
+    let rt = state
+        .get(&session_id)
+        .await
+        .ok_or_else(|| format!("No proxy session '{session_id}'"))?;
+    Ok(rt.set_status().await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::match_stats::{self, PlayerStats};
+
+    fn game(uuid: &str, local_won: bool) -> SetGame {
+        SetGame {
+            match_uuid: Some(uuid.to_string()),
+            local_player: 1,
+            winner_slot: if local_won { 1 } else { 2 },
+            local_won,
+        }
+    }
+
+    #[test]
+    fn formats_parse_within_bounds() {
+        assert_eq!(parse_format("FT3"), Ok(3));
+        assert_eq!(parse_format(" ft5 "), Ok(5));
+        assert_eq!(parse_format("2"), Ok(2));
+        assert!(parse_format("FT0").is_err());
+        assert!(parse_format("FT21").is_err());
+        assert!(parse_format("best of 3").is_err());
+    }
+
+    #[test]
+    fn set_completes_once_the_peer_agrees() {
+        let mut set = SetTracker::new(2, 1);
+        assert!(set.record(game("a", true)));
+        // the same match reported twice only counts once
+        assert!(!set.record(game("a", true)));
+        assert!(set.record(game("b", false)));
+        assert!(set.record(game("c", true)));
+        assert!(set.is_complete());
+        assert!(!set.record(game("d", true)));
+        assert_eq!(set.view(), ScoreView { games: 3, local_wins: 2, peer_wins: 1 });
+
+        // nothing goes out before the peer has confirmed
+        assert!(!set.take_completion());
+        assert!(set.set_peer_view(ScoreView { games: 3, local_wins: 1, peer_wins: 2 }));
+        assert!(!set.set_peer_view(ScoreView { games: 3, local_wins: 1, peer_wins: 2 }));
+        assert!(set.take_completion());
+        assert!(!set.take_completion());
+        let status = set.status("s", None);
+        assert!(status.agreed && !status.disputed && status.complete);
+        assert_eq!(status.local_won_set, Some(true));
+    }
+
+    #[test]
+    fn different_score_for_the_same_games_is_a_dispute() {
+        let mut set = SetTracker::new(1, 1);
+        set.record(game("a", true));
+        set.set_peer_view(ScoreView { games: 1, local_wins: 1, peer_wins: 0 });
+        let status = set.status("s", None);
+        assert!(status.disputed && !status.agreed);
+        assert!(set.take_completion());
+        // a peer that is a game behind isn't disputing anything yet
+        set.set_peer_view(ScoreView { games: 0, local_wins: 0, peer_wins: 0 });
+        assert!(!set.status("s", None).disputed);
+    }
+
+    #[test]
+    fn a_newer_set_from_the_peer_replaces_ours() {
+        let mut set = Some(SetTracker::new(3, 10));
+        set.as_mut().unwrap().record(game("a", true));
+        assert_eq!(follow_peer(&mut set, 3, 10), PeerSet::Current);
+        assert_eq!(follow_peer(&mut set, 5, 9), PeerSet::Outdated);
+        assert_eq!(set.as_ref().unwrap().view().games, 1);
+        assert_eq!(follow_peer(&mut set, 5, 11), PeerSet::Adopted);
+        let tracker = set.as_ref().unwrap();
+        assert_eq!((tracker.id, tracker.first_to, tracker.view().games), (11, 5, 0));
+
+        let mut none = None;
+        assert_eq!(follow_peer(&mut none, 2, 0), PeerSet::Adopted);
+    }
+
+    #[test]
+    fn stats_files_drive_the_set_events() {
+        const LEGACY: &str = include_str!("../tests/fixtures/match_stats/legacy_p1_win.txt");
+        let first = match_stats::parse_raw(LEGACY).unwrap();
+        let rematch = LEGACY.replace("7f3c9a2e-4b1d-4f6a-9c1e-2d8b5a0e6f13", "rematch");
+        let second = match_stats::parse_raw(&rematch).unwrap();
+
+        let mut set = SetTracker::new(2, 1);
+        assert_eq!(record_stats(&mut set, &first, 1), Ok(vec!["set:update"]));
+        // the watcher can pick up the same file twice
+        assert_eq!(record_stats(&mut set, &first, 1), Ok(vec![]));
+        // the peer's score for both games got here first
+        set.set_peer_view(ScoreView { games: 2, local_wins: 0, peer_wins: 2 });
+        assert_eq!(
+            record_stats(&mut set, &second, 1),
+            Ok(vec!["set:update", "set:complete"])
+        );
+        assert_eq!(set.status("s", None).local_won_set, Some(true));
+        assert!(record_stats(&mut set, &serde_json::json!({}), 1).is_err());
+    }
+
+    #[test]
+    fn games_come_from_validated_stats() {
+        let player = PlayerStats {
+            char: 11,
+            super_art: 2,
+            total_meter: 0.0,
+            meter_gained: Vec::new(),
+        };
+        let stats = MatchStats {
+            match_uuid: "m-1".to_string(),
+            created_at: None,
+            winner: Winner::Player2,
+            player1: player.clone(),
+            player2: player,
+        };
+        let game = SetGame::from_stats(&stats, 2);
+        assert_eq!(game.match_uuid.as_deref(), Some("m-1"));
+        assert!(game.winner_slot == 2 && game.local_won);
+        assert!(!SetGame::from_stats(&stats, 1).local_won);
+    }
+}
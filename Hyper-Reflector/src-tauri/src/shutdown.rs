@@ -0,0 +1,67 @@
+// Holds the app open on exit until everything we started is wound down:
+// proxy sessions say bye to the peer and kill to the server, emulators get the
+// graceful stop (which also drains their logs) and the upload queue gets one
+// last pass. Only then is the exit let through.
+use crate::emulator_supervisor::{EmulatorSupervisor, ShutdownOptions};
+use crate::loopback::{self, LoopbackManager};
+use crate::prewarm::PrewarmManager;
+use crate::proxy::ProxyManager;
+use crate::upload_queue::{UploadQueue, UploadStatus};
+use std::{
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tauri::{AppHandle, Emitter, EventTarget, Manager, RunEvent};
+use tauri_plugin_http::reqwest;
+use tokio::time::timeout;
+
+// A stuck peer or emulator must not keep the app from closing
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+const UPLOAD_FLUSH: Duration = Duration::from_secs(3);
+const LEAVE_REASON: &str = "Opponent closed Hyper Reflector";
+
+const RUNNING: u8 = 0;
+const CLEANING_UP: u8 = 1;
+const DONE: u8 = 2;
+static PHASE: AtomicU8 = AtomicU8::new(RUNNING);
+
+async fn clean_up(app: &AppHandle) {
+    let _ = app.emit_to(EventTarget::any(), "app:shutting-down", ());
+    let proxies = app.state::<ProxyManager>();
+    let _ = loopback::stop_loopback(&app.state::<LoopbackManager>(), &proxies).await;
+    app.state::<PrewarmManager>().cancel().await;
+    proxies.leave_all(LEAVE_REASON).await;
+    // the sessions stopped their own emulators, this is for training and mock ones
+    app.state::<EmulatorSupervisor>()
+        .stop_all(ShutdownOptions::default())
+        .await;
+    let uploads = app.state::<Arc<UploadQueue>>().inner().clone();
+    let client = reqwest::Client::new();
+    let flush = uploads.process_due(&client, &|_: UploadStatus| {});
+    let _ = timeout(UPLOAD_FLUSH, flush).await;
+}
+
+// Passed to App::run. Covers closing the last window as well as app.exit().
+pub fn on_event(app: &AppHandle, event: RunEvent) {
+    let RunEvent::ExitRequested { code, api, .. } = event else {
+        return;
+    };
+    match PHASE.compare_exchange(RUNNING, CLEANING_UP, Ordering::SeqCst, Ordering::SeqCst) {
+        Ok(_) => {
+            api.prevent_exit();
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                if timeout(SHUTDOWN_TIMEOUT, clean_up(&app)).await.is_err() {
+                    eprintln!("shutdown: cleanup timed out, exiting anyway");
+                }
+                PHASE.store(DONE, Ordering::SeqCst);
+                app.exit(code.unwrap_or(0));
+            });
+        }
+        Err(CLEANING_UP) => api.prevent_exit(),
+        Err(_) => {}
+    }
+}
@@ -0,0 +1,281 @@
+// The Lab's training session: at most one training emulator, with stop,
+// status and restart. The launch settings are kept after the emulator exits so
+// "restart" still works once the player closed FBNeo or it crashed. Every
+// change goes out as `training:started` / `training:stopped` for the Lab page.
+use crate::emulator_supervisor::{
+    EmulatorExit, EmulatorPurpose, EmulatorSupervisor, ShutdownOptions,
+};
+use crate::launch::{self, LaunchBackend, LaunchOptions};
+use crate::resolve_lua_args;
+use serde::{Deserialize, Serialize};
+use tauri::{async_runtime::JoinHandle, AppHandle, Emitter, EventTarget, Manager, State};
+use tokio::sync::Mutex;
+
+const LUA_FLAG: &str = "--lua";
+
+// What start does when a session is already running
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StartPolicy {
+    #[default]
+    Reject,
+    Replace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StopReason {
+    // stop_training_mode
+    Stopped,
+    // a new start with the replace policy
+    Replaced,
+    Restarting,
+    // the emulator went away on its own (window closed, crash)
+    Exited,
+}
+
+// Launch settings as the Lab sent them, --lua still unresolved
+#[derive(Debug, Clone)]
+struct TrainingLaunch {
+    use_sidecar: bool,
+    exe_path: Option<String>,
+    args: Vec<String>,
+    launch: LaunchOptions,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrainingInfo {
+    pub emulator_id: u64,
+    pub pid: Option<u32>,
+    pub started_at: u64,
+    pub exe_path: Option<String>,
+    pub args: Vec<String>,
+    pub lua_script: Option<String>,
+    // restarts since the session was first started
+    pub restarts: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TrainingStopped {
+    emulator_id: u64,
+    reason: StopReason,
+    exit: Option<EmulatorExit>,
+}
+
+struct Session {
+    info: TrainingInfo,
+    watcher: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct Inner {
+    running: Option<Session>,
+    // settings of the last successful start, for restarts
+    last: Option<(TrainingLaunch, u32)>,
+}
+
+pub struct TrainingManager {
+    inner: Mutex<Inner>,
+}
+
+impl TrainingManager {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+}
+
+fn lua_script(args: &[String]) -> Option<&str> {
+    let idx = args
+        .iter()
+        .position(|arg| arg.eq_ignore_ascii_case(LUA_FLAG))?;
+    args.get(idx + 1).map(String::as_str)
+}
+
+// Swaps the --lua argument, adding one if the launch had none
+fn set_lua_script(args: &mut Vec<String>, script: &str) {
+    match args
+        .iter()
+        .position(|arg| arg.eq_ignore_ascii_case(LUA_FLAG))
+    {
+        Some(idx) if idx + 1 < args.len() => args[idx + 1] = script.to_string(),
+        Some(_) => args.push(script.to_string()),
+        None => args.extend([LUA_FLAG.to_string(), script.to_string()]),
+    }
+}
+
+fn emit_stopped(app: &AppHandle, emulator_id: u64, reason: StopReason, exit: Option<EmulatorExit>) {
+    let _ = app.emit_to(
+        EventTarget::any(),
+        "training:stopped",
+        TrainingStopped {
+            emulator_id,
+            reason,
+            exit,
+        },
+    );
+}
+
+// Stops the running session, if any. The watcher goes first so it doesn't
+// report the exit a second time.
+async fn stop_session(
+    app: &AppHandle,
+    inner: &mut Inner,
+    reason: StopReason,
+    options: ShutdownOptions,
+) -> Option<EmulatorExit> {
+    let session = inner.running.take()?;
+    session.watcher.abort();
+    let id = session.info.emulator_id;
+    let exit = app.state::<EmulatorSupervisor>().stop(id, options).await;
+    emit_stopped(app, id, reason, exit.clone());
+    exit
+}
+
+fn launch_session(
+    app: &AppHandle,
+    inner: &mut Inner,
+    training: TrainingLaunch,
+    restarts: u32,
+) -> Result<TrainingInfo, String> {
+    let mut options = training.launch.clone();
+    if training.use_sidecar {
+        options.backend = Some(LaunchBackend::Sidecar { name: None });
+    } else if training.exe_path.is_none() {
+        return Err("exe_path required when use_sidecar=false".to_string());
+    }
+    let mut args = training.args.clone();
+    resolve_lua_args(app, &mut args)?;
+    let exe_path = training.exe_path.clone().unwrap_or_default();
+    let command = launch::build_command(app, &exe_path, args, &options)?;
+    let supervisor = app.state::<EmulatorSupervisor>();
+    let mut handle = supervisor.spawn(app, EmulatorPurpose::Training, None, command)?;
+    let emulator = supervisor.info(handle.id);
+    let info = TrainingInfo {
+        emulator_id: handle.id,
+        pid: emulator.as_ref().and_then(|emulator| emulator.pid),
+        started_at: emulator.map_or_else(crate::control::now_ms, |emulator| emulator.started_at),
+        exe_path: training.exe_path.clone(),
+        lua_script: lua_script(&training.args).map(str::to_string),
+        args: training.args.clone(),
+        restarts,
+    };
+
+    let watcher_app = app.clone();
+    let watcher = tauri::async_runtime::spawn(async move {
+        let exit = handle.wait().await;
+        let manager = watcher_app.state::<TrainingManager>();
+        let mut inner = manager.inner.lock().await;
+        if inner
+            .running
+            .as_ref()
+            .is_some_and(|session| session.info.emulator_id == handle.id)
+        {
+            inner.running = None;
+            emit_stopped(&watcher_app, handle.id, StopReason::Exited, exit);
+        }
+    });
+    inner.running = Some(Session {
+        info: info.clone(),
+        watcher,
+    });
+    inner.last = Some((training, restarts));
+    let _ = app.emit_to(EventTarget::any(), "training:started", &info);
+    Ok(info)
+}
+
+#[tauri::command]
+pub async fn start_training_mode(
+    app: AppHandle,
+    state: State<'_, TrainingManager>,
+    use_sidecar: bool,
+    exe_path: Option<String>,
+    args: Vec<String>,
+    launch: Option<LaunchOptions>,
+    policy: Option<StartPolicy>,
+) -> Result<TrainingInfo, String> {
+    let mut inner = state.inner.lock().await;
+    if inner.running.is_some() {
+        match policy.unwrap_or_default() {
+            StartPolicy::Reject => return Err("Training mode is already running".to_string()),
+            StartPolicy::Replace => {
+                stop_session(&app, &mut inner, StopReason::Replaced, ShutdownOptions::default())
+                    .await;
+            }
+        }
+    }
+    let training = TrainingLaunch {
+        use_sidecar,
+        exe_path,
+        args,
+        launch: launch.unwrap_or_default(),
+    };
+    launch_session(&app, &mut inner, training, 0)
+}
+
+#[tauri::command]
+pub async fn stop_training_mode(
+    app: AppHandle,
+    state: State<'_, TrainingManager>,
+    options: Option<ShutdownOptions>,
+) -> Result<Option<EmulatorExit>, String> {
+    let mut inner = state.inner.lock().await;
+    Ok(stop_session(&app, &mut inner, StopReason::Stopped, options.unwrap_or_default()).await)
+}
+
+#[tauri::command]
+pub async fn training_status(
+    state: State<'_, TrainingManager>,
+) -> Result<Option<TrainingInfo>, String> {
+    let inner = state.inner.lock().await;
+    Ok(inner.running.as_ref().map(|session| session.info.clone()))
+}
+
+// Restarts with the last settings, or with a different Lua script when
+// `lua_script` is given. Works after the emulator has already exited too.
+#[tauri::command]
+pub async fn restart_training_mode(
+    app: AppHandle,
+    state: State<'_, TrainingManager>,
+    lua_script: Option<String>,
+) -> Result<TrainingInfo, String> {
+    let mut inner = state.inner.lock().await;
+    let Some((mut training, restarts)) = inner.last.clone() else {
+        return Err("Training mode hasn't been started yet".to_string());
+    };
+    if let Some(script) = lua_script {
+        set_lua_script(&mut training.args, &script);
+    }
+    stop_session(&app, &mut inner, StopReason::Restarting, ShutdownOptions::default()).await;
+    launch_session(&app, &mut inner, training, restarts + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn lua_script_is_swapped_or_added() {
+        let mut args = strings(&["--rom", "sfiii3nr1", "--LUA", "old.lua"]);
+        assert_eq!(lua_script(&args), Some("old.lua"));
+        set_lua_script(&mut args, "new.lua");
+        assert_eq!(args, strings(&["--rom", "sfiii3nr1", "--LUA", "new.lua"]));
+
+        let mut args = strings(&["--rom", "sfiii3nr1"]);
+        assert_eq!(lua_script(&args), None);
+        set_lua_script(&mut args, "new.lua");
+        assert_eq!(args, strings(&["--rom", "sfiii3nr1", "--lua", "new.lua"]));
+
+        // a dangling flag gets its value
+        let mut args = strings(&["--lua"]);
+        set_lua_script(&mut args, "new.lua");
+        assert_eq!(args, strings(&["--lua", "new.lua"]));
+    }
+}
@@ -0,0 +1,350 @@
+// Fallback transport for networks that block outbound UDP. The proxy stream is
+// carried over a TCP or WebSocket connection to a relay which pairs us with the
+// peer and pipes frames between the two connections.
+//
+// frame: len u16 BE, sent_at u64 BE (sender's epoch ms), payload[len]
+// Over WebSocket each binary message holds exactly one frame.
+// The first frame on a connection is the JSON TunnelHello used for pairing.
+use crate::control;
+use anyhow::{anyhow, bail};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::mpsc,
+    task::JoinHandle,
+};
+use tokio_tungstenite::tungstenite::Message;
+
+const FRAME_HEADER_LEN: usize = 10;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TunnelTransport {
+    #[default]
+    Tcp,
+    Websocket,
+}
+
+impl From<TunnelTransport> for &'static str {
+    fn from(transport: TunnelTransport) -> Self {
+        match transport {
+            TunnelTransport::Tcp => "tcp",
+            TunnelTransport::Websocket => "websocket",
+        }
+    }
+}
+
+fn default_fallback_after_secs() -> u64 {
+    5
+}
+
+fn default_stale_after_ms() -> u64 {
+    250
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelConfig {
+    #[serde(default)]
+    pub transport: TunnelTransport,
+    // host:port for tcp, ws:// or wss:// url for websocket
+    pub relay: String,
+    // skip UDP entirely instead of waiting for the punch to time out
+    #[serde(default)]
+    pub force: bool,
+    #[serde(default = "default_fallback_after_secs")]
+    pub fallback_after_secs: u64,
+    // datagrams older than this on arrival are not handed to the emulator
+    #[serde(default = "default_stale_after_ms")]
+    pub stale_after_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TunnelHello {
+    pub uid: String,
+    pub peer_uid: String,
+    pub match_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TunnelDatagram {
+    pub sent_at: u64,
+    pub payload: Vec<u8>,
+}
+
+pub fn encode_frame(sent_at: u64, payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let len = u16::try_from(payload.len()).map_err(|_| anyhow!("datagram too large for tunnel"))?;
+    let mut out = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(&sent_at.to_be_bytes());
+    out.extend_from_slice(payload);
+    Ok(out)
+}
+
+pub fn decode_frame(bytes: &[u8]) -> Option<TunnelDatagram> {
+    let len = u16::from_be_bytes(bytes.get(0..2)?.try_into().ok()?) as usize;
+    let sent_at = u64::from_be_bytes(bytes.get(2..FRAME_HEADER_LEN)?.try_into().ok()?);
+    let payload = bytes.get(FRAME_HEADER_LEN..FRAME_HEADER_LEN + len)?.to_vec();
+    Some(TunnelDatagram { sent_at, payload })
+}
+
+async fn read_tcp_frame(stream: &mut tokio::net::tcp::OwnedReadHalf) -> anyhow::Result<TunnelDatagram> {
+    let mut header = [0u8; FRAME_HEADER_LEN];
+    stream.read_exact(&mut header).await?;
+    let len = u16::from_be_bytes([header[0], header[1]]) as usize;
+    let sent_at = u64::from_be_bytes(header[2..].try_into()?);
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).await?;
+    Ok(TunnelDatagram { sent_at, payload })
+}
+
+// Sending half of a tunnel. Inbound datagrams arrive on the receiver returned
+// by `connect`, which closes when the relay connection drops.
+pub struct TunnelLink {
+    outbound: mpsc::UnboundedSender<Vec<u8>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl TunnelLink {
+    pub async fn connect(
+        config: &TunnelConfig,
+        hello: &TunnelHello,
+    ) -> anyhow::Result<(Self, mpsc::UnboundedReceiver<TunnelDatagram>)> {
+        let hello = encode_frame(control::now_ms(), &serde_json::to_vec(hello)?)?;
+        let (out_tx, out_rx) = mpsc::unbounded_channel::<Vec<u8>>();
+        let (in_tx, in_rx) = mpsc::unbounded_channel::<TunnelDatagram>();
+
+        let tasks = match config.transport {
+            TunnelTransport::Tcp => Self::connect_tcp(&config.relay, hello, out_rx, in_tx).await?,
+            TunnelTransport::Websocket => Self::connect_ws(&config.relay, hello, out_rx, in_tx).await?,
+        };
+
+        Ok((
+            Self {
+                outbound: out_tx,
+                tasks,
+            },
+            in_rx,
+        ))
+    }
+
+    async fn connect_tcp(
+        relay: &str,
+        hello: Vec<u8>,
+        mut out_rx: mpsc::UnboundedReceiver<Vec<u8>>,
+        in_tx: mpsc::UnboundedSender<TunnelDatagram>,
+    ) -> anyhow::Result<Vec<JoinHandle<()>>> {
+        let stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(relay))
+            .await
+            .map_err(|_| anyhow!("relay connect timed out"))??;
+        stream.set_nodelay(true)?;
+        let (mut read, mut write) = stream.into_split();
+        write.write_all(&hello).await?;
+
+        let writer = tokio::spawn(async move {
+            while let Some(frame) = out_rx.recv().await {
+                if write.write_all(&frame).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let reader = tokio::spawn(async move {
+            while let Ok(datagram) = read_tcp_frame(&mut read).await {
+                if in_tx.send(datagram).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(vec![writer, reader])
+    }
+
+    async fn connect_ws(
+        relay: &str,
+        hello: Vec<u8>,
+        mut out_rx: mpsc::UnboundedReceiver<Vec<u8>>,
+        in_tx: mpsc::UnboundedSender<TunnelDatagram>,
+    ) -> anyhow::Result<Vec<JoinHandle<()>>> {
+        let (ws, _) = tokio::time::timeout(CONNECT_TIMEOUT, tokio_tungstenite::connect_async(relay))
+            .await
+            .map_err(|_| anyhow!("relay connect timed out"))??;
+        let (mut sink, mut stream) = ws.split();
+        sink.send(Message::Binary(hello.into())).await?;
+
+        let writer = tokio::spawn(async move {
+            while let Some(frame) = out_rx.recv().await {
+                if sink.send(Message::Binary(frame.into())).await.is_err() {
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+        });
+        let reader = tokio::spawn(async move {
+            while let Some(Ok(msg)) = stream.next().await {
+                let datagram = match msg {
+                    Message::Binary(bytes) => decode_frame(&bytes),
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
+                if let Some(datagram) = datagram {
+                    if in_tx.send(datagram).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(vec![writer, reader])
+    }
+
+    pub fn send(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let frame = encode_frame(control::now_ms(), payload)?;
+        if self.outbound.send(frame).is_err() {
+            bail!("tunnel closed");
+        }
+        Ok(())
+    }
+
+    pub fn close(&self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for TunnelLink {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    // Stand-in for the relay: pairs the first two connections and pipes bytes
+    // between them after swallowing each side's hello frame.
+    async fn spawn_tcp_relay() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (a, _) = listener.accept().await.unwrap();
+            let (b, _) = listener.accept().await.unwrap();
+            let (mut a_read, mut a_write) = a.into_split();
+            let (mut b_read, mut b_write) = b.into_split();
+            read_tcp_frame(&mut a_read).await.unwrap();
+            read_tcp_frame(&mut b_read).await.unwrap();
+            tokio::spawn(async move { tokio::io::copy(&mut a_read, &mut b_write).await });
+            tokio::io::copy(&mut b_read, &mut a_write).await.ok();
+        });
+        addr
+    }
+
+    // Same for WebSocket: each binary message after the hello is forwarded
+    async fn spawn_ws_relay() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (a, _) = listener.accept().await.unwrap();
+            let a = tokio_tungstenite::accept_async(a).await.unwrap();
+            let (b, _) = listener.accept().await.unwrap();
+            let b = tokio_tungstenite::accept_async(b).await.unwrap();
+            let (a_write, mut a_read) = a.split();
+            let (b_write, mut b_read) = b.split();
+            a_read.next().await.unwrap().unwrap();
+            b_read.next().await.unwrap().unwrap();
+            tokio::spawn(async move { a_read.forward(b_write).await });
+            b_read.forward(a_write).await.ok();
+        });
+        format!("ws://{addr}")
+    }
+
+    fn config(relay: String) -> TunnelConfig {
+        TunnelConfig {
+            transport: TunnelTransport::Tcp,
+            relay,
+            force: true,
+            fallback_after_secs: default_fallback_after_secs(),
+            stale_after_ms: default_stale_after_ms(),
+        }
+    }
+
+    fn hello(uid: &str, peer_uid: &str) -> TunnelHello {
+        TunnelHello {
+            uid: uid.to_string(),
+            peer_uid: peer_uid.to_string(),
+            match_id: Some("match-1".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn tcp_tunnel_carries_datagrams_both_ways() {
+        let relay = spawn_tcp_relay().await;
+        let (a, mut a_rx) = TunnelLink::connect(&config(relay.clone()), &hello("a", "b"))
+            .await
+            .unwrap();
+        let (b, mut b_rx) = TunnelLink::connect(&config(relay), &hello("b", "a"))
+            .await
+            .unwrap();
+
+        a.send(b"input from a").unwrap();
+        b.send(b"input from b").unwrap();
+
+        let at_b = b_rx.recv().await.unwrap();
+        let at_a = a_rx.recv().await.unwrap();
+        assert_eq!(at_b.payload, b"input from a");
+        assert_eq!(at_a.payload, b"input from b");
+        assert!(control::now_ms() - at_b.sent_at < 1000);
+    }
+
+    #[tokio::test]
+    async fn websocket_tunnel_carries_datagrams_both_ways() {
+        let relay = spawn_ws_relay().await;
+        let ws = |relay: String| TunnelConfig {
+            transport: TunnelTransport::Websocket,
+            ..config(relay)
+        };
+        let (a, mut a_rx) = TunnelLink::connect(&ws(relay.clone()), &hello("a", "b"))
+            .await
+            .unwrap();
+        let (b, mut b_rx) = TunnelLink::connect(&ws(relay), &hello("b", "a"))
+            .await
+            .unwrap();
+
+        a.send(b"input from a").unwrap();
+        b.send(b"input from b").unwrap();
+        b.send(b"second from b").unwrap();
+
+        assert_eq!(b_rx.recv().await.unwrap().payload, b"input from a");
+        assert_eq!(a_rx.recv().await.unwrap().payload, b"input from b");
+        assert_eq!(a_rx.recv().await.unwrap().payload, b"second from b");
+    }
+
+    #[tokio::test]
+    async fn wss_relays_get_a_tls_handshake() {
+        use tokio_tungstenite::tungstenite::{error::UrlError, Error};
+        // not a TLS server, so the handshake itself has to be what fails
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { drop(listener.accept().await) });
+        let err = tokio_tungstenite::connect_async(format!("wss://{addr}"))
+            .await
+            .unwrap_err();
+        assert!(
+            !matches!(err, Error::Url(UrlError::TlsFeatureNotEnabled)),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn frame_round_trip_keeps_sender_timestamp() {
+        let frame = encode_frame(42, b"payload").unwrap();
+        let datagram = decode_frame(&frame).unwrap();
+        assert_eq!(datagram.sent_at, 42);
+        assert_eq!(datagram.payload, b"payload");
+        assert!(decode_frame(&frame[..frame.len() - 1]).is_none());
+    }
+}
@@ -0,0 +1,593 @@
+// Durable queue for match result uploads. The frontend enqueues the request it
+// used to fire once with fetch; we keep it in SQLite until the backend accepts
+// it, retrying with backoff while the API host is unreachable and picking up
+// where we left off after a restart.
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tauri::{AppHandle, Emitter, EventTarget, State};
+use tauri_plugin_http::reqwest;
+use tokio::sync::Notify;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS upload_queue (
+    match_uuid TEXT PRIMARY KEY,
+    body TEXT NOT NULL,
+    state TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL,
+    last_error TEXT,
+    created_at INTEGER NOT NULL,
+    uploaded_at INTEGER
+);
+CREATE INDEX IF NOT EXISTS upload_queue_due ON upload_queue (state, next_attempt_at);
+";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+// With nothing due we still wake up now and then, cheap and covers clock jumps
+const IDLE_WAKE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UploadState {
+    Queued,
+    Uploading,
+    Uploaded,
+    Retrying,
+    // rejected by the backend (4xx), kept around for inspection
+    Failed,
+    // queued but no id token yet, or the backend refused the one we had
+    WaitingAuth,
+}
+
+impl UploadState {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Uploading => "uploading",
+            Self::Uploaded => "uploaded",
+            Self::Retrying => "retrying",
+            Self::Failed => "failed",
+            Self::WaitingAuth => "waiting-auth",
+        }
+    }
+}
+
+// The body the backend's /upload-match expects, minus the id token which is
+// added at send time since it expires long before a queued upload might.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchUpload {
+    pub match_uuid: String,
+    pub match_id: String,
+    pub player1: String,
+    pub player2: String,
+    pub match_data: Value,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadConfig {
+    // e.g. http://host:port
+    pub api_base: String,
+    pub id_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadStatus {
+    pub match_uuid: String,
+    pub state: UploadState,
+    pub attempts: u32,
+    pub next_attempt_at: Option<u64>,
+    pub error: Option<String>,
+    pub pending: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(5),
+            max: Duration::from_secs(600),
+        }
+    }
+}
+
+impl Backoff {
+    fn after(&self, attempts: u32) -> Duration {
+        let factor = 1u32 << attempts.saturating_sub(1).min(16);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+struct QueuedUpload {
+    match_uuid: String,
+    body: Value,
+    attempts: u32,
+}
+
+pub struct UploadQueue {
+    conn: Mutex<Connection>,
+    config: Mutex<UploadConfig>,
+    backoff: Backoff,
+    wake: Notify,
+    // one pass at a time, the exit flush runs next to the worker
+    pass: tokio::sync::Mutex<()>,
+}
+
+impl UploadQueue {
+    // Shares the history database file, each side keeps to its own table
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        Self::with_connection(conn, Backoff::default())
+    }
+
+    pub fn in_memory(backoff: Backoff) -> rusqlite::Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?, backoff)
+    }
+
+    fn with_connection(conn: Connection, backoff: Backoff) -> rusqlite::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        // an upload interrupted by a crash or exit is simply due again
+        conn.execute(
+            "UPDATE upload_queue SET state = 'queued' WHERE state = 'uploading'",
+            [],
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            config: Mutex::new(UploadConfig::default()),
+            backoff,
+            wake: Notify::new(),
+            pass: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub fn configure(&self, config: UploadConfig) {
+        *self.config.lock().unwrap() = config;
+        self.wake.notify_one();
+    }
+
+    // Returns false when this match-uuid was queued or uploaded before
+    pub fn enqueue(&self, upload: &MatchUpload, now_ms: u64) -> rusqlite::Result<bool> {
+        let body = json!({
+            "matchId": upload.match_id,
+            "player1": upload.player1,
+            "player2": upload.player2,
+            "matchData": upload.match_data,
+        });
+        let inserted = self.conn.lock().unwrap().execute(
+            "INSERT INTO upload_queue (match_uuid, body, state, next_attempt_at, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?4) ON CONFLICT (match_uuid) DO NOTHING",
+            params![
+                upload.match_uuid,
+                body.to_string(),
+                UploadState::Queued.as_str(),
+                now_ms
+            ],
+        )?;
+        if inserted > 0 {
+            self.wake.notify_one();
+        }
+        Ok(inserted > 0)
+    }
+
+    pub fn pending(&self) -> rusqlite::Result<u64> {
+        self.conn.lock().unwrap().query_row(
+            "SELECT COUNT(*) FROM upload_queue WHERE state NOT IN ('uploaded', 'failed')",
+            [],
+            |row| row.get(0),
+        )
+    }
+
+    fn next_due(&self, now_ms: u64) -> rusqlite::Result<Option<QueuedUpload>> {
+        let row = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT match_uuid, body, attempts FROM upload_queue \
+                 WHERE state NOT IN ('uploaded', 'failed', 'waiting-auth') \
+                 AND next_attempt_at <= ?1 \
+                 ORDER BY next_attempt_at, created_at LIMIT 1",
+                params![now_ms],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, u32>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+        Ok(row.map(|(match_uuid, body, attempts)| QueuedUpload {
+            match_uuid,
+            body: serde_json::from_str(&body).unwrap_or(Value::Null),
+            attempts,
+        }))
+    }
+
+    // Time until the earliest pending upload is due
+    fn wait_hint(&self, now_ms: u64) -> rusqlite::Result<Option<Duration>> {
+        let next: Option<u64> = self.conn.lock().unwrap().query_row(
+            "SELECT MIN(next_attempt_at) FROM upload_queue \
+             WHERE state NOT IN ('uploaded', 'failed', 'waiting-auth')",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(next.map(|at| Duration::from_millis(at.saturating_sub(now_ms))))
+    }
+
+    fn set_state(
+        &self,
+        match_uuid: &str,
+        state: UploadState,
+        attempts: u32,
+        next_attempt_at: u64,
+        error: Option<&str>,
+        now_ms: u64,
+    ) -> rusqlite::Result<()> {
+        let uploaded_at = (state == UploadState::Uploaded).then_some(now_ms);
+        self.conn.lock().unwrap().execute(
+            "UPDATE upload_queue SET state = ?2, attempts = ?3, next_attempt_at = ?4, \
+             last_error = ?5, uploaded_at = ?6 WHERE match_uuid = ?1",
+            params![
+                match_uuid,
+                state.as_str(),
+                attempts,
+                next_attempt_at,
+                error,
+                uploaded_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    // Uploads waiting on a token become due as soon as one shows up
+    fn release_waiting_auth(&self, now_ms: u64) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE upload_queue SET state = 'queued', next_attempt_at = ?1 \
+             WHERE state = 'waiting-auth'",
+            params![now_ms],
+        )?;
+        Ok(())
+    }
+
+    // Sends everything that is due once. Returns how long to sleep before the
+    // next upload is due, None when the queue is empty or waiting on auth.
+    pub async fn process_due(
+        &self,
+        client: &reqwest::Client,
+        emit: &(dyn Fn(UploadStatus) + Send + Sync),
+    ) -> rusqlite::Result<Option<Duration>> {
+        let _pass = self.pass.lock().await;
+        let config = self.config.lock().unwrap().clone();
+        if config.id_token.is_some() {
+            self.release_waiting_auth(crate::control::now_ms())?;
+        }
+        loop {
+            let now = crate::control::now_ms();
+            let Some(upload) = self.next_due(now)? else {
+                return self.wait_hint(now);
+            };
+            let attempts = upload.attempts + 1;
+            let status =
+                |state, next_attempt_at, error: Option<String>| -> rusqlite::Result<UploadStatus> {
+                    Ok(UploadStatus {
+                        match_uuid: upload.match_uuid.clone(),
+                        state,
+                        attempts,
+                        next_attempt_at,
+                        error,
+                        pending: self.pending()?,
+                    })
+                };
+
+            let Some(id_token) = config
+                .id_token
+                .clone()
+                .filter(|_| !config.api_base.is_empty())
+            else {
+                self.set_state(
+                    &upload.match_uuid,
+                    UploadState::WaitingAuth,
+                    upload.attempts,
+                    now,
+                    None,
+                    now,
+                )?;
+                emit(status(UploadState::WaitingAuth, None, None)?);
+                continue;
+            };
+
+            self.set_state(
+                &upload.match_uuid,
+                UploadState::Uploading,
+                upload.attempts,
+                now,
+                None,
+                now,
+            )?;
+            emit(status(UploadState::Uploading, None, None)?);
+
+            let mut body = upload.body.clone();
+            body["idToken"] = Value::String(id_token);
+            let url = format!("{}/upload-match", config.api_base.trim_end_matches('/'));
+            let result = client
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .body(body.to_string())
+                .timeout(REQUEST_TIMEOUT)
+                .send()
+                .await;
+
+            let now = crate::control::now_ms();
+            match result {
+                Ok(res) if res.status().is_success() => {
+                    self.set_state(
+                        &upload.match_uuid,
+                        UploadState::Uploaded,
+                        attempts,
+                        now,
+                        None,
+                        now,
+                    )?;
+                    emit(status(UploadState::Uploaded, None, None)?);
+                }
+                Ok(res) if matches!(res.status().as_u16(), 401 | 403) => {
+                    // the token expired, hold everything until the frontend sends a new one
+                    let error = format!("backend refused the id token ({})", res.status());
+                    self.config.lock().unwrap().id_token = None;
+                    self.set_state(
+                        &upload.match_uuid,
+                        UploadState::WaitingAuth,
+                        upload.attempts,
+                        now,
+                        Some(&error),
+                        now,
+                    )?;
+                    emit(status(UploadState::WaitingAuth, None, Some(error))?);
+                    return Ok(None);
+                }
+                Ok(res) if res.status().is_client_error() && res.status().as_u16() != 429 => {
+                    let error = format!("backend rejected the upload ({})", res.status());
+                    self.set_state(
+                        &upload.match_uuid,
+                        UploadState::Failed,
+                        attempts,
+                        now,
+                        Some(&error),
+                        now,
+                    )?;
+                    emit(status(UploadState::Failed, None, Some(error))?);
+                }
+                other => {
+                    let error = match other {
+                        Ok(res) => format!("backend answered {}", res.status()),
+                        Err(e) => e.to_string(),
+                    };
+                    let next = now + self.backoff.after(attempts).as_millis() as u64;
+                    self.set_state(
+                        &upload.match_uuid,
+                        UploadState::Retrying,
+                        attempts,
+                        next,
+                        Some(&error),
+                        now,
+                    )?;
+                    emit(status(UploadState::Retrying, Some(next), Some(error))?);
+                }
+            }
+        }
+    }
+}
+
+// Runs for the lifetime of the app
+pub fn spawn_worker(app: AppHandle, queue: Arc<UploadQueue>) {
+    tauri::async_runtime::spawn(async move {
+        let client = reqwest::Client::new();
+        let emit = move |status: UploadStatus| {
+            let _ = app.emit_to(EventTarget::any(), "upload:status", status);
+        };
+        loop {
+            let wait = match queue.process_due(&client, &emit).await {
+                Ok(wait) => wait.unwrap_or(IDLE_WAKE).min(IDLE_WAKE),
+                Err(e) => {
+                    eprintln!("upload queue: {e}");
+                    IDLE_WAKE
+                }
+            };
+            tokio::select! {
+                _ = queue.wake.notified() => {}
+                _ = tokio::time::sleep(wait) => {}
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn configure_uploads(queue: State<'_, Arc<UploadQueue>>, config: UploadConfig) {
+    queue.configure(config);
+}
+
+#[tauri::command]
+pub fn enqueue_match_upload(
+    queue: State<'_, Arc<UploadQueue>>,
+    upload: MatchUpload,
+) -> Result<bool, String> {
+    queue
+        .enqueue(&upload, crate::control::now_ms())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn pending_uploads(queue: State<'_, Arc<UploadQueue>>) -> Result<u64, String> {
+    queue.pending().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    // Minimal HTTP/1.1 server answering each request with the next status
+    // from `statuses`, and keeping the request bodies it saw.
+    async fn spawn_mock_backend(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&bodies);
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut sock, _) = listener.accept().await.unwrap();
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 4096];
+                let body = loop {
+                    let n = sock.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                    let text = String::from_utf8_lossy(&buf).to_string();
+                    let Some(split) = text.find("\r\n\r\n") else {
+                        continue;
+                    };
+                    let length = text[..split]
+                        .lines()
+                        .find_map(|l| {
+                            let (name, value) = l.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or(0);
+                    if buf.len() >= split + 4 + length {
+                        break buf[split + 4..split + 4 + length].to_vec();
+                    }
+                };
+                seen.lock()
+                    .unwrap()
+                    .push(serde_json::from_slice(&body).unwrap());
+                let response = format!(
+                    "HTTP/1.1 {status} Mock\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                );
+                sock.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (base, bodies)
+    }
+
+    fn state_of(queue: &UploadQueue, match_uuid: &str) -> rusqlite::Result<Option<String>> {
+        queue
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT state FROM upload_queue WHERE match_uuid = ?1",
+                params![match_uuid],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    fn upload(uuid: &str) -> MatchUpload {
+        MatchUpload {
+            match_uuid: uuid.to_string(),
+            match_id: "match-1".to_string(),
+            player1: "me".to_string(),
+            player2: "rival".to_string(),
+            match_data: json!({ "raw": "{}" }),
+        }
+    }
+
+    fn fast_queue() -> UploadQueue {
+        UploadQueue::in_memory(Backoff {
+            initial: Duration::from_millis(20),
+            max: Duration::from_millis(20),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn enqueue_dedupes_by_match_uuid() {
+        let queue = fast_queue();
+        assert!(queue.enqueue(&upload("a"), 0).unwrap());
+        assert!(!queue.enqueue(&upload("a"), 1).unwrap());
+        assert_eq!(queue.pending().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_until_the_backend_accepts() {
+        let (base, bodies) = spawn_mock_backend(vec![503, 200]).await;
+        let queue = fast_queue();
+        let events = AtomicUsize::new(0);
+        let emit = |_: UploadStatus| {
+            events.fetch_add(1, Ordering::Relaxed);
+        };
+        let client = reqwest::Client::new();
+        queue.enqueue(&upload("a"), 0).unwrap();
+
+        // no token yet: parked until configured
+        assert_eq!(queue.process_due(&client, &emit).await.unwrap(), None);
+        assert_eq!(
+            state_of(&queue, "a").unwrap().as_deref(),
+            Some("waiting-auth")
+        );
+
+        queue.configure(UploadConfig {
+            api_base: base,
+            id_token: Some("token".to_string()),
+        });
+        let wait = queue.process_due(&client, &emit).await.unwrap();
+        assert_eq!(state_of(&queue, "a").unwrap().as_deref(), Some("retrying"));
+        tokio::time::sleep(wait.unwrap()).await;
+        assert_eq!(queue.process_due(&client, &emit).await.unwrap(), None);
+        assert_eq!(state_of(&queue, "a").unwrap().as_deref(), Some("uploaded"));
+        assert_eq!(queue.pending().unwrap(), 0);
+
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[1]["idToken"], "token");
+        assert_eq!(bodies[1]["player2"], "rival");
+        assert!(events.load(Ordering::Relaxed) >= 4);
+    }
+
+    #[tokio::test]
+    async fn unreachable_backend_backs_off() {
+        // bind and drop to get a port nothing listens on
+        let port = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let queue = UploadQueue::in_memory(Backoff {
+            initial: Duration::from_secs(5),
+            max: Duration::from_secs(600),
+        })
+        .unwrap();
+        queue.configure(UploadConfig {
+            api_base: format!("http://127.0.0.1:{port}"),
+            id_token: Some("token".to_string()),
+        });
+        queue.enqueue(&upload("a"), 0).unwrap();
+        let wait = queue
+            .process_due(&reqwest::Client::new(), &|_: UploadStatus| {})
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(wait > Duration::from_secs(4));
+        assert_eq!(state_of(&queue, "a").unwrap().as_deref(), Some("retrying"));
+    }
+}
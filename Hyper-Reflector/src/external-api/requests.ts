@@ -4,6 +4,7 @@ import { firebaseConfig } from '../private/firebase'
 const SERVER = keys.COTURN_IP
 
 import { fetch } from '@tauri-apps/plugin-http';
+import { invoke } from '@tauri-apps/api/core'
 
 // await fetch(`http://${SERVER}:${keys.API_PORT}/logged-in`, {
 //     method: "POST",
@@ -249,29 +250,37 @@ async function getCustomToken(idToken: string) {
 }
 
 // match related
+// Uploads go through the persistent queue in src-tauri/src/upload_queue.rs, which
+// retries while the API is unreachable. It needs a fresh id token to send them.
+async function configureMatchUploads(auth) {
+    const idToken = auth.currentUser ? await auth.currentUser.getIdToken() : null
+    await invoke('configure_uploads', {
+        config: { apiBase: `http://${SERVER}:${keys.API_PORT}`, idToken },
+    })
+}
+
+// Queued first and regardless of sign-in, so a match played offline or signed
+// out is kept until the queue can send it.
 async function uploadMatchData(auth, matchData) {
-    if (checkCurrentAuthState(auth)) {
-        const idToken = await auth.currentUser.getIdToken().then((res) => res)
-        const requestBody = JSON.stringify({
-            idToken,
-            matchId: matchData.matchId, // generated by the hole punching server
-            player1: matchData.player1,
-            player2: matchData.player2,
-            matchData: matchData.matchData, // this is the entirety of the stat-tracking-file
+    try {
+        await invoke('enqueue_match_upload', {
+            upload: {
+                matchUuid: matchData.matchUuid,
+                matchId: matchData.matchId, // generated by the hole punching server
+                player1: matchData.player1,
+                player2: matchData.player2,
+                matchData: matchData.matchData, // condensed stat-tracking-file
+            },
         })
-        console.log(requestBody)
-        try {
-            fetch(`http://${SERVER}:${keys.API_PORT}/upload-match`, {
-                method: 'POST',
-                headers: {
-                    'Content-Type': 'application/json',
-                },
-                body: requestBody,
-            })
-        } catch (error) {
-            console.log(error)
-            console.error(error.message)
-        }
+    } catch (error) {
+        console.error('Failed to queue match upload', error)
+        return
+    }
+    // refreshing the token fails offline, the queue keeps the last one it had
+    try {
+        await configureMatchUploads(auth)
+    } catch (error) {
+        console.warn('Failed to refresh upload credentials', error)
     }
 }
 
@@ -503,6 +512,7 @@ export default {
     searchUsers,
     getLeaderboard,
     //matches
+    configureMatchUploads,
     uploadMatchData,
     getUserMatches,
     getGlobalSet,
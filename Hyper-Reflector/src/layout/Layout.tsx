@@ -73,18 +73,36 @@ import {
     declineCall as webrtcDeclineCall,
     closeConnectionWithUser,
 } from '../webRTC/WebPeer'
-import { isMockUserId, startMockMatch, startProxyMatch } from '../match'
+import {
+    cancelProxyPrewarm,
+    isMockUserId,
+    prewarmProxy,
+    startMockMatch,
+    startProxyMatch,
+} from '../match'
 import { SOCKET_STATE_EVENT, type SocketStateUpdateDetail } from './helpers/socketBridge'
 import { auth } from '../utils/firebase'
+import { onIdTokenChanged } from 'firebase/auth'
 import api from '../external-api/requests'
-import {
-    clearMatchCommandFile,
-    clearMatchStatsFile,
-    readMatchCommandFile,
-    readMatchStatsFile,
-} from '../utils/matchFiles'
-import { parseMatchData } from '../utils/matchParser'
 import { isTauriEnv, resolveFilesPath } from '../utils/pathSettings'
+import { listen } from '@tauri-apps/api/event'
+
+// condensed upload payload built by src-tauri/src/match_stats.rs
+type CondensedMatchPayload = {
+    matchUuid: string
+    createdAt: number
+    winner: 'player1' | 'player2'
+    'p1-win': boolean
+    'p2-win': boolean
+    [key: string]: unknown
+}
+
+type MatchStatsEvent = {
+    raw: string
+    stats: Record<string, unknown> | null
+    condensed: CondensedMatchPayload | null
+    errors: Array<{ field: string; message: string }>
+}
 
 const DEV_MATCH_ID = 'dev-matches-and-bugs'
 
@@ -100,112 +118,8 @@ function resolveMockDisplayName(uid?: string | null) {
     return 'Mock Opponent'
 }
 
-const MAX_METER_EVENTS = 200
 const MAX_RAW_PAYLOAD_LENGTH = 450_000
 
-function coerceBooleanFlag(value: unknown): boolean | undefined {
-    if (typeof value === 'boolean') {
-        return value
-    }
-    if (typeof value === 'number') {
-        if (!Number.isFinite(value)) return undefined
-        return value !== 0
-    }
-    if (typeof value === 'string') {
-        const normalized = value.trim().toLowerCase()
-        if (!normalized.length) return undefined
-        return normalized === 'true' || normalized === '1'
-    }
-    return undefined
-}
-
-const coerceNumber = (value: unknown): number | undefined => {
-    if (typeof value === 'number' && Number.isFinite(value)) {
-        return value
-    }
-    if (typeof value === 'string') {
-        const num = Number(value)
-        return Number.isFinite(num) ? num : undefined
-    }
-    return undefined
-}
-
-const coerceString = (value: unknown): string | undefined => {
-    if (typeof value === 'string' && value.trim().length) {
-        return value.trim()
-    }
-    if (typeof value === 'number') {
-        return String(value)
-    }
-    return undefined
-}
-
-const limitArray = (value: unknown, limit = MAX_METER_EVENTS) => {
-    if (!Array.isArray(value)) return []
-    const mapped = value
-        .map((entry) => coerceNumber(entry))
-        .filter((entry): entry is number => typeof entry === 'number')
-    if (mapped.length <= limit) return mapped
-    return mapped.slice(mapped.length - limit)
-}
-
-const buildCondensedMatchPayload = (source: Record<string, unknown>) => {
-    const matchUuid = coerceString(source['match-uuid'])
-    const createdAt = coerceNumber(source['created-at']) ?? Date.now()
-    const explicitP1Win = coerceBooleanFlag(source['p1-win'])
-    const explicitP2Win = coerceBooleanFlag(source['p2-win'])
-
-    let resolvedWinner: 'player1' | 'player2'
-    if (explicitP1Win === true) {
-        resolvedWinner = 'player1'
-    } else if (explicitP2Win === true) {
-        resolvedWinner = 'player2'
-    } else {
-        const fallbackWinner =
-            coerceString(source['winner']) ||
-            (coerceBooleanFlag(source['p1-win']) ? 'player1' : 'player2')
-        resolvedWinner = fallbackWinner === 'player2' ? 'player2' : 'player1'
-    }
-
-    const p1WinFinal = resolvedWinner === 'player1'
-    const p2WinFinal = !p1WinFinal
-
-    const safeNumber = (value: unknown) => coerceNumber(value) ?? 0
-
-    return {
-        matchUuid,
-        createdAt,
-        winner: resolvedWinner,
-        'p1-win': p1WinFinal,
-        'p2-win': p2WinFinal,
-        // legacy keys to keep backend parser happy
-        'player1-char': safeNumber(source['player1-char']),
-        'player2-char': safeNumber(source['player2-char']),
-        'player1-super': safeNumber(source['player1-super']),
-        'player2-super': safeNumber(source['player2-super']),
-        'p1-total-meter-gained': safeNumber(source['p1-total-meter-gained']),
-        'p2-total-meter-gained': safeNumber(source['p2-total-meter-gained']),
-        'p1-meter-gained': limitArray(source['p1-meter-gained']),
-        'p2-meter-gained': limitArray(source['p2-meter-gained']),
-        participants: {
-            player1: {
-                char: safeNumber(source['player1-char']),
-                super: safeNumber(source['player1-super']),
-                totalMeter: safeNumber(source['p1-total-meter-gained']),
-            },
-            player2: {
-                char: safeNumber(source['player2-char']),
-                super: safeNumber(source['player2-super']),
-                totalMeter: safeNumber(source['p2-total-meter-gained']),
-            },
-        },
-        meterSamples: {
-            player1: limitArray(source['p1-meter-gained']),
-            player2: limitArray(source['p2-meter-gained']),
-        },
-    }
-}
-
 type SendMessageEventDetail = {
     text: string
     onSuccess?: () => void
@@ -274,7 +188,6 @@ export default function Layout({ children }: { children: ReactElement[] }) {
     >(new Map())
     const pendingChallengeByUserRef = useRef<Map<string, string>>(new Map())
     const pendingIceCandidatesRef = useRef<Map<string, RTCIceCandidateInit[]>>(new Map())
-    const matchUploadPendingRef = useRef(false)
     const activeMatchIdRef = useRef<string | null>(null)
     const localPlayerSlotRef = useRef<0 | 1>(0)
     const lastMatchUuidRef = useRef<string | null>(null)
@@ -542,6 +455,7 @@ export default function Layout({ children }: { children: ReactElement[] }) {
                         })
                     }
                 } else {
+                    void cancelProxyPrewarm()
                     toaster.info({
                         title: 'Challenge declined',
                         description: `${responder} declined the challenge.`,
@@ -704,21 +618,9 @@ export default function Layout({ children }: { children: ReactElement[] }) {
     }, [openLobbyManager])
 
     const handleMatchStats = useCallback(
-        async (rawData: string) => {
-            if (!rawData?.trim()) {
-                return
-            }
-
-            matchUploadPendingRef.current = true
+        async (condensed: CondensedMatchPayload) => {
             try {
-                const parsed = parseMatchData(rawData)
-                if (!parsed) return
-
-                const pickValue = (entry: string | number | Array<string | number>) =>
-                    Array.isArray(entry) ? entry[entry.length - 1] : entry
-
-                const matchUuidEntry = parsed['match-uuid']
-                const matchUuid = matchUuidEntry ? String(pickValue(matchUuidEntry)) : undefined
+                const matchUuid = condensed.matchUuid
                 if (matchUuid && lastMatchUuidRef.current === matchUuid) {
                     return
                 }
@@ -731,7 +633,7 @@ export default function Layout({ children }: { children: ReactElement[] }) {
 
                 const isPlayerOne = localPlayerSlotRef.current === 0
                 const resultKey = isPlayerOne ? 'p1-win' : 'p2-win'
-                const didWin = coerceBooleanFlag(parsed[resultKey])
+                const didWin = condensed[resultKey]
                 let nextStreakValue: number | null = null
 
                 if (typeof didWin === 'boolean') {
@@ -753,9 +655,9 @@ export default function Layout({ children }: { children: ReactElement[] }) {
                     matchUuid ||
                     `local-${viewer.uid}-${Date.now()}`
 
-                const condensed = buildCondensedMatchPayload(parsed)
                 const limitedRaw = JSON.stringify(condensed)
                 await api.uploadMatchData(auth, {
+                    matchUuid,
                     matchId,
                     player1: isPlayerOne ? viewer.uid : opponentUid,
                     player2: isPlayerOne ? opponentUid : viewer.uid,
@@ -770,8 +672,6 @@ export default function Layout({ children }: { children: ReactElement[] }) {
                 }
             } catch (error) {
                 console.error('Failed to upload match data', error)
-            } finally {
-                matchUploadPendingRef.current = false
             }
         },
         [sendSocketStateUpdate, syncViewerWinStreak]
@@ -781,59 +681,50 @@ export default function Layout({ children }: { children: ReactElement[] }) {
         if (!isTauriEnv()) {
             return
         }
+        // uploads queued in an earlier session go out once they have a token
+        return onIdTokenChanged(auth, () => {
+            void api.configureMatchUploads(auth)
+        })
+    }, [])
 
-        let cancelled = false
-        let busy = false
-
-        if (import.meta.env.DEV) {
-            console.info('[match-tracker] poller armed')
+    useEffect(() => {
+        if (!isTauriEnv()) {
+            return
         }
 
-        const poll = async () => {
-            if (cancelled || busy) return
-            if (matchUploadPendingRef.current) {
+        // the Rust match watcher picks up the Lua command/stats files and hands
+        // us the payload, see src-tauri/src/match_watcher.rs
+        let cancelled = false
+        const unlisten = listen<MatchStatsEvent>('match:stats', (event) => {
+            if (cancelled) return
+            const payload = event.payload
+            if (!payload?.raw?.trim()) {
                 return
             }
-            busy = true
-            try {
-                const command = await readMatchCommandFile()
-                console.log('commands', JSON.stringify(command))
-                if (!command || !command.trim().length) {
-                    return
-                }
-                await clearMatchCommandFile()
-
-                console.debug('[match-tracker] command contents:', command)
 
-                if (!command.toLowerCase().includes('read-tracking-file')) {
-                    return
-                }
-
-                const resolvedWinSound = winSoundPathRef.current
-                if (resolvedWinSound) {
-                    void playSoundFile(resolvedWinSound)
-                }
+            const resolvedWinSound = winSoundPathRef.current
+            if (resolvedWinSound) {
+                void playSoundFile(resolvedWinSound)
+            }
 
-                const rawStats = await readMatchStatsFile()
-                await clearMatchStatsFile()
-                if (rawStats && rawStats.trim()) {
-                    console.info('[match-tracker] received stats payload; uploading…')
-                    await handleMatchStats(rawStats)
-                }
-            } catch (error) {
-                console.error('Failed to process match tracking data', error)
-            } finally {
-                busy = false
+            if (!payload.condensed) {
+                console.warn('[match-tracker] invalid stats payload', payload.errors)
+                toaster.error({
+                    title: 'Match stats not uploaded',
+                    description: payload.errors
+                        .map((error) => `${error.field || 'stats'}: ${error.message}`)
+                        .join(', '),
+                })
+                return
             }
-        }
 
-        const intervalId = window.setInterval(() => {
-            void poll()
-        }, 1000)
+            console.info('[match-tracker] received stats payload; uploading…')
+            void handleMatchStats(payload.condensed)
+        })
 
         return () => {
             cancelled = true
-            window.clearInterval(intervalId)
+            void unlisten.then((off) => off())
         }
     }, [globalLoggedIn, globalUser?.uid, handleMatchStats, playSoundFile])
 
@@ -960,6 +851,7 @@ export default function Layout({ children }: { children: ReactElement[] }) {
                 opponentUidRef.current = targetUid
                 sentMatchRequestRef.current.delete(targetUid)
                 await startCall(peer, socket, targetUid, globalUser.uid, true)
+                void prewarmProxy(targetUid)
                 toaster.success({
                     title: 'Challenge sent',
                     description: 'Waiting for opponent to respond.',
@@ -1645,6 +1537,7 @@ export default function Layout({ children }: { children: ReactElement[] }) {
                         })
                         pendingChallengeByUserRef.current.set(payload.from, messageId)
                         pendingIceCandidatesRef.current.set(payload.from, [])
+                        void prewarmProxy(payload.from)
 
                         const { addChatMessage, updateMessage } = useMessageStore.getState()
                         if (existingMessageId) {
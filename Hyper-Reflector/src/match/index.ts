@@ -35,6 +35,34 @@ export function isMockUserId(uid?: string | null): boolean {
     return uid.startsWith('mock-') || MOCK_USER_MAP.has(uid)
 }
 
+// While a challenge is pending the match socket is bound and punched ahead of
+// time, start_proxy adopts it (src-tauri/src/prewarm.rs). Best effort: without
+// it the proxy binds its own socket when the match starts.
+export async function prewarmProxy(opponentUid: string): Promise<void> {
+    const { globalUser } = useUserStore.getState()
+    if (!globalUser?.uid || isMockUserId(opponentUid)) return
+    try {
+        await invoke('prewarm_proxy', {
+            args: {
+                my_uid: globalUser.uid,
+                peer_uid: opponentUid,
+                server_host: keys.COTURN_IP,
+                server_port: Number(keys.PUNCH_PORT ?? 33334),
+            },
+        })
+    } catch (error) {
+        console.warn('Failed to prewarm match socket:', error)
+    }
+}
+
+export async function cancelProxyPrewarm(): Promise<void> {
+    try {
+        await invoke('cancel_prewarm')
+    } catch (error) {
+        console.warn('Failed to cancel match socket prewarm:', error)
+    }
+}
+
 export async function startProxyMatch({
     matchId,
     playerSlot,
@@ -138,59 +166,56 @@ export async function startMockMatch({
         typeof gameName === 'string' && gameName.trim().length ? gameName.trim() : 'sfiii3nr1'
     const delay = Number.parseInt(ggpoDelay || '0', 10) || 0
 
-    const primaryPorts =
-        playerSlot === 0
-            ? { local: 7000, remote: 7001 }
-            : { local: 7001, remote: 7000 }
-    const opponentPorts =
-        playerSlot === 0
-            ? { local: 7001, remote: 7000 }
-            : { local: 7000, remote: 7001 }
-
     const matchLuaPath = (await resolveMatchLuaPath(emulatorPath)) || trainingPath
 
-    const playerArgs = buildEmulatorArgs({
-        emulatorPath,
-        playerIndex: (playerSlot === 0 ? 1 : 2) as 1 | 2,
-        localPort: primaryPorts.local,
-        remotePort: primaryPorts.remote,
-        playerName,
-        delay,
-        luaPath: matchLuaPath,
-        rom: romName,
-    })
-
-    const opponentArgs = buildEmulatorArgs({
-        emulatorPath,
-        playerIndex: (playerSlot === 0 ? 2 : 1) as 1 | 2,
-        localPort: opponentPorts.local,
-        remotePort: opponentPorts.remote,
-        playerName: opponentDisplayName,
-        delay,
-        luaPath: matchLuaPath,
-        rom: romName,
-    })
+    // Both emulators go through their own proxy session (src-tauri/src/loopback.rs),
+    // each plays on gamePort and sends to its proxy on listenPort.
+    const side = (playerIndex: 1 | 2, name: string) => {
+        const gamePort = 7000 + (playerIndex - 1) * 2
+        const listenPort = gamePort + 1
+        return {
+            user_name: name,
+            game_port: gamePort,
+            listen_port: listenPort,
+            emulator_args: buildEmulatorArgs({
+                emulatorPath,
+                playerIndex,
+                localPort: gamePort,
+                remotePort: listenPort,
+                playerName: name,
+                delay,
+                luaPath: matchLuaPath,
+                rom: romName,
+            }),
+        }
+    }
+    const [p1Name, p2Name] =
+        playerSlot === 0 ? [playerName, opponentDisplayName] : [opponentDisplayName, playerName]
 
     try {
-        await Promise.all([
-            invoke('launch_emulator', {
-                exePath: emulatorPath,
-                args: playerArgs,
-            }),
-            invoke('launch_emulator', {
-                exePath: emulatorPath,
-                args: opponentArgs,
-            }),
-        ])
+        await invoke('start_loopback_match', {
+            args: {
+                emulator_path: emulatorPath,
+                delay,
+                game_name: romName,
+                p1: side(1, p1Name),
+                p2: side(2, p2Name),
+            },
+        })
         toaster.success({
             title: 'Mock match started',
-            description: 'Launched two local emulator instances.',
+            description: 'Two local proxy sessions are connecting their emulators.',
         })
     } catch (error) {
         console.error('Failed to start mock match:', error)
         toaster.error({
             title: 'Failed to start mock match',
-            description: error instanceof Error ? error.message : 'Unknown error launching emulator',
+            description:
+                typeof error === 'string'
+                    ? error
+                    : error instanceof Error
+                      ? error.message
+                      : 'Unknown error starting the loopback match',
         })
     }
 }
@@ -1,4 +1,6 @@
+import { useEffect, useState } from 'react'
 import { invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
 import { Box, Button, Card, IconButton, Text } from '@chakra-ui/react'
 import { open } from '@tauri-apps/plugin-dialog'
 import { toaster } from '../components/chakra/ui/toaster'
@@ -8,13 +10,54 @@ import { useTranslation } from 'react-i18next'
 import {
     ensureDefaultEmulatorPath,
     ensureDefaultTrainingPath,
+    isTauriEnv,
 } from '../utils/pathSettings'
 
+// src-tauri/src/training.rs
+type TrainingInfo = {
+    emulatorId: number
+    pid: number | null
+    startedAt: number
+    luaScript: string | null
+    restarts: number
+}
+
+type TrainingStopped = {
+    emulatorId: number
+    reason: 'stopped' | 'replaced' | 'restarting' | 'exited'
+}
+
 export default function LabPage() {
     const { t } = useTranslation()
     const theme = useSettingsStore((s) => s.theme)
     const setTrainingPath = useSettingsStore((s) => s.setTrainingPath)
     const trainingPath = useSettingsStore((s) => s.trainingPath)
+    const [training, setTraining] = useState<TrainingInfo | null>(null)
+
+    useEffect(() => {
+        if (!isTauriEnv()) {
+            return
+        }
+
+        let cancelled = false
+        void invoke<TrainingInfo | null>('training_status').then((info) => {
+            if (!cancelled) setTraining(info)
+        })
+        const offStarted = listen<TrainingInfo>('training:started', (event) => {
+            if (!cancelled) setTraining(event.payload)
+        })
+        const offStopped = listen<TrainingStopped>('training:stopped', (event) => {
+            if (cancelled) return
+            setTraining((current) =>
+                current?.emulatorId === event.payload.emulatorId ? null : current
+            )
+        })
+        return () => {
+            cancelled = true
+            void offStarted.then((off) => off())
+            void offStopped.then((off) => off())
+        }
+    }, [])
 
     async function handleStartTraining() {
         try {
@@ -41,6 +84,7 @@ export default function LabPage() {
                 useSidecar: false,
                 exePath: ensuredEmulatorPath,
                 args,
+                policy: 'replace',
             })
         } catch (error) {
             console.error('Failed to start training mode:', error)
@@ -52,6 +96,26 @@ export default function LabPage() {
         }
     }
 
+    async function handleStopTraining() {
+        try {
+            await invoke('stop_training_mode')
+        } catch (error) {
+            console.error('Failed to stop training mode:', error)
+        }
+    }
+
+    async function handleRestartTraining() {
+        try {
+            // picks up a Lua script changed since the last start
+            await invoke('restart_training_mode', { luaScript: trainingPath || null })
+        } catch (error) {
+            toaster.error({
+                title: 'Failed to restart training',
+                description: String(error),
+            })
+        }
+    }
+
     const pickLua = async () => {
         try {
             const res = await open({
@@ -82,6 +146,24 @@ export default function LabPage() {
                     >
                         {t('Lab.start')}
                     </Button>
+                    {training && (
+                        <Box display="flex" gap="2">
+                            <Button
+                                colorPalette={theme.colorPalette}
+                                variant="outline"
+                                onClick={handleRestartTraining}
+                            >
+                                {t('Lab.restart')}
+                            </Button>
+                            <Button
+                                colorPalette={'red'}
+                                variant="outline"
+                                onClick={handleStopTraining}
+                            >
+                                {t('Lab.stop')}
+                            </Button>
+                        </Box>
+                    )}
                     <Text textStyle="xs">
                         {t('Lab.currentPath')} {trainingPath || 'Default'}
                     </Text>